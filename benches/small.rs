@@ -0,0 +1,26 @@
+//! Allocation of small buffers, where the alignment of the element type is
+//! enough and the constructors allocate like `Vec`, compared with `Vec` itself
+//! and with a cache line alignment.
+use aligned_vec::aligned_vec;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const LENS: [usize; 3] = [4, 64, 1024];
+
+fn small(c: &mut Criterion) {
+    let mut g = c.benchmark_group("small");
+    for len in LENS {
+        g.bench_with_input(BenchmarkId::new("vec", len), &len, |b, &len| {
+            b.iter(|| Vec::<u64>::with_capacity(len))
+        });
+        g.bench_with_input(BenchmarkId::new("natural", len), &len, |b, &len| {
+            b.iter(|| aligned_vec::<u64>(0, len, 8, None))
+        });
+        g.bench_with_input(BenchmarkId::new("align_64", len), &len, |b, &len| {
+            b.iter(|| aligned_vec::<u64>(0, len, 64, None))
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(benches, small);
+criterion_main!(benches);
@@ -0,0 +1,115 @@
+//! Allocation and first full write latency of each touch strategy.
+//!
+//! The `alloc` group times the constructor alone, the `first_access` group the
+//! first write of every byte of the buffer it returned, where untouched pages
+//! are faulted in. The thread is pinned to one core so that page faults and
+//! writes are not spread across CPUs.
+use aligned_vec::{aligned_vec, zeroed_aligned_vec, AlignedVec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::time::{Duration, Instant};
+
+const SIZES: [(usize, &str); 3] = [(1 << 20, "1MiB"), (64 << 20, "64MiB"), (1 << 30, "1GiB")];
+
+// Buffer returned by a strategy.
+enum Buffer {
+    Vec(AlignedVec<u8>),
+    #[cfg(target_os = "linux")]
+    Mmap(aligned_vec::MmapVec<u8>),
+}
+
+impl Buffer {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self {
+            Buffer::Vec(v) => v.as_mut_slice(),
+            #[cfg(target_os = "linux")]
+            Buffer::Mmap(v) => v.as_mut_slice(),
+        }
+    }
+}
+
+// Ways of obtaining a page aligned buffer of `size` bytes.
+#[derive(Clone, Copy)]
+enum Strategy {
+    // pages are faulted in by the first write
+    NoTouch,
+    // one byte per page written by the constructor
+    PerPage,
+    // zeroed memory from the allocator
+    Zeroed,
+    // pages prefaulted by the kernel
+    #[cfg(target_os = "linux")]
+    MapPopulate,
+}
+
+impl Strategy {
+    const ALL: &'static [Strategy] = &[
+        Strategy::NoTouch,
+        Strategy::PerPage,
+        Strategy::Zeroed,
+        #[cfg(target_os = "linux")]
+        Strategy::MapPopulate,
+    ];
+    fn name(self) -> &'static str {
+        match self {
+            Strategy::NoTouch => "no_touch",
+            Strategy::PerPage => "per_page",
+            Strategy::Zeroed => "zeroed",
+            #[cfg(target_os = "linux")]
+            Strategy::MapPopulate => "map_populate",
+        }
+    }
+    fn alloc(self, size: usize) -> Buffer {
+        let ps = page_size::get();
+        match self {
+            Strategy::NoTouch => Buffer::Vec(aligned_vec::<u8>(size, size, ps, None)),
+            Strategy::PerPage => Buffer::Vec(aligned_vec::<u8>(size, size, ps, Some(1))),
+            Strategy::Zeroed => Buffer::Vec(zeroed_aligned_vec::<u8>(size, size, ps)),
+            #[cfg(target_os = "linux")]
+            Strategy::MapPopulate => {
+                Buffer::Mmap(aligned_vec::mmap_aligned_vec::<u8>(size, size, Some(1)))
+            }
+        }
+    }
+}
+
+// Run `iters` allocations followed by a full write, return the time spent
+// allocating and the time spent writing. Buffers are dropped outside the
+// timed sections.
+fn run(strategy: Strategy, size: usize, iters: u64) -> (Duration, Duration) {
+    let mut alloc = Duration::ZERO;
+    let mut access = Duration::ZERO;
+    for _ in 0..iters {
+        let start = Instant::now();
+        let mut b = strategy.alloc(size);
+        let allocated = Instant::now();
+        b.as_mut_slice().fill(2);
+        std::hint::black_box(b.as_mut_slice());
+        access += allocated.elapsed();
+        alloc += allocated - start;
+    }
+    (alloc, access)
+}
+
+fn strategies(c: &mut Criterion) {
+    if let Some(core) = core_affinity::get_core_ids().and_then(|ids| ids.into_iter().next()) {
+        core_affinity::set_for_current(core);
+    }
+    for (group, pick) in [("alloc", 0), ("first_access", 1)] {
+        let mut g = c.benchmark_group(group);
+        g.sample_size(10);
+        for &(size, label) in &SIZES {
+            for &s in Strategy::ALL {
+                g.bench_with_input(BenchmarkId::new(s.name(), label), &size, |b, &size| {
+                    b.iter_custom(|iters| {
+                        let (alloc, access) = run(s, size, iters);
+                        [alloc, access][pick]
+                    })
+                });
+            }
+        }
+        g.finish();
+    }
+}
+
+criterion_group!(benches, strategies);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+use aligned_vec::{aligned_vec, init_aligned_vec, init_aligned_vec_parallel, par_aligned_vec};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const SIZE: usize = 256 << 20;
+
+fn touch(c: &mut Criterion) {
+    let ps = page_size::get();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut g = c.benchmark_group("touch");
+    g.sample_size(10);
+    g.bench_function("serial", |b| {
+        b.iter(|| aligned_vec::<u8>(SIZE, SIZE, ps, Some(1)))
+    });
+    g.bench_function("parallel", |b| {
+        b.iter(|| par_aligned_vec::<u8>(SIZE, SIZE, ps, Some(1), threads))
+    });
+    #[cfg(target_os = "linux")]
+    g.bench_function("map_populate", |b| {
+        b.iter(|| aligned_vec::mmap_aligned_vec::<u8>(SIZE, SIZE, Some(1)))
+    });
+    g.finish();
+}
+
+fn init(c: &mut Criterion) {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut g = c.benchmark_group("init");
+    g.sample_size(10);
+    g.bench_function("serial", |b| {
+        b.iter(|| init_aligned_vec::<u64>(SIZE / 8, 0, 64, 1))
+    });
+    g.bench_function("parallel", |b| {
+        b.iter(|| init_aligned_vec_parallel::<u64>(SIZE / 8, 0, 64, 1, threads))
+    });
+    g.finish();
+}
+
+criterion_group!(benches, touch, init);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+//! Buffers wrapping memory obtained outside of the crate.
+//!
+//! The memory is released by the closure passed to [`adopt_aligned`], never by
+//! a [`crate::Backend`]: a region handed out by a foreign allocator or mapped
+//! from a file is given back to whoever provided it.
+use crate::AlignedVec;
+use alloc::boxed::Box;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, Index, IndexMut};
+use core::slice::SliceIndex;
+
+// Called with the pointer and the capacity of the region.
+type Dealloc<T> = Box<dyn FnOnce(*mut T, usize) + Send>;
+
+//-----------------------------------------------------------------------------
+/// Region adopted with [`adopt_aligned`], its elements are dropped and the
+/// deallocation closure is called when the value is dropped.
+pub struct AdoptedVec<T> {
+    // never dropped, the memory does not belong to its backend
+    vec: ManuallyDrop<AlignedVec<T>>,
+    // taken on drop
+    dealloc: Option<Dealloc<T>>,
+}
+
+//-----------------------------------------------------------------------------
+/// Wrap `capacity` elements at `ptr`, the first `len` initialised, without
+/// copying them, e.g. a buffer returned by a foreign allocator, so that the
+/// aligned buffer helpers can be used on it. When the returned value is
+/// dropped the `len` elements are dropped and `dealloc` is called with `ptr`
+/// and `capacity`, the crate never releases the memory itself.
+///
+/// # Safety
+///
+/// - `ptr` must be valid for reads and writes of `capacity` elements of `T`
+///   until `dealloc` is called, and must not be accessed by other means in the
+///   meantime.
+/// - The first `len` elements must be initialised.
+/// - `dealloc` must be the correct way to release the region, it is called
+///   exactly once, unless the buffer is leaked.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if `ptr` is null or not
+/// aligned to `align` and `align_of::<T>()`, if `len` exceeds `capacity` or if
+/// the size in bytes of `capacity` elements overflows.
+pub unsafe fn adopt_aligned<T>(
+    ptr: *mut T,
+    len: usize,
+    capacity: usize,
+    align: usize,
+    dealloc: impl FnOnce(*mut T, usize) + Send + 'static,
+) -> AdoptedVec<T> {
+    crate::assert_valid_alignment(align);
+    let (layout, _) = crate::aligned_layout::<T>(0, capacity, align)
+        .unwrap_or_else(|e| panic!("adopt_aligned: {e}"));
+    let ptr = core::ptr::NonNull::new(ptr).expect("adopt_aligned: null pointer");
+    assert!(
+        (ptr.as_ptr() as usize).is_multiple_of(layout.align()),
+        "adopt_aligned: pointer {ptr:?} not aligned to {}",
+        layout.align()
+    );
+    assert!(
+        len <= capacity,
+        "adopt_aligned: length {len} exceeds capacity {capacity}"
+    );
+    // the backend is never used, the buffer is not dropped nor grown
+    let vec = AlignedVec::from_raw_parts_in(ptr, len, capacity, layout, crate::Backend::Rust);
+    AdoptedVec {
+        vec: ManuallyDrop::new(vec),
+        dealloc: Some(Box::new(dealloc)),
+    }
+}
+
+impl<T> Drop for AdoptedVec<T> {
+    fn drop(&mut self) {
+        let (ptr, capacity) = (self.vec.as_mut_ptr(), self.vec.capacity());
+        self.vec.truncate(0);
+        if let Some(dealloc) = self.dealloc.take() {
+            dealloc(ptr, capacity);
+        }
+    }
+}
+
+// The closure is only reached through `&mut self` on drop, sharing the buffer
+// shares the elements alone.
+unsafe impl<T: Sync> Sync for AdoptedVec<T> {}
+
+// Only shared access to the inner buffer: growing it would release the region
+// through the wrong allocator.
+impl<T> Deref for AdoptedVec<T> {
+    type Target = AlignedVec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for AdoptedVec<T> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.vec[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for AdoptedVec<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.vec[index]
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use core::alloc::Layout;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    #[test]
+    fn adopt_aligned_test() {
+        // region from a foreign allocator, released by the closure only
+        let layout = Layout::from_size_align(16 * 8, 128).unwrap();
+        let freed = Arc::new(AtomicUsize::new(0));
+        let mut v = unsafe {
+            let p = std::alloc::alloc(layout) as *mut u64;
+            for i in 0..10 {
+                p.add(i).write(i as u64);
+            }
+            let freed = Arc::clone(&freed);
+            adopt_aligned(p, 10, 16, 128, move |p, capacity| {
+                assert_eq!(capacity, 16);
+                std::alloc::dealloc(p as *mut u8, layout);
+                freed.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        assert_eq!(v.len(), 10);
+        assert_eq!(v.capacity(), 16);
+        assert_eq!(v.alignment(), 128);
+        assert!(v.is_aligned_to(128));
+        v[3] = 30;
+        crate::force_commit(&mut v[..]);
+        assert_eq!(v.as_slice(), [0, 1, 2, 30, 4, 5, 6, 7, 8, 9]);
+        drop(v);
+        assert_eq!(freed.load(Ordering::Relaxed), 1);
+        // elements are dropped before the region is released
+        let layout = Layout::array::<String>(2).unwrap();
+        let v = unsafe {
+            let p = std::alloc::alloc(layout) as *mut String;
+            p.write("a".to_string());
+            adopt_aligned(p, 1, 2, 1, move |p, _| {
+                std::alloc::dealloc(p as *mut u8, layout)
+            })
+        };
+        assert_eq!(v[0], "a");
+        assert_eq!(v.alignment(), std::mem::align_of::<String>());
+    }
+    #[test]
+    #[should_panic(expected = "not aligned to 64")]
+    fn adopt_misaligned_test() {
+        let mut region = [0u64; 16];
+        let p = region.as_mut_ptr();
+        let p = if (p as usize).is_multiple_of(64) {
+            p.wrapping_add(1)
+        } else {
+            p
+        };
+        let _ = unsafe { adopt_aligned(p, 0, 8, 64, |_, _| ()) };
+    }
+}
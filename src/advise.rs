@@ -0,0 +1,136 @@
+//! Access pattern hints with `madvise`.
+use crate::{AlignedVec, AllocError, Zeroable};
+use nix::sys::mman::MmapAdvise;
+use std::ffi::c_void;
+
+//-----------------------------------------------------------------------------
+/// Expected access pattern passed to [`advise`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// `MADV_SEQUENTIAL`: pages are accessed in order, read ahead aggressively.
+    Sequential,
+    /// `MADV_RANDOM`: pages are accessed in random order, do not read ahead.
+    Random,
+    /// `MADV_WILLNEED`: pages will be accessed soon.
+    WillNeed,
+    /// `MADV_DONTNEED`: pages will not be accessed soon, the kernel may free
+    /// them and on Linux they read back as zero on the next access.
+    DontNeed,
+}
+
+impl From<Advice> for MmapAdvise {
+    fn from(a: Advice) -> Self {
+        match a {
+            Advice::Sequential => MmapAdvise::MADV_SEQUENTIAL,
+            Advice::Random => MmapAdvise::MADV_RANDOM,
+            Advice::WillNeed => MmapAdvise::MADV_WILLNEED,
+            Advice::DontNeed => MmapAdvise::MADV_DONTNEED,
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Tell the kernel how the buffer is going to be accessed.
+///
+/// The advice applies to the whole pages of the allocation, a trailing partial
+/// page shared with other allocations is left untouched. `T` must be
+/// `Zeroable` because [`Advice::DontNeed`] can reset the contents to zero.
+///
+/// Returns [`AllocError::NotPageAligned`] if the buffer does not start on a page
+/// boundary; empty buffers are ignored.
+pub fn advise<T: Zeroable>(v: &mut AlignedVec<T>, advice: Advice) -> Result<(), AllocError> {
+    let ps = crate::cached_page_size();
+    if v.capacity() == 0 {
+        return Ok(());
+    }
+    if !(v.as_ptr() as usize).is_multiple_of(ps) {
+        return Err(AllocError::NotPageAligned);
+    }
+    let len = v.capacity() * std::mem::size_of::<T>() / ps * ps;
+    if len == 0 {
+        return Ok(());
+    }
+    unsafe { nix::sys::mman::madvise(v.as_mut_ptr() as *mut c_void, len, advice.into()) }.map_err(
+        |e| AllocError::Syscall {
+            op: "madvise",
+            source: e.into(),
+        },
+    )
+}
+
+impl<T: Zeroable> AlignedVec<T> {
+    /// Let the kernel reclaim the physical pages of the buffer while keeping
+    /// the mapping, e.g. before an idle buffer goes back to a pool, using
+    /// `MADV_FREE` and `MADV_DONTNEED` on kernels without it.
+    ///
+    /// The pages are faulted in again on the next access. Until the kernel
+    /// reclaims a page it keeps its contents, after that it reads back as zero;
+    /// elements must therefore be written before being read again. Like
+    /// [`advise`] only whole pages are released.
+    ///
+    /// Returns [`AllocError::NotPageAligned`] if the buffer does not start on a
+    /// page boundary; empty buffers are ignored.
+    pub fn release_pages(&mut self) -> Result<(), AllocError> {
+        let ps = crate::cached_page_size();
+        if self.capacity() == 0 {
+            return Ok(());
+        }
+        if !(self.as_ptr() as usize).is_multiple_of(ps) {
+            return Err(AllocError::NotPageAligned);
+        }
+        let len = self.capacity() * std::mem::size_of::<T>() / ps * ps;
+        if len == 0 {
+            return Ok(());
+        }
+        let addr = self.as_mut_ptr() as *mut c_void;
+        match unsafe { nix::sys::mman::madvise(addr, len, MmapAdvise::MADV_FREE) } {
+            // MADV_FREE is only available since Linux 4.5
+            Err(nix::errno::Errno::EINVAL) => unsafe {
+                nix::sys::mman::madvise(addr, len, MmapAdvise::MADV_DONTNEED)
+            },
+            r => r,
+        }
+        .map_err(|e| AllocError::Syscall {
+            op: "madvise",
+            source: e.into(),
+        })
+    }
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn advise_test() {
+        let ps = page_size::get();
+        let mut v = crate::page_aligned_vec::<u8>(8 * ps, 0, Some(1), false);
+        advise(&mut v, Advice::WillNeed).unwrap();
+        advise(&mut v, Advice::Sequential).unwrap();
+        assert_eq!(v[ps], 1);
+        let mut v = crate::aligned_vec::<u8>(8 * ps, 0, 64, None);
+        if !(v.as_ptr() as usize).is_multiple_of(ps) {
+            let r = advise(&mut v, Advice::WillNeed);
+            assert!(matches!(r, Err(AllocError::NotPageAligned)));
+        }
+    }
+    #[test]
+    fn release_pages_test() {
+        let ps = page_size::get();
+        let mut v = crate::init_aligned_vec::<u32>(4 * ps, 0, ps, 7);
+        v.release_pages().unwrap();
+        // zero once the kernel has reclaimed a page, unchanged until then
+        assert!(v.iter().all(|&x| x == 0 || x == 7));
+        v.fill(3);
+        assert!(v.iter().all(|&x| x == 3));
+        v.release_pages().unwrap();
+        assert_eq!(v.len(), 4 * ps);
+        crate::page_aligned_vec::<u8>(0, 0, None, false)
+            .release_pages()
+            .unwrap();
+        let mut v = crate::aligned_vec::<u8>(8 * ps, 0, 64, Some(1));
+        if !(v.as_ptr() as usize).is_multiple_of(ps) {
+            assert!(matches!(v.release_pages(), Err(AllocError::NotPageAligned)));
+        }
+    }
+}
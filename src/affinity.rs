@@ -0,0 +1,172 @@
+//! Touching pages from chosen CPUs.
+//!
+//! Page faults are served on the CPU that takes them. Real-time applications
+//! keeping a core isolated for their latency-critical thread can have the
+//! touch loop run on other cores: the calling thread is pinned to the given
+//! CPUs with `sched_setaffinity` while touching and its previous affinity is
+//! restored afterwards.
+use crate::{AlignedVec, AllocError};
+
+//-----------------------------------------------------------------------------
+/// Same as [`crate::aligned_vec`] with one element per page initialised with
+/// `touch` while the calling thread is pinned to `cpus`, e.g. all the cores
+/// but an isolated real-time one. The affinity of the thread is restored when
+/// the function returns.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if `cpus` is empty, if
+/// the thread cannot be pinned to `cpus` or if allocation fails.
+pub fn pinned_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+    cpus: &[usize],
+) -> AlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_pinned_aligned_vec(size, capacity, align, touch, cpus)
+        .unwrap_or_else(|e| panic!("pinned_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`pinned_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two,
+/// [`AllocError::EmptyCpuSet`] if `cpus` is empty and
+/// [`AllocError::Syscall`] if the thread cannot be pinned to `cpus`,
+/// the buffer is released in that case.
+pub fn try_pinned_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+    cpus: &[usize],
+) -> Result<AlignedVec<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    if cpus.is_empty() {
+        return Err(AllocError::EmptyCpuSet);
+    }
+    let set = cpu_set(cpus)?;
+    let span = crate::AllocSpan::enter(size, align, true);
+    let mut v = crate::alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    if v.is_empty() {
+        return Ok(v);
+    }
+    let _pinned = Pinned::new(&set)?;
+    span.touch(|| crate::touch_pages(v.as_mut_slice(), touch));
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return the set of `cpus`, [`AllocError::Syscall`] with `EINVAL` if one does
+/// not fit in a `cpu_set_t`, as `sched_setaffinity` would return.
+pub(crate) fn cpu_set(cpus: &[usize]) -> Result<libc::cpu_set_t, AllocError> {
+    let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &c in cpus {
+        if c >= max {
+            return Err(AllocError::Syscall {
+                op: "sched_setaffinity",
+                source: std::io::Error::from_raw_os_error(libc::EINVAL),
+            });
+        }
+        unsafe { libc::CPU_SET(c, &mut set) };
+    }
+    Ok(set)
+}
+//-----------------------------------------------------------------------------
+/// Set the affinity of the calling thread to `set`.
+pub(crate) fn set_affinity(set: &libc::cpu_set_t) -> Result<(), AllocError> {
+    let r = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) };
+    if r != 0 {
+        return Err(AllocError::Syscall {
+            op: "sched_setaffinity",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+//-----------------------------------------------------------------------------
+/// Return the affinity of the calling thread.
+fn affinity() -> Result<libc::cpu_set_t, AllocError> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let r = unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if r != 0 {
+        return Err(AllocError::Syscall {
+            op: "sched_getaffinity",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(set)
+}
+
+// Calling thread pinned to a CPU set, its previous affinity is restored on
+// drop, also when unwinding.
+struct Pinned {
+    previous: libc::cpu_set_t,
+}
+
+impl Pinned {
+    fn new(set: &libc::cpu_set_t) -> Result<Self, AllocError> {
+        let previous = affinity()?;
+        set_affinity(set)?;
+        Ok(Self { previous })
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        // only fails if all the previous CPUs went offline meanwhile, the
+        // kernel then already moved the thread
+        let _ = set_affinity(&self.previous);
+    }
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn cpus(set: &libc::cpu_set_t) -> Vec<usize> {
+        let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+        (0..max)
+            .filter(|&c| unsafe { libc::CPU_ISSET(c, set) })
+            .collect()
+    }
+    #[test]
+    fn pinned_aligned_vec_test() {
+        let ps = page_size::get();
+        let original = cpus(&affinity().unwrap());
+        // the last allowed CPU only, as when keeping the others free
+        let last = *original.last().unwrap();
+        let v = pinned_aligned_vec::<u64>(3 * ps / 8 + 1, 0, ps, 7, &[last]);
+        assert!(v.is_aligned_to(ps));
+        assert_eq!(crate::resident_pages(&v), 4);
+        for i in (0..v.len()).step_by(ps / 8) {
+            assert_eq!(v[i], 7);
+        }
+        assert_eq!(cpus(&affinity().unwrap()), original);
+        let r = try_pinned_aligned_vec::<u8>(10, 0, 64, 1, &[]);
+        assert!(matches!(r, Err(AllocError::EmptyCpuSet)));
+        let r = try_pinned_aligned_vec::<u8>(10, 0, 64, 1, &[1 << 20]);
+        assert!(
+            matches!(r, Err(AllocError::Syscall { op: "sched_setaffinity", source })
+            if source.raw_os_error() == Some(libc::EINVAL))
+        );
+        // a CPU that does not exist, still within a `cpu_set_t`
+        let missing = 8 * std::mem::size_of::<libc::cpu_set_t>() - 1;
+        if !original.contains(&missing) {
+            let r = try_pinned_aligned_vec::<u8>(10, 0, 64, 1, &[missing]);
+            assert!(matches!(
+                r,
+                Err(AllocError::Syscall {
+                    op: "sched_setaffinity",
+                    ..
+                })
+            ));
+        }
+        assert_eq!(cpus(&affinity().unwrap()), original);
+        let r = try_pinned_aligned_vec::<u8>(10, 0, 3, 1, &[last]);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+        assert!(pinned_aligned_vec::<u8>(0, 0, 64, 1, &[last]).is_empty());
+    }
+}
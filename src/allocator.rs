@@ -0,0 +1,149 @@
+//! Aligned allocator for the standard containers, requires a nightly compiler
+//! and the `allocator_api` feature.
+//!
+//! ```ignore,rust
+//! let mut v = Vec::with_capacity_in(100, AlignedAllocator { align: 64 });
+//! v.push(1u8);
+//! assert_eq!(v.as_ptr() as usize % 64, 0);
+//! ```
+use core::alloc::{Allocator, Layout};
+use core::ptr::NonNull;
+
+//-----------------------------------------------------------------------------
+/// Allocator forcing every allocation to be aligned to at least `align`
+/// bytes, memory is obtained from the global allocator. Allocation fails if
+/// `align` is not a non-zero power of two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AlignedAllocator {
+    pub align: usize,
+}
+
+impl AlignedAllocator {
+    /// Layout with the alignment raised to `align`.
+    fn layout(&self, layout: Layout) -> Result<Layout, core::alloc::AllocError> {
+        layout
+            .align_to(self.align)
+            .map_err(|_| core::alloc::AllocError)
+    }
+}
+
+unsafe impl Allocator for AlignedAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+        let layout = self.layout(layout)?;
+        if layout.size() == 0 {
+            let ptr = NonNull::new(core::ptr::without_provenance_mut(layout.align())).unwrap();
+            return Ok(NonNull::slice_from_raw_parts(ptr, 0));
+        }
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        NonNull::new(ptr)
+            .map(|p| NonNull::slice_from_raw_parts(p, layout.size()))
+            .ok_or(core::alloc::AllocError)
+    }
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // cannot fail: the same layout was accepted by `allocate`
+        let layout = self.layout(layout).unwrap();
+        if layout.size() != 0 {
+            alloc::alloc::dealloc(ptr.as_ptr(), layout);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    // alignment set by the innermost `with_alignment` on this thread
+    static SCOPED_ALIGN: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+}
+
+//-----------------------------------------------------------------------------
+/// Run `f` with `align` as the alignment of the vectors created by
+/// [`scoped_vec`] and [`scoped_vec_with_capacity`] on this thread, instead of
+/// threading an allocator through every call. Scopes nest, the previous
+/// alignment is restored when `f` returns or panics. Vectors keep the
+/// alignment they were created with once the scope ends.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two.
+#[cfg(feature = "std")]
+pub fn with_alignment<R>(align: usize, f: impl FnOnce() -> R) -> R {
+    crate::assert_valid_alignment(align);
+    // restores the outer alignment, also while unwinding
+    struct Restore(Option<usize>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_ALIGN.with(|a| a.set(self.0));
+        }
+    }
+    let _restore = Restore(SCOPED_ALIGN.with(|a| a.replace(Some(align))));
+    f()
+}
+//-----------------------------------------------------------------------------
+/// Allocator aligned as set by the innermost [`with_alignment`] on this
+/// thread, not raising the alignment of the elements outside of any scope.
+#[cfg(feature = "std")]
+fn scoped_allocator() -> AlignedAllocator {
+    AlignedAllocator {
+        align: SCOPED_ALIGN.with(|a| a.get()).unwrap_or(1),
+    }
+}
+//-----------------------------------------------------------------------------
+/// Empty `Vec` whose allocations are aligned as set by the innermost
+/// [`with_alignment`] on this thread when it is created, to the alignment of
+/// `T` outside of any scope.
+#[cfg(feature = "std")]
+pub fn scoped_vec<T>() -> Vec<T, AlignedAllocator> {
+    Vec::new_in(scoped_allocator())
+}
+//-----------------------------------------------------------------------------
+/// Same as [`scoped_vec`] with room for `capacity` elements.
+#[cfg(feature = "std")]
+pub fn scoped_vec_with_capacity<T>(capacity: usize) -> Vec<T, AlignedAllocator> {
+    Vec::with_capacity_in(capacity, scoped_allocator())
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_allocator_test() {
+        let a = AlignedAllocator { align: 64 };
+        let mut v: Vec<u8, _> = Vec::new_in(a);
+        v.push(1);
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        // grow
+        for i in 0..10000 {
+            v.push(i as u8);
+            assert_eq!(v.as_ptr() as usize % 64, 0);
+        }
+        // shrink
+        v.truncate(10);
+        v.shrink_to_fit();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v[0], 1);
+        let r = AlignedAllocator { align: 3 }.allocate(Layout::new::<u8>());
+        assert!(r.is_err());
+    }
+    #[cfg(feature = "std")]
+    #[test]
+    fn with_alignment_test() {
+        let (v, w) = with_alignment(4096, || {
+            let mut v = scoped_vec::<u8>();
+            v.push(1);
+            // nested scope, the outer alignment is restored after it
+            let w = with_alignment(128, || scoped_vec_with_capacity::<u64>(10));
+            v.extend_from_slice(&[2; 5000]);
+            (v, w)
+        });
+        assert_eq!(v.allocator().align, 4096);
+        assert_eq!(v.as_ptr() as usize % 4096, 0);
+        assert_eq!(w.allocator().align, 128);
+        assert_eq!(w.as_ptr() as usize % 128, 0);
+        // outside of any scope only the alignment of the type is requested
+        let u = scoped_vec_with_capacity::<u8>(1);
+        assert_eq!(u.allocator().align, 1);
+        let r = std::panic::catch_unwind(|| with_alignment(64, || panic!("inside")));
+        assert!(r.is_err());
+        assert_eq!(scoped_vec::<u8>().allocator().align, 1);
+    }
+}
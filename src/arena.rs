@@ -0,0 +1,106 @@
+//! Bump allocation of aligned sub-slices out of one aligned buffer.
+use crate::{AlignedVec, AllocError, Zeroable};
+use core::cell::Cell;
+
+//-----------------------------------------------------------------------------
+/// Arena handing out aligned, non-overlapping slices of a single zeroed
+/// allocation. Slices are carved in order with a cursor and stay valid until
+/// the arena is dropped, memory is never reused.
+pub struct AlignedArena {
+    buf: AlignedVec<u8>,
+    // offset in bytes of the first free byte
+    cursor: Cell<usize>,
+}
+
+impl AlignedArena {
+    /// Arena of `capacity` bytes aligned to `align`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a non-zero power of two or if allocation fails.
+    pub fn new(capacity: usize, align: usize) -> Self {
+        crate::assert_valid_alignment(align);
+        Self::try_new(capacity, align).unwrap_or_else(|e| panic!("AlignedArena::new: {e}"))
+    }
+    /// Fallible version of [`AlignedArena::new`], returns
+    /// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of
+    /// two.
+    pub fn try_new(capacity: usize, align: usize) -> Result<Self, AllocError> {
+        Ok(Self {
+            buf: crate::try_zeroed_aligned_vec(capacity, 0, align)?,
+            cursor: Cell::new(0),
+        })
+    }
+    /// Size of the arena in bytes.
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+    /// Number of bytes handed out, alignment padding included.
+    pub fn used(&self) -> usize {
+        self.cursor.get()
+    }
+    /// Return a zeroed slice of `count` elements aligned to
+    /// `align.max(align_of::<T>())` bytes, `None` if the arena has not enough
+    /// room left. Alignments larger than the alignment of the arena are
+    /// honoured by skipping bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a non-zero power of two.
+    // each range is handed out once, the returned slices never alias
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_aligned<T: Zeroable>(&self, count: usize, align: usize) -> Option<&mut [T]> {
+        crate::assert_valid_alignment(align);
+        let align = align.max(core::mem::align_of::<T>());
+        let cursor = self.cursor.get();
+        // the pointer keeps the provenance of the whole allocation
+        let base = self.buf.as_ptr() as *mut u8;
+        let start = cursor + unsafe { base.add(cursor) }.align_offset(align);
+        let end = start.checked_add(count.checked_mul(core::mem::size_of::<T>())?)?;
+        if end > self.capacity() {
+            return None;
+        }
+        self.cursor.set(end);
+        // the range is zeroed, handed out once and lies within the buffer
+        Some(unsafe { core::slice::from_raw_parts_mut(base.add(start) as *mut T, count) })
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_arena_test() {
+        let arena = AlignedArena::new(16 << 10, 64);
+        let a = arena.alloc_aligned::<u8>(3, 1).unwrap();
+        let b = arena.alloc_aligned::<u64>(5, 64).unwrap();
+        let c = arena.alloc_aligned::<u32>(7, 4096).unwrap();
+        let d = arena.alloc_aligned::<u16>(0, 8).unwrap();
+        let e = arena.alloc_aligned::<u16>(9, 1).unwrap();
+        assert_eq!(b.as_ptr() as usize % 64, 0);
+        assert_eq!(c.as_ptr() as usize % 4096, 0);
+        assert!(d.is_empty());
+        assert_eq!(e.as_ptr() as usize % 2, 0);
+        assert!(c.iter().all(|&x| x == 0));
+        a.fill(1);
+        b.fill(2);
+        c.fill(3);
+        e.fill(4);
+        assert!(a.iter().all(|&x| x == 1));
+        assert!(b.iter().all(|&x| x == 2));
+        assert!(c.iter().all(|&x| x == 3));
+        let ranges = [
+            (a.as_ptr() as usize, std::mem::size_of_val(a)),
+            (b.as_ptr() as usize, std::mem::size_of_val(b)),
+            (c.as_ptr() as usize, std::mem::size_of_val(c)),
+            (e.as_ptr() as usize, std::mem::size_of_val(e)),
+        ];
+        for w in ranges.windows(2) {
+            assert!(w[0].0 + w[0].1 <= w[1].0);
+        }
+        assert!(arena.used() <= arena.capacity());
+        assert!(arena.alloc_aligned::<u8>(16 << 10, 1).is_none());
+        assert!(arena.alloc_aligned::<u8>(usize::MAX, 1).is_none());
+    }
+}
@@ -0,0 +1,536 @@
+//! Allocation backends.
+//!
+//! Memory must be released by the allocator that provided it, buffers record
+//! the backend they were allocated with and free their memory through it.
+use core::alloc::Layout;
+
+// Largest alignment requested from the global allocator, allocators commonly
+// serve alignments up to this size from their regular pools but may waste
+// memory or fail for larger ones.
+pub(crate) const MAX_NATIVE_ALIGN: usize = 64 << 10;
+
+// Alignment of the blocks returned by C `malloc` and `realloc`, two words on
+// the supported platforms.
+#[cfg(all(unix, feature = "std"))]
+const MALLOC_ALIGN: usize = 2 * core::mem::size_of::<usize>();
+
+//-----------------------------------------------------------------------------
+/// Allocator used to obtain the memory of a buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Rust global allocator, `alloc::alloc::alloc`.
+    #[default]
+    Rust,
+    /// POSIX `posix_memalign`, released with `free`.
+    #[cfg(all(unix, feature = "std"))]
+    PosixMemalign,
+    /// C11 `aligned_alloc`, released with `free`. The allocation size is
+    /// rounded up to a multiple of the alignment as required by the standard.
+    #[cfg(all(unix, feature = "std"))]
+    AlignedAlloc,
+    /// Rust global allocator with the alignment obtained by over-allocating
+    /// `align` bytes and rounding the pointer up, the address returned by the
+    /// allocator is stored just before the aligned pointer. Used for
+    /// alignments larger than 64 KiB requested from [`Backend::Rust`].
+    OverAligned,
+    /// mimalloc `mi_malloc_aligned`, released with `mi_free`. Requires the
+    /// `mimalloc` feature.
+    #[cfg(feature = "mimalloc")]
+    Mimalloc,
+    /// jemalloc `aligned_alloc`, released with jemalloc's `free`. Requires the
+    /// `jemalloc` feature.
+    #[cfg(feature = "jemalloc")]
+    Jemalloc,
+}
+
+impl Backend {
+    /// Backend actually used to allocate memory aligned to `align`.
+    pub(crate) fn for_align(self, align: usize) -> Backend {
+        match self {
+            Backend::Rust if align > MAX_NATIVE_ALIGN => Backend::OverAligned,
+            b => b,
+        }
+    }
+    /// Return `true` if zero-initialised memory is written while zeroing, so
+    /// that all its pages are already faulted in.
+    pub(crate) fn zeroed_pages_faulted(self) -> bool {
+        match self {
+            // `alloc_zeroed` may return fresh zero pages from the OS
+            Backend::Rust | Backend::OverAligned => false,
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc => false,
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc => false,
+            #[cfg(all(unix, feature = "std"))]
+            Backend::PosixMemalign | Backend::AlignedAlloc => true,
+        }
+    }
+    /// Allocate memory for `layout`, return null on failure.
+    ///
+    /// # Safety
+    ///
+    /// `layout` must have a non-zero size.
+    pub(crate) unsafe fn alloc(self, layout: Layout, zeroed: bool) -> *mut u8 {
+        let p = self.alloc_unregistered(layout, zeroed);
+        self.record_alloc(p, layout);
+        p
+    }
+    /// Register the block of `layout` at `p` as if returned by
+    /// [`Backend::alloc`], for memory obtained by other means from the same
+    /// allocator, e.g. by a `Vec`. Nothing is recorded if `p` is null.
+    pub(crate) fn record_alloc(self, p: *mut u8, layout: Layout) {
+        #[cfg(all(feature = "alloc_registry", debug_assertions))]
+        crate::registry::register(p, layout);
+        // over-aligned blocks are counted by the inner allocation
+        #[cfg(feature = "stats")]
+        if !p.is_null() && self != Backend::OverAligned {
+            crate::stats::record_alloc(layout.size());
+        }
+        let _ = (p, layout);
+    }
+    /// Release memory obtained with [`Backend::alloc`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `self.alloc(layout, _)`.
+    pub(crate) unsafe fn dealloc(self, ptr: *mut u8, layout: Layout) {
+        self.record_free(ptr, layout);
+        self.dealloc_unregistered(ptr, layout);
+    }
+    /// Unregister the block of `layout` at `ptr` as if released by
+    /// [`Backend::dealloc`], for memory handed over to another owner, e.g. a
+    /// `Vec`, which releases it with the same allocator.
+    pub(crate) fn record_free(self, ptr: *mut u8, layout: Layout) {
+        #[cfg(all(feature = "alloc_registry", debug_assertions))]
+        crate::registry::unregister(ptr, layout);
+        #[cfg(feature = "stats")]
+        if self != Backend::OverAligned {
+            crate::stats::record_free(layout.size());
+        }
+        let _ = (ptr, layout);
+    }
+    /// Resize the block at `ptr` allocated with `layout` to `new_size` bytes
+    /// keeping its contents up to the smaller size and its alignment, the
+    /// block is extended in place when the allocator can. Return null on
+    /// failure, the original block is left untouched in that case.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `self.alloc(layout, _)` and `new_size`
+    /// must be non-zero and not overflow `isize` when rounded up to
+    /// `layout.align()`.
+    pub(crate) unsafe fn realloc(self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let p = match self {
+            // `realloc` honours the alignment of the layout, the result is
+            // checked anyway and `free` only ever sees a block of `new_layout`
+            Backend::Rust => realloc_aligned_with(
+                ptr,
+                layout,
+                new_size,
+                usize::MAX,
+                |p, n| alloc::alloc::realloc(p, layout, n),
+                |l| alloc::alloc::alloc(l),
+                |p| alloc::alloc::dealloc(p, new_layout),
+            ),
+            // the stored base address rules out resizing in place
+            Backend::OverAligned => {
+                let p = self.alloc_unregistered(new_layout, false);
+                if !p.is_null() {
+                    core::ptr::copy_nonoverlapping(ptr, p, layout.size().min(new_size));
+                    self.dealloc_unregistered(ptr, layout);
+                }
+                p
+            }
+            // C `realloc` only guarantees the alignment of `malloc`
+            #[cfg(all(unix, feature = "std"))]
+            Backend::PosixMemalign | Backend::AlignedAlloc => realloc_aligned_with(
+                ptr,
+                layout,
+                new_size,
+                MALLOC_ALIGN,
+                |p, n| libc::realloc(p as *mut libc::c_void, n) as *mut u8,
+                |l| self.alloc_unregistered(l, false),
+                |p| libc::free(p as *mut libc::c_void),
+            ),
+            // both honour the alignment, the result is checked anyway
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc => realloc_aligned_with(
+                ptr,
+                layout,
+                new_size,
+                usize::MAX,
+                |p, n| libmimalloc_sys::mi_realloc_aligned(p as _, n, layout.align()) as *mut u8,
+                |l| self.alloc_unregistered(l, false),
+                |p| libmimalloc_sys::mi_free(p as _),
+            ),
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc => realloc_aligned_with(
+                ptr,
+                layout,
+                new_size,
+                usize::MAX,
+                |p, n| {
+                    let flags = tikv_jemalloc_sys::MALLOCX_ALIGN(layout.align());
+                    tikv_jemalloc_sys::rallocx(p as _, n, flags) as *mut u8
+                },
+                |l| self.alloc_unregistered(l, false),
+                |p| tikv_jemalloc_sys::free(p as _),
+            ),
+        };
+        #[cfg(all(feature = "alloc_registry", debug_assertions))]
+        if !p.is_null() {
+            crate::registry::unregister(ptr, layout);
+            crate::registry::register(p, new_layout);
+        }
+        #[cfg(feature = "stats")]
+        if !p.is_null() && self != Backend::OverAligned {
+            crate::stats::record_free(layout.size());
+            crate::stats::record_alloc(new_size);
+        }
+        p
+    }
+    /// [`Backend::alloc`] without registration.
+    unsafe fn alloc_unregistered(self, layout: Layout, zeroed: bool) -> *mut u8 {
+        match self {
+            Backend::Rust if zeroed => alloc::alloc::alloc_zeroed(layout),
+            Backend::Rust => alloc::alloc::alloc(layout),
+            Backend::OverAligned => {
+                let Some(base_layout) = over_aligned_base(layout) else {
+                    return core::ptr::null_mut();
+                };
+                let base = Backend::Rust.alloc(base_layout, zeroed);
+                if base.is_null() {
+                    return base;
+                }
+                // room for the base address, the aligned pointer cannot be
+                // further than `align - size_of::<usize>()` bytes away
+                let header = core::mem::size_of::<*mut u8>();
+                let align = base_layout.align().max(layout.align());
+                let p = base.add(header);
+                let p = p.add(p.align_offset(align));
+                (p as *mut *mut u8).sub(1).write(base);
+                p
+            }
+            #[cfg(all(unix, feature = "std"))]
+            Backend::PosixMemalign => {
+                // the alignment must also be a multiple of the pointer size
+                let align = layout
+                    .align()
+                    .max(core::mem::size_of::<*mut libc::c_void>());
+                let mut p = core::ptr::null_mut();
+                if libc::posix_memalign(&mut p, align, layout.size()) != 0 {
+                    return core::ptr::null_mut();
+                }
+                zero_if(p as *mut u8, layout, zeroed)
+            }
+            #[cfg(all(unix, feature = "std"))]
+            Backend::AlignedAlloc => {
+                let p = libc::aligned_alloc(layout.align(), layout.pad_to_align().size());
+                zero_if(p as *mut u8, layout, zeroed)
+            }
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc if zeroed => {
+                libmimalloc_sys::mi_zalloc_aligned(layout.size(), layout.align()) as *mut u8
+            }
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc => {
+                libmimalloc_sys::mi_malloc_aligned(layout.size(), layout.align()) as *mut u8
+            }
+            // `aligned_alloc` has no zeroing variant, `mallocx` is used instead
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc if zeroed => {
+                let flags = tikv_jemalloc_sys::MALLOCX_ALIGN(layout.align())
+                    | tikv_jemalloc_sys::MALLOCX_ZERO;
+                tikv_jemalloc_sys::mallocx(layout.size(), flags) as *mut u8
+            }
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc => {
+                tikv_jemalloc_sys::aligned_alloc(layout.align(), layout.size()) as *mut u8
+            }
+        }
+    }
+    /// [`Backend::dealloc`] without registration.
+    unsafe fn dealloc_unregistered(self, ptr: *mut u8, layout: Layout) {
+        match self {
+            Backend::Rust => alloc::alloc::dealloc(ptr, layout),
+            Backend::OverAligned => {
+                let base = (ptr as *mut *mut u8).sub(1).read();
+                Backend::Rust.dealloc(base, over_aligned_base(layout).unwrap());
+            }
+            #[cfg(all(unix, feature = "std"))]
+            Backend::PosixMemalign | Backend::AlignedAlloc => libc::free(ptr as *mut libc::c_void),
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc => libmimalloc_sys::mi_free(ptr as _),
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc => tikv_jemalloc_sys::free(ptr as _),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Resize the block at `ptr` with `realloc`, which honours alignments up to
+/// `realloc_align`. For larger alignments the block is moved into memory
+/// obtained from `alloc` and released with `free` once copied, so that a
+/// failure returns null with the block untouched as a failing `realloc` does.
+/// A block `realloc` moves to a misaligned address nonetheless is moved again
+/// the same way; the original block is gone by then, failing to allocate
+/// aborts.
+///
+/// # Safety
+///
+/// Same as [`Backend::realloc`], the functions must operate on the allocator
+/// `ptr` belongs to.
+pub(crate) unsafe fn realloc_aligned_with(
+    ptr: *mut u8,
+    layout: Layout,
+    new_size: usize,
+    realloc_align: usize,
+    realloc: impl FnOnce(*mut u8, usize) -> *mut u8,
+    alloc: impl FnOnce(Layout) -> *mut u8,
+    free: impl FnOnce(*mut u8),
+) -> *mut u8 {
+    let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+    if layout.align() > realloc_align {
+        let q = alloc(new_layout);
+        if !q.is_null() {
+            core::ptr::copy_nonoverlapping(ptr, q, layout.size().min(new_size));
+            free(ptr);
+        }
+        return q;
+    }
+    let p = realloc(ptr, new_size);
+    if p.is_null() || p.align_offset(layout.align()) == 0 {
+        return p;
+    }
+    let q = alloc(new_layout);
+    if q.is_null() {
+        alloc::alloc::handle_alloc_error(new_layout);
+    }
+    core::ptr::copy_nonoverlapping(p, q, layout.size().min(new_size));
+    free(p);
+    q
+}
+//-----------------------------------------------------------------------------
+/// Layout of the block holding an over-aligned allocation and the address of
+/// the block, `None` if the size overflows.
+fn over_aligned_base(layout: Layout) -> Option<Layout> {
+    let align = core::mem::align_of::<*mut u8>();
+    let size = layout.size().checked_add(layout.align().max(align))?;
+    Layout::from_size_align(size, align).ok()
+}
+//-----------------------------------------------------------------------------
+/// Overwrite the `layout.size()` bytes at `p` with zeros if `zeroed` is `true`
+/// and `p` is not null, the C allocators have no zeroing variant.
+#[cfg(all(unix, feature = "std"))]
+unsafe fn zero_if(p: *mut u8, layout: Layout, zeroed: bool) -> *mut u8 {
+    if zeroed && !p.is_null() {
+        p.write_bytes(0, layout.size());
+    }
+    p
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    // backends available on the target
+    const BACKENDS: &[Backend] = &[
+        Backend::Rust,
+        #[cfg(unix)]
+        Backend::PosixMemalign,
+        #[cfg(unix)]
+        Backend::AlignedAlloc,
+        Backend::OverAligned,
+        #[cfg(feature = "mimalloc")]
+        Backend::Mimalloc,
+        #[cfg(feature = "jemalloc")]
+        Backend::Jemalloc,
+    ];
+    #[test]
+    fn backend_test() {
+        for &backend in BACKENDS {
+            for align in [4, 8, 64, 4096] {
+                let mut v = crate::aligned_vec_in::<u32>(1000, 1500, align, Some(3), backend);
+                assert_eq!(v.backend(), backend);
+                assert_eq!(v.as_ptr() as usize % align, 0);
+                assert_eq!(v.capacity(), 1500);
+                v.as_mut_slice().fill(7);
+                assert!(v.as_slice().iter().all(|&x| x == 7));
+                let v = crate::realloc_aligned(v, 2000, 0).unwrap();
+                assert_eq!(v.backend(), backend);
+                assert_eq!(v[999], 7);
+                let b = v.into_boxed_slice();
+                assert_eq!(b.len(), 2000);
+                assert_eq!(b.as_ptr() as usize % align, 0);
+            }
+            let v = crate::AlignedVecBuilder::<u8>::new()
+                .size(100)
+                .align(256)
+                .zeroed(true)
+                .backend(backend)
+                .build()
+                .unwrap();
+            assert_eq!(v.backend(), backend);
+            assert!(v.as_slice().iter().all(|&x| x == 0));
+        }
+    }
+    #[test]
+    fn realloc_test() {
+        // mock allocator serving blocks out of a fixed pool
+        let mut pool = crate::aligned_vec::<u8>(4096, 0, 256, Some(0));
+        let base = pool.as_mut_ptr();
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            base.write_bytes(5, 64);
+            // extended in place
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                128,
+                64,
+                |p, _| p,
+                |_| unreachable!(),
+                |_| unreachable!(),
+            );
+            assert_eq!(p, base);
+            // moved, still aligned
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                128,
+                64,
+                |p, n| {
+                    p.copy_to(base.add(256), n.min(64));
+                    base.add(256)
+                },
+                |_| unreachable!(),
+                |_| unreachable!(),
+            );
+            assert_eq!(p, base.add(256));
+            assert_eq!(*p.add(63), 5);
+            // moved to a misaligned address, moved again
+            let mut freed = core::ptr::null_mut();
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                32,
+                64,
+                |p, n| {
+                    p.copy_to(base.add(1024 + 16), n);
+                    base.add(1024 + 16)
+                },
+                |l| {
+                    assert_eq!((l.size(), l.align()), (32, 64));
+                    base.add(2048)
+                },
+                |p| freed = p,
+            );
+            assert_eq!(p, base.add(2048));
+            assert_eq!(freed, base.add(1024 + 16));
+            assert!(std::slice::from_raw_parts(p, 32).iter().all(|&x| x == 5));
+            // failure leaves the block alone
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                128,
+                64,
+                |_, _| core::ptr::null_mut(),
+                |_| unreachable!(),
+                |_| unreachable!(),
+            );
+            assert!(p.is_null());
+            // alignment above what `realloc` honours, never passed to it
+            let mut freed = core::ptr::null_mut();
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                128,
+                16,
+                |_, _| unreachable!(),
+                |l| {
+                    assert_eq!((l.size(), l.align()), (128, 64));
+                    base.add(3072)
+                },
+                |p| freed = p,
+            );
+            assert_eq!((p, freed), (base.add(3072), base));
+            assert!(std::slice::from_raw_parts(p, 64).iter().all(|&x| x == 5));
+            // the allocation fails, the block is neither freed nor changed
+            base.write_bytes(6, 64);
+            let p = realloc_aligned_with(
+                base,
+                layout,
+                128,
+                16,
+                |_, _| unreachable!(),
+                |_| core::ptr::null_mut(),
+                |_| unreachable!(),
+            );
+            assert!(p.is_null());
+            assert!(std::slice::from_raw_parts(base, 64).iter().all(|&x| x == 6));
+        }
+        for &backend in BACKENDS {
+            for align in [8, 64, 4096] {
+                let layout = Layout::from_size_align(1000, align).unwrap();
+                unsafe {
+                    let p = backend.alloc(layout, false);
+                    p.write_bytes(9, 1000);
+                    let p = backend.realloc(p, layout, 1 << 20);
+                    assert_eq!(p.align_offset(align), 0);
+                    assert!(std::slice::from_raw_parts(p, 1000).iter().all(|&x| x == 9));
+                    backend.dealloc(p, Layout::from_size_align(1 << 20, align).unwrap());
+                }
+            }
+        }
+    }
+    // the external allocators serve large alignments themselves
+    #[cfg(any(feature = "mimalloc", feature = "jemalloc"))]
+    #[test]
+    fn external_allocator_test() {
+        let backends = [
+            #[cfg(feature = "mimalloc")]
+            Backend::Mimalloc,
+            #[cfg(feature = "jemalloc")]
+            Backend::Jemalloc,
+        ];
+        for backend in backends {
+            for align in [16, 4096, 1 << 20] {
+                let mut v = crate::aligned_vec_in::<u16>(5000, 0, align, Some(1), backend);
+                assert_eq!(v.backend(), backend);
+                assert!(v.is_aligned_to(align));
+                v.as_mut_slice().fill(3);
+                v.reserve(100_000);
+                assert!(v.is_aligned_to(align));
+                assert!(v.as_slice().iter().all(|&x| x == 3));
+                let v = crate::AlignedVecBuilder::<u64>::new()
+                    .size(10_000)
+                    .align(align)
+                    .zeroed(true)
+                    .backend(backend)
+                    .build()
+                    .unwrap();
+                assert!(v.is_aligned_to(align));
+                assert!(v.as_slice().iter().all(|&x| x == 0));
+            }
+        }
+    }
+    #[test]
+    fn over_aligned_test() {
+        for align in [1 << 20, 16 << 20] {
+            let mut v = crate::aligned_vec::<u64>(1000, 0, align, Some(1));
+            assert_eq!(v.backend(), Backend::OverAligned);
+            assert_eq!(v.alignment(), align);
+            assert!(v.is_aligned_to(align));
+            v.as_mut_slice().fill(2);
+            assert_eq!(v[999], 2);
+            let v = crate::zeroed_aligned_vec::<u8>(align + 1, 0, align);
+            assert_eq!(v.backend(), Backend::OverAligned);
+            assert!(v.is_aligned_to(align));
+            assert!(v.as_slice().iter().all(|&x| x == 0));
+        }
+        let v = crate::aligned_vec::<u64>(1000, 0, MAX_NATIVE_ALIGN, None);
+        assert_eq!(v.backend(), Backend::Rust);
+    }
+}
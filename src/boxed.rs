@@ -0,0 +1,131 @@
+//! Owning aligned fixed-size allocation.
+use crate::Backend;
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+//-----------------------------------------------------------------------------
+/// Aligned heap allocation, the equivalent of `Box` for memory allocated with
+/// an alignment larger than the alignment of `T`. Memory is released with the
+/// layout and by the [`Backend`] it was allocated with.
+pub struct AlignedBox<T: ?Sized> {
+    ptr: NonNull<T>,
+    layout: Layout,
+    backend: Backend,
+}
+
+impl<T: ?Sized> AlignedBox<T> {
+    /// Take ownership of memory returned by `backend.alloc(layout, _)`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to an initialised value allocated by `backend` with
+    /// `layout`, or be dangling if the size of `layout` is zero.
+    pub(crate) unsafe fn from_raw_parts(ptr: NonNull<T>, layout: Layout, backend: Backend) -> Self {
+        Self {
+            ptr,
+            layout,
+            backend,
+        }
+    }
+    /// Alignment of the allocation.
+    pub fn alignment(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl<T> AlignedBox<[MaybeUninit<T>]> {
+    /// Convert into an `AlignedBox<[T]>` once all the elements are
+    /// initialised, the allocation is reused.
+    ///
+    /// # Safety
+    ///
+    /// Every element must have been initialised.
+    pub unsafe fn assume_init(self) -> AlignedBox<[T]> {
+        let b = core::mem::ManuallyDrop::new(self);
+        let ptr = NonNull::slice_from_raw_parts(b.ptr.cast::<T>(), b.ptr.len());
+        AlignedBox::from_raw_parts(ptr, b.layout, b.backend)
+    }
+}
+
+// Owns its value like `Box<T>`.
+unsafe impl<T: ?Sized + Send> Send for AlignedBox<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for AlignedBox<T> {}
+
+impl<T: ?Sized> Drop for AlignedBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.ptr.as_ptr());
+            if self.layout.size() != 0 {
+                self.backend
+                    .dealloc(self.ptr.as_ptr() as *mut u8, self.layout);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for AlignedBox<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for AlignedBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    #[test]
+    fn aligned_boxed_slice_test() {
+        let ps = page_size::get();
+        let b = crate::aligned_boxed_slice::<u16>(3 * ps + 1, 256, Some(4));
+        assert_eq!(b.len(), 3 * ps + 1);
+        assert_eq!(b.as_ptr() as usize % 256, 0);
+        assert_eq!(b.alignment(), 256);
+        assert_eq!(b[0], 4);
+        let b = crate::aligned_vec_with(10, 100, 64, |i| i.to_string()).into_boxed_slice();
+        assert_eq!(b.len(), 10);
+        assert_eq!(b.as_ptr() as usize % 64, 0);
+        assert_eq!(b[9], "9");
+        let b = crate::aligned_boxed_slice::<u16>(0, 256, Some(4));
+        assert!(b.is_empty());
+        // a full buffer is not copied, whatever its backend
+        let v = crate::aligned_vec::<u8>(1000, 0, 1 << 20, Some(1));
+        let ptr = v.as_ptr();
+        let b = v.into_boxed_slice();
+        assert_eq!((b.as_ptr(), b.alignment()), (ptr, 1 << 20));
+        // spare capacity released by the backend of the buffer
+        let v = crate::aligned_vec_in::<u8>(10, 1000, 4096, Some(2), crate::Backend::OverAligned);
+        let b = v.into_boxed_slice();
+        assert_eq!(b.as_ptr() as usize % 4096, 0);
+        assert_eq!(b.backend, crate::Backend::OverAligned);
+        assert_eq!((b.len(), b[0]), (10, 2));
+    }
+    #[test]
+    fn aligned_uninit_boxed_slice_test() {
+        let mut b = crate::aligned_uninit_boxed_slice::<u32>(1000, 128);
+        assert_eq!(b.len(), 1000);
+        assert_eq!(b.alignment(), 128);
+        for (i, x) in b.iter_mut().enumerate() {
+            x.write(i as u32 * 3);
+        }
+        let b = unsafe { b.assume_init() };
+        assert_eq!(b.as_ptr() as usize % 128, 0);
+        assert_eq!(b.alignment(), 128);
+        assert!(b.iter().enumerate().all(|(i, &x)| x == i as u32 * 3));
+        let mut b = crate::aligned_uninit_boxed_slice::<String>(3, 64);
+        for x in b.iter_mut() {
+            x.write("x".repeat(10));
+        }
+        let b = unsafe { b.assume_init() };
+        assert_eq!(b[2], "xxxxxxxxxx");
+        let b = unsafe { crate::aligned_uninit_boxed_slice::<u8>(0, 4096).assume_init() };
+        assert!(b.is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+//! Builder for aligned buffers.
+use crate::{AlignedVec, AllocError, Backend, TouchReport, Zeroable};
+
+//-----------------------------------------------------------------------------
+/// Builder for `AlignedVec` replacing the positional arguments of the
+/// constructor functions.
+///
+/// Defaults: `size` zero, `capacity` equal to `size`, alignment
+/// `align_of::<T>()`, no touching, no page locking, uninitialised memory from
+/// the global allocator.
+///
+/// ```
+/// use aligned_vec::AlignedVecBuilder;
+/// let v = AlignedVecBuilder::<u32>::new()
+///     .size(1000)
+///     .align(64)
+///     .touch(0)
+///     .build()
+///     .unwrap();
+/// assert_eq!(v.as_ptr() as usize % 64, 0);
+/// assert_eq!(v.capacity(), 1000);
+/// ```
+#[derive(Clone, Debug)]
+pub struct AlignedVecBuilder<T> {
+    size: usize,
+    capacity: Option<usize>,
+    align: usize,
+    touch: Option<T>,
+    #[cfg(feature = "std")]
+    page_locked: bool,
+    // set to a zero test for `T` when memory is zero-initialised
+    zeroed: Option<fn(&T) -> bool>,
+    backend: Backend,
+}
+
+impl<T: Copy> AlignedVecBuilder<T> {
+    /// Builder with default settings.
+    pub fn new() -> Self {
+        Self {
+            size: 0,
+            capacity: None,
+            align: core::mem::align_of::<T>(),
+            touch: None,
+            #[cfg(feature = "std")]
+            page_locked: false,
+            zeroed: None,
+            backend: Backend::Rust,
+        }
+    }
+    /// Number of elements.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+    /// Number of elements to reserve, values smaller than the size are
+    /// ignored.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+    /// Alignment in bytes, must be a non-zero power of two. Values smaller
+    /// than `align_of::<T>()` are raised to it.
+    pub fn align(mut self, align: usize) -> Self {
+        self.align = align;
+        self
+    }
+    /// Initialise one element per page with `x`. Zero-initialised memory can
+    /// only be touched with a zero value, which is skipped when the backend
+    /// already faulted the pages in while zeroing.
+    pub fn touch(mut self, x: T) -> Self {
+        self.touch = Some(x);
+        self
+    }
+    /// Lock pages in memory, the alignment is raised to the page size.
+    /// Pages are not unlocked when the buffer is dropped, see
+    /// [`crate::page_locked_vec`].
+    #[cfg(feature = "std")]
+    pub fn page_locked(mut self, page_locked: bool) -> Self {
+        self.page_locked = page_locked;
+        self
+    }
+    /// Allocator to obtain memory from.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    /// Build the buffer.
+    pub fn build(self) -> Result<AlignedVec<T>, AllocError> {
+        self.build_with_report().map(|(v, _)| v)
+    }
+    /// Build the buffer and report the pages written to while touching it,
+    /// none if [`AlignedVecBuilder::touch`] was not called or touching was
+    /// skipped for zeroed memory.
+    pub fn build_with_report(self) -> Result<(AlignedVec<T>, TouchReport), AllocError> {
+        if !self.align.is_power_of_two() {
+            return Err(AllocError::InvalidAlignment { align: self.align });
+        }
+        #[cfg(feature = "std")]
+        let align = if self.page_locked {
+            self.align.max(crate::cached_page_size())
+        } else {
+            self.align
+        };
+        #[cfg(not(feature = "std"))]
+        let align = self.align;
+        let capacity = self.capacity.unwrap_or(self.size);
+        if let (Some(is_zero), Some(x)) = (self.zeroed, &self.touch) {
+            if !is_zero(x) {
+                return Err(AllocError::NonZeroTouch);
+            }
+        }
+        let mut v = crate::alloc_aligned_vec_in::<T>(
+            self.size,
+            capacity,
+            align,
+            self.zeroed.is_some(),
+            self.backend,
+        )?;
+        let mut report = TouchReport::default();
+        if let Some(x) = self.touch {
+            // touching only faults pages in, the value is already stored
+            if self.zeroed.is_none() || !v.backend().zeroed_pages_faulted() {
+                report = crate::touch_pages_counted(v.as_mut_slice(), x);
+            }
+        }
+        #[cfg(feature = "std")]
+        if self.page_locked {
+            crate::lock::lock_pages(&v)?;
+        }
+        Ok((v, report))
+    }
+}
+
+impl<T: Copy + Zeroable + PartialEq> AlignedVecBuilder<T> {
+    /// Zero-initialise memory with `alloc_zeroed`.
+    pub fn zeroed(mut self, zeroed: bool) -> Self {
+        self.zeroed = if zeroed { Some(is_zero::<T>) } else { None };
+        self
+    }
+}
+
+// Return `true` if `x` is the all-zero value.
+fn is_zero<T: Zeroable + PartialEq>(x: &T) -> bool {
+    *x == T::zeroed()
+}
+
+impl<T: Copy> Default for AlignedVecBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn builder_defaults_test() {
+        let v = AlignedVecBuilder::<u64>::new().size(10).build().unwrap();
+        assert_eq!(v.len(), 10);
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(v.as_ptr() as usize % std::mem::align_of::<u64>(), 0);
+        let v = AlignedVecBuilder::<u64>::default().build().unwrap();
+        assert!(v.is_empty());
+    }
+    #[test]
+    fn builder_test() {
+        let ps = page_size::get();
+        let v = AlignedVecBuilder::<u32>::new()
+            .size(3 * ps)
+            .capacity(4 * ps)
+            .align(128)
+            .zeroed(true)
+            .build()
+            .unwrap();
+        assert_eq!(v.as_ptr() as usize % 128, 0);
+        assert_eq!(v.capacity(), 4 * ps);
+        assert!(v.as_slice().iter().all(|&x| x == 0));
+        let v = AlignedVecBuilder::<u8>::new()
+            .size(2 * ps)
+            .touch(5)
+            .page_locked(true)
+            .build()
+            .unwrap();
+        assert_eq!(v.as_ptr() as usize % ps, 0);
+        assert_eq!(v[ps], 5);
+        unsafe {
+            crate::lock::sys::unlock(v.as_ptr() as *const std::ffi::c_void, 2 * ps).unwrap();
+        }
+        let r = AlignedVecBuilder::<u8>::new().size(1).align(12).build();
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 12 })));
+    }
+    #[test]
+    fn zeroed_touch_test() {
+        let ps = page_size::get();
+        let r = AlignedVecBuilder::<u8>::new()
+            .size(ps)
+            .zeroed(true)
+            .touch(1)
+            .build();
+        assert!(matches!(r, Err(AllocError::NonZeroTouch)));
+        // the global allocator may hand back zero pages that are not faulted
+        // in yet, they are still touched
+        let touches = crate::touch_count();
+        let v = AlignedVecBuilder::<u8>::new()
+            .size(4 * ps)
+            .zeroed(true)
+            .touch(0)
+            .build()
+            .unwrap();
+        assert!(v.as_slice().iter().all(|&x| x == 0));
+        // one touch per page spanned, the buffer may not start on a boundary
+        let start = v.as_ptr() as usize;
+        let pages = (start + 4 * ps).div_ceil(ps) - start / ps;
+        assert_eq!(crate::touch_count() - touches, pages);
+        // the C allocators are zeroed by writing every byte
+        #[cfg(unix)]
+        {
+            let touches = crate::touch_count();
+            let v = AlignedVecBuilder::<u8>::new()
+                .size(4 * ps)
+                .zeroed(true)
+                .touch(0)
+                .backend(Backend::PosixMemalign)
+                .build()
+                .unwrap();
+            assert!(v.as_slice().iter().all(|&x| x == 0));
+            assert_eq!(crate::touch_count(), touches);
+        }
+    }
+    #[test]
+    fn build_with_report_test() {
+        let ps = page_size::get();
+        let (v, report) = AlignedVecBuilder::<u64>::new()
+            .size(7 * ps / 8)
+            .align(ps)
+            .touch(1)
+            .build_with_report()
+            .unwrap();
+        assert_eq!(
+            report,
+            TouchReport {
+                pages: 7,
+                bytes: 7 * ps
+            }
+        );
+        assert_eq!(v[ps / 8], 1);
+        // the last page is partially filled
+        let (_, report) = AlignedVecBuilder::<u8>::new()
+            .size(5 * ps / 2)
+            .capacity(8 * ps)
+            .align(ps)
+            .touch(1)
+            .build_with_report()
+            .unwrap();
+        assert_eq!(report.pages, 3);
+        assert_eq!(report.bytes, 5 * ps / 2);
+        let (_, report) = AlignedVecBuilder::<u8>::new()
+            .size(ps)
+            .build_with_report()
+            .unwrap();
+        assert_eq!(report, TouchReport::default());
+    }
+}
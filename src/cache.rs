@@ -0,0 +1,59 @@
+//! CPU cache line size detection.
+
+// Used when the cache line size cannot be detected.
+const DEFAULT_CACHE_LINE_SIZE: usize = 64;
+
+//-----------------------------------------------------------------------------
+/// Return the CPU cache line size in bytes, 64 if it cannot be detected.
+///
+/// The size is read from `/sys/devices/system/cpu` on Linux and from the
+/// `hw.cachelinesize` sysctl on macOS, detection requires the `std` feature.
+pub fn cache_line_size() -> usize {
+    match detect() {
+        Some(s) if s.is_power_of_two() => s,
+        _ => DEFAULT_CACHE_LINE_SIZE,
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "std"))]
+fn detect() -> Option<usize> {
+    std::fs::read_to_string("/sys/devices/system/cpu/cpu0/cache/index0/coherency_line_size")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(all(target_os = "macos", feature = "std"))]
+fn detect() -> Option<usize> {
+    let mut size: u64 = 0;
+    let mut len = std::mem::size_of::<u64>();
+    let r = unsafe {
+        libc::sysctlbyname(
+            c"hw.cachelinesize".as_ptr(),
+            &mut size as *mut u64 as *mut std::ffi::c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (r == 0).then_some(size as usize)
+}
+
+#[cfg(not(all(any(target_os = "linux", target_os = "macos"), feature = "std")))]
+fn detect() -> Option<usize> {
+    None
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn cache_line_size_test() {
+        let s = cache_line_size();
+        assert!(s.is_power_of_two());
+        let v = crate::cache_line_aligned_vec::<u8>(1000, 0, Some(0));
+        assert_eq!(v.as_ptr() as usize % s, 0);
+    }
+}
@@ -0,0 +1,28 @@
+//! Child processes for tests.
+//!
+//! Tests changing process-wide state, such as resource limits or the memory
+//! locked by the process, or expecting a fault run in a forked child, so that
+//! the tests running concurrently in other threads are not affected.
+
+//-----------------------------------------------------------------------------
+/// Run `f` in a child process and return its wait status, the child exits
+/// with status 0 if `f` returns `true` and 1 otherwise.
+pub(crate) fn in_child(f: impl FnOnce() -> bool) -> i32 {
+    unsafe {
+        let pid = libc::fork();
+        assert!(pid >= 0);
+        if pid == 0 {
+            libc::_exit(if f() { 0 } else { 1 });
+        }
+        let mut status = 0;
+        assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+        status
+    }
+}
+//-----------------------------------------------------------------------------
+/// Run `f` in a child process and assert that it returned `true`.
+pub(crate) fn assert_in_child(f: impl FnOnce() -> bool) {
+    let status = in_child(f);
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(libc::WEXITSTATUS(status), 0);
+}
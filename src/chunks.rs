@@ -0,0 +1,116 @@
+//! Iteration over fixed-width, aligned groups of elements.
+//!
+//! Chunk `k` starts `k * N * size_of::<T>()` bytes after the first element, so
+//! every chunk is aligned to `N * size_of::<T>()` bytes when that width is a
+//! power of two not larger than the alignment of the buffer, e.g. chunks of
+//! 16 `f32` in a buffer aligned to 64 bytes can be loaded into AVX-512
+//! registers with aligned loads.
+use crate::AlignedVec;
+
+//-----------------------------------------------------------------------------
+/// Iterate over groups of `N` elements of an aligned buffer.
+pub trait AlignedChunks<T> {
+    /// Iterator over the complete chunks of `N` elements, elements past the
+    /// last complete chunk are returned by
+    /// [`AlignedChunks::aligned_chunks_remainder`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    fn aligned_chunks<'a, const N: usize>(&'a self) -> impl Iterator<Item = &'a [T; N]>
+    where
+        T: 'a;
+    /// Elements past the last complete chunk of `N` elements, fewer than `N`.
+    /// The remainder is aligned like the chunks but does not fill a register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero.
+    fn aligned_chunks_remainder<const N: usize>(&self) -> &[T];
+    /// Iterator over non-overlapping mutable windows of `window_elems`
+    /// elements, e.g. to hand successive parts of the buffer to SIMD
+    /// consumers. Every window starts on an alignment boundary of the buffer,
+    /// the last window is shorter if the length is not a multiple of
+    /// `window_elems`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_elems` is zero or if `window_elems * size_of::<T>()`
+    /// is not a multiple of [`AlignedVec::alignment`].
+    fn aligned_windows_mut<'a>(
+        &'a mut self,
+        window_elems: usize,
+    ) -> impl Iterator<Item = &'a mut [T]>
+    where
+        T: 'a;
+}
+
+impl<T> AlignedChunks<T> for AlignedVec<T> {
+    fn aligned_chunks<'a, const N: usize>(&'a self) -> impl Iterator<Item = &'a [T; N]>
+    where
+        T: 'a,
+    {
+        self.as_slice().as_chunks::<N>().0.iter()
+    }
+    fn aligned_chunks_remainder<const N: usize>(&self) -> &[T] {
+        self.as_slice().as_chunks::<N>().1
+    }
+    fn aligned_windows_mut<'a>(
+        &'a mut self,
+        window_elems: usize,
+    ) -> impl Iterator<Item = &'a mut [T]>
+    where
+        T: 'a,
+    {
+        assert!(window_elems != 0, "aligned_windows_mut: empty windows");
+        let bytes = window_elems.checked_mul(core::mem::size_of::<T>());
+        assert!(
+            bytes.is_some_and(|b| b % self.alignment() == 0),
+            "aligned_windows_mut: windows of {window_elems} elements are not a multiple of \
+             the alignment {}",
+            self.alignment()
+        );
+        self.as_mut_slice().chunks_mut(window_elems)
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_chunks_test() {
+        let v = crate::init_aligned_vec::<f32>(1003, 0, crate::simd::AVX512_ALIGN, 1.0);
+        let mut n = 0;
+        for c in v.aligned_chunks::<16>() {
+            assert_eq!(c.as_ptr() as usize % crate::simd::AVX512_ALIGN, 0);
+            assert_eq!(c, &[1.0; 16]);
+            n += 1;
+        }
+        assert_eq!(n, 1003 / 16);
+        let r = v.aligned_chunks_remainder::<16>();
+        assert_eq!(r.len(), 1003 % 16);
+        assert_eq!(r.as_ptr() as usize % crate::simd::AVX512_ALIGN, 0);
+        assert_eq!(v.aligned_chunks::<4>().count(), 1003 / 4);
+    }
+    #[test]
+    fn aligned_windows_mut_test() {
+        let mut v = crate::init_aligned_vec::<u32>(1000, 0, 128, 0);
+        let mut n = 0;
+        for (i, w) in v.aligned_windows_mut(96).enumerate() {
+            assert_eq!(w.as_ptr() as usize % 128, 0);
+            assert_eq!(w.len(), if i < 10 { 96 } else { 40 });
+            w.fill(i as u32);
+            n += 1;
+        }
+        assert_eq!(n, 11);
+        assert!(v.iter().enumerate().all(|(i, &x)| x == (i / 96) as u32));
+        assert_eq!(v.aligned_windows_mut(32).count(), 32);
+    }
+    #[test]
+    #[should_panic(expected = "windows of 24 elements are not a multiple of the alignment 128")]
+    fn aligned_windows_mut_misaligned_test() {
+        let mut v = crate::init_aligned_vec::<u32>(1000, 0, 128, 0);
+        v.aligned_windows_mut(24).for_each(drop);
+    }
+}
@@ -0,0 +1,54 @@
+//! Collecting iterators into aligned buffers with method syntax.
+use crate::{AlignedVec, AllocError};
+
+//-----------------------------------------------------------------------------
+/// Collect an iterator into an [`AlignedVec`], implemented for all iterators.
+///
+/// ```
+/// use aligned_vec::AlignedCollect;
+/// let v = (0..100u32).aligned_collect(64);
+/// assert_eq!(v.as_ptr() as usize % 64, 0);
+/// assert_eq!(v.len(), 100);
+/// ```
+pub trait AlignedCollect: Iterator + Sized {
+    /// Collect the elements into a buffer aligned to `align`, see
+    /// [`crate::from_iter_aligned`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a non-zero power of two, if the size in bytes
+    /// overflows or if allocation fails.
+    fn aligned_collect(self, align: usize) -> AlignedVec<Self::Item> {
+        crate::from_iter_aligned(self, align)
+    }
+    /// Fallible version of [`AlignedCollect::aligned_collect`], returns
+    /// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of
+    /// two.
+    fn try_aligned_collect(self, align: usize) -> Result<AlignedVec<Self::Item>, AllocError> {
+        crate::try_from_iter_aligned(self, align)
+    }
+}
+
+impl<I: Iterator> AlignedCollect for I {}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_collect_test() {
+        // the filter leaves the size hint without a useful lower bound
+        let v = (0..10_000u64).filter(|x| x % 7 == 3).aligned_collect(32);
+        assert!(v.is_aligned_to(32));
+        assert_eq!(v.alignment(), 32);
+        assert!(v.iter().copied().eq((3..10_000).step_by(7)));
+        let v = ["a", "b"]
+            .iter()
+            .map(|s| s.to_string())
+            .aligned_collect(128);
+        assert_eq!(v.as_slice(), ["a", "b"]);
+        assert!(v.is_aligned_to(128));
+        let r = (0..3u8).try_aligned_collect(12);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 12 })));
+    }
+}
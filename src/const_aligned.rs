@@ -0,0 +1,182 @@
+//! Buffers with an alignment fixed at compile time.
+//!
+//! `AlignedVecN<T, ALIGN>` carries its alignment in the type, so that a buffer
+//! aligned to 32 bytes cannot be passed where 64 are required and no runtime
+//! alignment value can be wrong. An `ALIGN` that is not a power of two is a
+//! compile time error.
+use crate::{AlignedVec, AllocError, Zeroable};
+use core::ops::{Deref, Index, IndexMut};
+use core::slice::SliceIndex;
+
+//-----------------------------------------------------------------------------
+/// `AlignedVec` aligned to `ALIGN.max(align_of::<T>())` bytes.
+pub struct AlignedVecN<T, const ALIGN: usize> {
+    vec: AlignedVec<T>,
+}
+
+impl<T, const ALIGN: usize> AlignedVecN<T, ALIGN> {
+    // evaluated, and failing to compile, when a constructor is instantiated
+    const VALID_ALIGNMENT: () = assert!(
+        ALIGN.is_power_of_two(),
+        "ALIGN must be a non-zero power of two"
+    );
+    /// Empty buffer with room for `capacity` elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if allocation fails.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::try_with_capacity(capacity)
+            .unwrap_or_else(|e| panic!("AlignedVecN::with_capacity: {e}"))
+    }
+    /// Fallible version of [`AlignedVecN::with_capacity`].
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, AllocError> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_ALIGNMENT;
+        Ok(Self {
+            vec: crate::try_aligned_vec_with_capacity(capacity, ALIGN)?,
+        })
+    }
+    /// Buffer of `size` elements initialised to `x`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if allocation fails.
+    pub fn from_elem(size: usize, x: T) -> Self
+    where
+        T: Copy,
+    {
+        Self::try_from_elem(size, x).unwrap_or_else(|e| panic!("AlignedVecN::from_elem: {e}"))
+    }
+    /// Fallible version of [`AlignedVecN::from_elem`].
+    pub fn try_from_elem(size: usize, x: T) -> Result<Self, AllocError>
+    where
+        T: Copy,
+    {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_ALIGNMENT;
+        Ok(Self {
+            vec: crate::try_init_aligned_vec(size, 0, ALIGN, x)?,
+        })
+    }
+    /// Buffer of `size` zeroed elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if allocation fails.
+    pub fn zeroed(size: usize) -> Self
+    where
+        T: Copy + Zeroable,
+    {
+        Self::try_zeroed(size).unwrap_or_else(|e| panic!("AlignedVecN::zeroed: {e}"))
+    }
+    /// Fallible version of [`AlignedVecN::zeroed`].
+    pub fn try_zeroed(size: usize) -> Result<Self, AllocError>
+    where
+        T: Copy + Zeroable,
+    {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_ALIGNMENT;
+        Ok(Self {
+            vec: crate::try_zeroed_aligned_vec(size, 0, ALIGN)?,
+        })
+    }
+    /// View elements as mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.vec.as_mut_slice()
+    }
+    /// Append an element, the buffer grows keeping its alignment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if growing the buffer fails.
+    pub fn push(&mut self, x: T) {
+        self.vec.push(x);
+    }
+    /// Unwrap the buffer, the alignment is then only known at runtime.
+    pub fn into_inner(self) -> AlignedVec<T> {
+        self.vec
+    }
+}
+
+// Only shared access to the inner buffer: replacing it could change the
+// alignment.
+impl<T, const ALIGN: usize> Deref for AlignedVecN<T, ALIGN> {
+    type Target = AlignedVec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl<T, const ALIGN: usize> FromIterator<T> for AlignedVecN<T, ALIGN> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_ALIGNMENT;
+        Self {
+            vec: crate::from_iter_aligned(iter, ALIGN),
+        }
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const ALIGN: usize> Index<I> for AlignedVecN<T, ALIGN> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.vec[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const ALIGN: usize> IndexMut<I> for AlignedVecN<T, ALIGN> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.vec[index]
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, const ALIGN: usize> serde::Serialize for AlignedVecN<T, ALIGN> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.vec.serialize(serializer)
+    }
+}
+
+// Deserialized into a buffer aligned to `ALIGN` rather than the default
+// alignment of `AlignedVec`.
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>, const ALIGN: usize> serde::Deserialize<'de>
+    for AlignedVecN<T, ALIGN>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let mut v = Self::try_with_capacity(0).map_err(D::Error::custom)?;
+        crate::serde_impl::deserialize_into(deserializer, &mut v.vec)?;
+        Ok(v)
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_vec_n_test() {
+        let mut v = AlignedVecN::<f32, 32>::from_elem(1000, 1.5);
+        assert!(v.is_aligned_to(32));
+        assert_eq!(v.alignment(), 32);
+        assert!(v.iter().all(|&x| x == 1.5));
+        v[3] = 2.0;
+        v.as_mut_slice()[4] = 3.0;
+        for _ in 0..5000 {
+            v.push(0.0);
+        }
+        assert!(v.is_aligned_to(32));
+        assert_eq!((v.len(), v[3], v[4]), (6000, 2.0, 3.0));
+        let v = AlignedVecN::<u64, 4096>::zeroed(10);
+        assert!(v.is_aligned_to(4096) && v.iter().all(|&x| x == 0));
+        let v = AlignedVecN::<u8, 64>::with_capacity(100);
+        assert!(v.is_empty() && v.capacity() >= 100);
+        // the element alignment wins when larger
+        let v: AlignedVecN<u64, 2> = (0..10).collect();
+        assert_eq!(v.alignment(), 8);
+        let v = v.into_inner();
+        assert!(v.iter().copied().eq(0..10));
+    }
+}
@@ -0,0 +1,107 @@
+//! Allocation counting for tests.
+//!
+//! The test binary allocates through `CountingAllocator`, which forwards to
+//! the system allocator and counts the calls made by each thread, so that a
+//! test can assert exactly how many allocations, reallocations and frees an
+//! operation performs. Counters are per thread so that tests running
+//! concurrently do not disturb each other.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+//-----------------------------------------------------------------------------
+/// Calls made to the global allocator by the current thread.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct AllocCounts {
+    pub(crate) allocs: usize,
+    pub(crate) reallocs: usize,
+    pub(crate) frees: usize,
+}
+
+std::thread_local! {
+    // const initialised, accessing it never allocates
+    static COUNTS: Cell<AllocCounts> = const {
+        Cell::new(AllocCounts { allocs: 0, reallocs: 0, frees: 0 })
+    };
+}
+
+/// Global allocator counting the calls of each thread.
+pub(crate) struct CountingAllocator;
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// the thread local is gone while the thread is being torn down, calls are not
+// counted then
+fn count(f: impl FnOnce(&mut AllocCounts)) {
+    let _ = COUNTS.try_with(|c| {
+        let mut counts = c.get();
+        f(&mut counts);
+        c.set(counts);
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(|c| c.allocs += 1);
+        System.alloc(layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(|c| c.allocs += 1);
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(|c| c.reallocs += 1);
+        System.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        count(|c| c.frees += 1);
+        System.dealloc(ptr, layout)
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Return the result of `f` and the allocator calls it made on this thread.
+pub(crate) fn count_allocs<R>(f: impl FnOnce() -> R) -> (R, AllocCounts) {
+    let before = COUNTS.with(|c| c.get());
+    let r = f();
+    let after = COUNTS.with(|c| c.get());
+    let counts = AllocCounts {
+        allocs: after.allocs - before.allocs,
+        reallocs: after.reallocs - before.reallocs,
+        frees: after.frees - before.frees,
+    };
+    (r, counts)
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn init_aligned_vec_allocs_test() {
+        let ps = page_size::get();
+        let ((), counts) = count_allocs(|| {
+            let v = crate::init_aligned_vec::<u32>(10 * ps, 0, ps, 7);
+            assert!(v.iter().all(|&x| x == 7));
+        });
+        let once = AllocCounts {
+            allocs: 1,
+            reallocs: 0,
+            frees: 1,
+        };
+        assert_eq!(counts, once);
+        // nothing to allocate
+        let (_, counts) = count_allocs(|| crate::aligned_vec::<u8>(0, 0, 64, None));
+        assert_eq!(counts, AllocCounts::default());
+        // growing reallocates, an over-aligned buffer the allocator moves is
+        // allocated again
+        let (v, counts) = count_allocs(|| {
+            let mut v = crate::aligned_vec_with_capacity::<u64>(16, 64);
+            v.extend_from_slice(&[1; 100]);
+            v
+        });
+        assert_eq!(counts.allocs, 1 + counts.frees);
+        assert_eq!(counts.reallocs, 1);
+        assert!(v.is_aligned_to(64));
+    }
+}
@@ -0,0 +1,248 @@
+//! Drop correctness of every constructor, meant to be run under Miri and
+//! AddressSanitizer as well as natively.
+//!
+//! Each constructor is called for several element types, alignments and
+//! lengths, from empty to several pages, and its buffer dropped right away.
+//! Allocations made through the global allocator must all be freed by the
+//! same thread, the other backends and the memory mappings are left to the
+//! leak and mismatch checks of the tools. With the `alloc_registry` feature
+//! every release is also checked against the layout it was allocated with.
+use crate::*;
+use core::ops::Deref;
+
+//-----------------------------------------------------------------------------
+// Element with an alignment larger than that of any primitive, never read.
+#[repr(align(32))]
+#[derive(Clone, Copy)]
+struct Align32(#[allow(dead_code)] [u8; 32]);
+
+unsafe impl Zeroable for Align32 {}
+
+const ALIGNS: [usize; 4] = [1, 64, 4096, 1 << 17];
+
+//-----------------------------------------------------------------------------
+/// Expected length and minimum alignment of the buffers of a case.
+#[derive(Clone, Copy)]
+struct Case {
+    len: usize,
+    align: usize,
+}
+
+impl Case {
+    /// Build a buffer with `build`, check its length and alignment through
+    /// `view` and drop it, the global allocations made by this thread must all
+    /// be freed.
+    fn check<T, B>(self, build: impl FnOnce() -> B, view: fn(&B) -> &[T]) {
+        let ((), counts) = crate::counting::count_allocs(|| self.check_uncounted(build, view));
+        // the registry allocates and frees its nodes on whichever thread
+        // inserts or removes them
+        if !cfg!(feature = "alloc_registry") {
+            assert_eq!(counts.allocs, counts.frees, "{counts:?}");
+        }
+    }
+    /// Same as [`Case::check`] for buffers allocated outside of the global
+    /// allocator or by other threads.
+    fn check_uncounted<T, B>(self, build: impl FnOnce() -> B, view: fn(&B) -> &[T]) {
+        let b = build();
+        let s = view(&b);
+        let align = self.align.max(core::mem::align_of::<T>());
+        assert_eq!(s.len(), self.len);
+        assert!(
+            is_aligned_to(s, align),
+            "{:p} not aligned to {align}",
+            s.as_ptr()
+        );
+        drop(b);
+    }
+    /// Same case with buffers aligned to `align`.
+    fn aligned(self, align: usize) -> Self {
+        Case { align, ..self }
+    }
+}
+
+fn slice<B: Deref<Target = [T]>, T>(b: &B) -> &[T] {
+    b
+}
+
+//-----------------------------------------------------------------------------
+/// Lengths covered for `T`: empty, single-element and a few pages plus one
+/// element.
+fn lengths<T>() -> [usize; 3] {
+    let multi_page = 3 * cached_page_size() / core::mem::size_of::<T>().max(1) + 1;
+    [0, 1, multi_page]
+}
+
+//-----------------------------------------------------------------------------
+/// Run every constructor with elements `x`.
+fn matrix<T: Copy + Zeroable + Send + Sync>(x: T) {
+    for len in lengths::<T>() {
+        for align in ALIGNS {
+            let c = Case { len, align };
+            c.check(|| aligned_vec::<T>(len, 0, align, None), slice);
+            c.check(|| aligned_vec(len, 2 * len, align, Some(x)), slice);
+            c.check(|| init_aligned_vec(len, 0, align, x), slice);
+            c.check(|| aligned_vec_with(len, 0, align, |_| x), slice);
+            c.check(|| aligned_vec_fill_with(len, 0, align, |_| x), slice);
+            c.check(|| zeroed_aligned_vec::<T>(len, 0, align), slice);
+            c.check(|| strided_aligned_vec(len, 0, align, x, 64), slice);
+            c.check(|| padded_aligned_vec(len, 0, align, Some(x)).0, slice);
+            c.check(|| capacity_aligned_vec(len, 0, align, 4096, Some(x)), slice);
+            for mode in [
+                TouchMode::None,
+                TouchMode::PerPage,
+                TouchMode::PerCacheLine,
+                TouchMode::ReadOnly,
+            ] {
+                c.check(|| touched_aligned_vec(len, 0, align, x, mode), slice);
+            }
+            c.check(
+                || from_iter_aligned(core::iter::repeat_n(x, len), align),
+                slice,
+            );
+            c.check(|| from_vec_aligned(vec![x; len], align), slice);
+            c.check(|| from_slice_aligned(&vec![x; len], align), slice);
+            c.check(|| aligned_boxed_slice(len, align, Some(x)), slice);
+            c.check(
+                || aligned_uninit_boxed_slice::<T>(len, align),
+                // never read, only the length and address are checked
+                |b| unsafe { core::slice::from_raw_parts(b.as_ptr() as *const T, b.len()) },
+            );
+            c.check(
+                || {
+                    let size = len * core::mem::size_of::<T>();
+                    let align = align.max(core::mem::align_of::<T>());
+                    let layout = Layout::from_size_align(size, align).unwrap();
+                    aligned_vec_from_layout(layout, len, Some(x)).unwrap()
+                },
+                slice,
+            );
+            c.check(
+                || {
+                    let mut v = aligned_vec_with_capacity(0, align);
+                    v.extend(core::iter::repeat_n(x, len));
+                    v
+                },
+                slice,
+            );
+            c.check(
+                || {
+                    let v = aligned_vec(len / 2, 0, align, Some(x));
+                    let v = realloc_aligned(v, 2 * len + 1, 0).unwrap();
+                    let mut v = shrink_aligned(v).unwrap();
+                    v.truncate(len);
+                    v
+                },
+                slice,
+            );
+            c.check(
+                || {
+                    let builder = AlignedVecBuilder::new().size(len).align(align);
+                    builder.touch(x).build().unwrap()
+                },
+                slice,
+            );
+            c.check(
+                || aligned_vec_group(&[1, len, 2], align, Some(x)),
+                |g| g.buffer(1),
+            );
+            for backend in [
+                Backend::Rust,
+                Backend::OverAligned,
+                #[cfg(unix)]
+                Backend::PosixMemalign,
+                #[cfg(unix)]
+                Backend::AlignedAlloc,
+                #[cfg(feature = "mimalloc")]
+                Backend::Mimalloc,
+                #[cfg(feature = "jemalloc")]
+                Backend::Jemalloc,
+            ] {
+                let build = || aligned_vec_in(len, 0, align, Some(x), backend);
+                if matches!(backend, Backend::Rust | Backend::OverAligned) {
+                    c.check(build, slice);
+                } else {
+                    c.check_uncounted(build, slice);
+                }
+            }
+            c.check_uncounted(|| par_aligned_vec(len, 0, align, Some(x), 3), slice);
+            c.check_uncounted(|| init_aligned_vec_parallel(len, 0, align, x, 3), slice);
+            os_matrix(c, x);
+        }
+        let c = Case { len, align: 1 };
+        c.aligned(cache_line_size())
+            .check(|| cache_line_aligned_vec(len, 0, Some(x)), slice);
+        c.check(|| dma_aligned_vec::<T>(len, 1 << 20), slice);
+        c.aligned(64)
+            .check(|| AlignedVecN::<T, 64>::from_elem(len, x), |v| v.as_slice());
+        c.aligned(4096)
+            .check(|| AlignedVecN::<T, 4096>::zeroed(len), |v| v.as_slice());
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Run the constructors relying on system calls Miri does not support.
+#[cfg(not(miri))]
+fn os_matrix<T: Copy + Zeroable + Send + Sync>(c: Case, x: T) {
+    let Case { len, align } = c;
+    let page = c.aligned(cached_page_size());
+    let paged = c.aligned(cached_page_size().max(align));
+    paged.check(
+        || page_aligned_vec_with_align(len, 0, paged.align, Some(x), false),
+        slice,
+    );
+    // locking, mapping and binding may be refused by the environment, the
+    // buffer is then released before the error is returned
+    if let Ok(v) = try_page_aligned_vec_with_align(len, 0, paged.align, Some(x), true) {
+        paged.check_uncounted(|| v, slice);
+    }
+    if let Ok(v) = try_page_locked_vec(len, 0, Some(x)) {
+        page.check_uncounted(|| v, |v| v.as_slice());
+    }
+    if let Ok(v) = try_secure_page_locked_vec(len, 0, Some(x)) {
+        page.check_uncounted(|| v, |v| v.as_slice());
+    }
+    if let Ok(v) = secure_buffer::<T>(len, align) {
+        paged.check_uncounted(|| v, |v| v.as_slice());
+    }
+    #[cfg(unix)]
+    {
+        c.check_uncounted(|| guarded_aligned_vec::<T>(len, align), slice);
+        let build = || seal_readonly(aligned_vec(len, 0, paged.align, Some(x))).unwrap();
+        paged.check_uncounted(build, slice);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let mapped = || mmap_aligned_vec(len, 0, Some(x));
+        page.check_uncounted(mapped, |v| v.as_slice());
+        c.check(|| thp_aligned_vec(len, 0, align, Some(x)), slice);
+        let cpu = unsafe { libc::sched_getcpu() } as usize;
+        c.check(|| pinned_aligned_vec(len, 0, align, x, &[cpu]), slice);
+        if let Ok(v) = try_huge_page_aligned_vec(len, 0, 2 << 20, Some(x)) {
+            c.aligned(2 << 20).check_uncounted(|| v, |v| v.as_slice());
+        }
+        if let Ok(v) = try_numa_aligned_vec(len, 0, align, 0, Some(x)) {
+            paged.check_uncounted(|| v, slice);
+        }
+    }
+}
+
+#[cfg(miri)]
+fn os_matrix<T>(_: Case, _: T) {}
+
+//=============================================================================
+#[test]
+fn drop_u8_test() {
+    matrix(7u8);
+}
+#[test]
+fn drop_u64_test() {
+    matrix(u64::MAX);
+}
+#[test]
+fn drop_align32_test() {
+    matrix(Align32([3; 32]));
+}
+#[test]
+fn drop_zst_test() {
+    matrix(());
+}
@@ -0,0 +1,117 @@
+//! Buffers of integers stored in a fixed byte order, e.g. the fields of DMA
+//! descriptor rings read by a device whatever the endianness of the host.
+use crate::{AlignedVec, AllocError};
+
+mod private {
+    pub trait Sealed {}
+}
+
+//-----------------------------------------------------------------------------
+/// Primitive integer types, implemented for the signed and unsigned integers
+/// only.
+pub trait Integer: private::Sealed + Copy {
+    /// Value whose in-memory bytes are the big-endian bytes of `self`.
+    fn to_be_order(self) -> Self;
+    /// Value whose in-memory bytes are the little-endian bytes of `self`.
+    fn to_le_order(self) -> Self;
+}
+
+macro_rules! integer {
+    ($($t:ty)*) => {$(
+        impl private::Sealed for $t {}
+        impl Integer for $t {
+            fn to_be_order(self) -> Self {
+                <$t>::from_ne_bytes(self.to_be_bytes())
+            }
+            fn to_le_order(self) -> Self {
+                <$t>::from_ne_bytes(self.to_le_bytes())
+            }
+        }
+    )*};
+}
+integer!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` with every element holding `x` stored in
+/// big-endian byte order. Elements read on a little-endian host have their
+/// bytes reversed.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn init_aligned_vec_be<T: Integer>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> AlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_init_aligned_vec_be(size, capacity, align, x)
+        .unwrap_or_else(|e| panic!("init_aligned_vec_be: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`init_aligned_vec_be`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_init_aligned_vec_be<T: Integer>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> Result<AlignedVec<T>, AllocError> {
+    crate::try_init_aligned_vec(size, capacity, align, x.to_be_order())
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` with every element holding `x` stored in
+/// little-endian byte order. Elements read on a big-endian host have their
+/// bytes reversed.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn init_aligned_vec_le<T: Integer>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> AlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_init_aligned_vec_le(size, capacity, align, x)
+        .unwrap_or_else(|e| panic!("init_aligned_vec_le: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`init_aligned_vec_le`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_init_aligned_vec_le<T: Integer>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> Result<AlignedVec<T>, AllocError> {
+    crate::try_init_aligned_vec(size, capacity, align, x.to_le_order())
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn init_aligned_vec_endian_test() {
+        let be = init_aligned_vec_be::<u32>(100, 0, 64, 0x0102_0304);
+        let le = init_aligned_vec_le::<u32>(100, 0, 64, 0x0102_0304);
+        assert_eq!(be.as_ptr() as usize % 64, 0);
+        assert_eq!(be.len(), 100);
+        assert!(be.as_bytes().chunks(4).all(|b| b == [1, 2, 3, 4]));
+        assert!(le.as_bytes().chunks(4).all(|b| b == [4, 3, 2, 1]));
+        #[cfg(target_endian = "little")]
+        {
+            assert!(be.iter().all(|&x| x == 0x0403_0201));
+            assert!(le.iter().all(|&x| x == 0x0102_0304));
+        }
+        let v = init_aligned_vec_be::<i16>(3, 0, 8, -2);
+        assert_eq!(v.as_bytes(), [0xff, 0xfe, 0xff, 0xfe, 0xff, 0xfe]);
+        let r = try_init_aligned_vec_le::<u64>(1, 0, 3, 1);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+    }
+}
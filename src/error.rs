@@ -0,0 +1,104 @@
+//! Errors returned by the fallible constructors.
+use core::alloc::Layout;
+use core::fmt;
+
+//-----------------------------------------------------------------------------
+/// Allocation error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AllocError {
+    /// The allocator returned a null pointer for the requested layout.
+    OutOfMemory { layout: Layout },
+    /// The requested alignment is zero or not a power of two.
+    InvalidAlignment { align: usize },
+    /// The size in bytes of the buffer overflows `isize`.
+    LayoutOverflow,
+    /// The layout is too small or not aligned enough for the `elements`
+    /// elements it must hold.
+    IncompatibleLayout { layout: Layout, elements: usize },
+    /// The touch stride is zero.
+    InvalidTouchStride,
+    /// A non-zero touch value was requested for zero-initialised memory.
+    NonZeroTouch,
+    /// The huge page size is not supported.
+    InvalidHugePageSize { huge_page_size: usize },
+    /// The NUMA node does not exist.
+    InvalidNumaNode { node: usize },
+    /// The buffer does not start on a page boundary.
+    NotPageAligned,
+    /// The requested alignment is smaller than the page size.
+    BelowPageSize { align: usize, page_size: usize },
+    /// Locking `requested` bytes would exceed the soft `RLIMIT_MEMLOCK` limit
+    /// of `limit` bytes.
+    LockLimitExceeded { requested: usize, limit: u64 },
+    /// No CPU was given to run the touch loop on.
+    EmptyCpuSet,
+    /// A buffer of `bytes` bytes cannot fit in a region of `boundary` bytes.
+    BoundaryTooSmall { bytes: usize, boundary: usize },
+    /// The system call `op`, e.g. `mlock`, failed with the OS error `source`:
+    /// `mmap` when huge pages are not reserved, `mlock` or `mlockall` above
+    /// `RLIMIT_MEMLOCK`, `sched_setaffinity` for a CPU that does not exist.
+    #[cfg(feature = "std")]
+    Syscall {
+        op: &'static str,
+        source: std::io::Error,
+    },
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocError::OutOfMemory { layout } => write!(
+                f,
+                "out of memory allocating {} bytes aligned to {}",
+                layout.size(),
+                layout.align()
+            ),
+            AllocError::InvalidAlignment { align } => {
+                write!(f, "alignment {align} is not a non-zero power of two")
+            }
+            AllocError::LayoutOverflow => write!(f, "buffer size in bytes overflows isize::MAX"),
+            AllocError::IncompatibleLayout { layout, elements } => write!(
+                f,
+                "layout of {} bytes aligned to {} cannot hold {elements} elements",
+                layout.size(),
+                layout.align()
+            ),
+            AllocError::InvalidTouchStride => write!(f, "touch stride must be non-zero"),
+            AllocError::NonZeroTouch => {
+                write!(f, "zero-initialised memory can only be touched with zero")
+            }
+            AllocError::InvalidHugePageSize { huge_page_size } => {
+                write!(f, "unsupported huge page size {huge_page_size}")
+            }
+            AllocError::InvalidNumaNode { node } => write!(f, "invalid NUMA node {node}"),
+            AllocError::NotPageAligned => write!(f, "buffer is not page aligned"),
+            AllocError::BelowPageSize { align, page_size } => write!(
+                f,
+                "alignment {align} is not a multiple of the page size {page_size}"
+            ),
+            AllocError::LockLimitExceeded { requested, limit } => write!(
+                f,
+                "locking {requested} bytes exceeds the RLIMIT_MEMLOCK limit of {limit} bytes \
+                 (ulimit -l)"
+            ),
+            AllocError::EmptyCpuSet => write!(f, "CPU set must not be empty"),
+            AllocError::BoundaryTooSmall { bytes, boundary } => write!(
+                f,
+                "buffer of {bytes} bytes does not fit within a {boundary} byte boundary"
+            ),
+            #[cfg(feature = "std")]
+            AllocError::Syscall { op, source } => write!(f, "{op} failed: {source}"),
+        }
+    }
+}
+
+impl core::error::Error for AllocError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            AllocError::Syscall { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
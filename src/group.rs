@@ -0,0 +1,147 @@
+//! Several aligned buffers sharing a single allocation.
+//!
+//! Buffers used together, e.g. the inputs and outputs of a DSP stage, are
+//! laid out one after the other in one allocation for locality. Each buffer
+//! starts on an alignment boundary, padding is inserted between buffers as
+//! needed.
+use crate::{AlignedVec, AllocError};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+//-----------------------------------------------------------------------------
+/// Group of aligned buffers of `T` elements stored in one allocation, created
+/// with [`aligned_vec_group`].
+pub struct AlignedGroup<T> {
+    buf: AlignedVec<u8>,
+    // byte offset and element count of each buffer
+    buffers: Vec<(usize, usize)>,
+    _elements: PhantomData<T>,
+}
+
+impl<T> AlignedGroup<T> {
+    /// Number of buffers.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+    /// Return `true` if the group holds no buffers.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+    /// Total size in bytes of the allocation, padding included.
+    pub fn allocated_bytes(&self) -> usize {
+        self.buf.len()
+    }
+    /// Buffer `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn buffer(&self, i: usize) -> &[T] {
+        let (offset, len) = self.buffers[i];
+        unsafe { core::slice::from_raw_parts(self.buf.as_ptr().add(offset) as *const T, len) }
+    }
+    /// Mutable buffer `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    pub fn buffer_mut(&mut self, i: usize) -> &mut [T] {
+        let (offset, len) = self.buffers[i];
+        unsafe { core::slice::from_raw_parts_mut(self.buf.as_mut_ptr().add(offset) as *mut T, len) }
+    }
+    /// All the buffers, mutable at the same time.
+    pub fn buffers_mut(&mut self) -> Vec<&mut [T]> {
+        let base = self.buf.as_mut_ptr();
+        // buffers do not overlap
+        self.buffers
+            .iter()
+            .map(|&(offset, len)| unsafe {
+                core::slice::from_raw_parts_mut(base.add(offset) as *mut T, len)
+            })
+            .collect()
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Return a group of buffers of `sizes[i]` elements each starting on an
+/// `align.max(align_of::<T>())` boundary, allocated together. When `touch` is
+/// not `None` one element per page of each buffer is initialised with the
+/// passed value.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_group<T: Copy>(
+    sizes: &[usize],
+    align: usize,
+    touch: Option<T>,
+) -> AlignedGroup<T> {
+    crate::assert_valid_alignment(align);
+    try_aligned_vec_group(sizes, align, touch).unwrap_or_else(|e| panic!("aligned_vec_group: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_group`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_group<T: Copy>(
+    sizes: &[usize],
+    align: usize,
+    touch: Option<T>,
+) -> Result<AlignedGroup<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    let align = align.max(core::mem::align_of::<T>());
+    let mut buffers = Vec::with_capacity(sizes.len());
+    let mut end = 0usize;
+    for &size in sizes {
+        // padding up to the next boundary
+        let offset = end
+            .checked_next_multiple_of(align)
+            .ok_or(AllocError::LayoutOverflow)?;
+        let bytes = size
+            .checked_mul(core::mem::size_of::<T>())
+            .ok_or(AllocError::LayoutOverflow)?;
+        end = offset
+            .checked_add(bytes)
+            .ok_or(AllocError::LayoutOverflow)?;
+        buffers.push((offset, size));
+    }
+    let mut g = AlignedGroup {
+        buf: crate::alloc_aligned_vec::<u8>(end, 0, align, false)?,
+        buffers,
+        _elements: PhantomData,
+    };
+    if let Some(x) = touch {
+        for b in g.buffers_mut() {
+            crate::touch_pages(b, x);
+        }
+    }
+    Ok(g)
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn aligned_vec_group_test() {
+        let sizes = [1000, 3, 4096];
+        let mut g = aligned_vec_group::<[u32; 3]>(&sizes, 128, Some([1, 2, 3]));
+        assert_eq!(g.len(), 3);
+        for (i, &size) in sizes.iter().enumerate() {
+            assert_eq!(g.buffer(i).len(), size);
+            assert_eq!(g.buffer(i).as_ptr() as usize % 128, 0);
+            assert_eq!(g.buffer(i)[0], [1, 2, 3]);
+        }
+        let mut b = g.buffers_mut();
+        b[0].fill([4; 3]);
+        b[1].fill([5; 3]);
+        b[2].fill([6; 3]);
+        assert!(g.buffer(0).iter().all(|&x| x == [4; 3]));
+        assert!(g.buffer(1).iter().all(|&x| x == [5; 3]));
+        assert!(g.buffer_mut(2).iter().all(|&x| x == [6; 3]));
+        assert_eq!(g.allocated_bytes(), 12032 + 128 + 4096 * 12);
+        assert!(aligned_vec_group::<u8>(&[], 64, None).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Buffers followed by an inaccessible guard page.
+//!
+//! The buffer and the page after it are mapped together with `mmap`, the
+//! trailing page is protected with `PROT_NONE` so that running past the end of
+//! the buffer faults immediately instead of corrupting other memory.
+use crate::{AllocError, Zeroable};
+use nix::sys::mman::{MapFlags, ProtFlags};
+use std::alloc::Layout;
+use std::ffi::c_void;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+//-----------------------------------------------------------------------------
+/// Page-backed buffer of `T` elements followed by a guard page, created with
+/// [`guarded_aligned_vec`]. The whole mapping, guard page included, is
+/// removed when the value is dropped.
+pub struct GuardedAlignedVec<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    // start and size in bytes of the mapping, guard page included
+    map: *mut c_void,
+    map_len: usize,
+}
+
+impl<T> GuardedAlignedVec<T> {
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Return `true` if the buffer contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Pointer to the first element.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+    /// Mutable pointer to the first element.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+    /// View elements as slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+    /// View elements as mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+    /// Start of the guard page, null for empty buffers.
+    pub fn guard_page(&self) -> *const u8 {
+        if self.map_len == 0 {
+            return std::ptr::null();
+        }
+        unsafe { (self.map as *const u8).add(self.map_len - crate::cached_page_size()) }
+    }
+    /// Size in bytes of the memory mapping, guard page included.
+    pub fn mapped_bytes(&self) -> usize {
+        self.map_len
+    }
+}
+
+// The mapping is private to the buffer, `map` is only used to unmap it on
+// drop, which any thread can do.
+unsafe impl<T: Send> Send for GuardedAlignedVec<T> {}
+unsafe impl<T: Sync> Sync for GuardedAlignedVec<T> {}
+
+impl<T> Drop for GuardedAlignedVec<T> {
+    fn drop(&mut self) {
+        unsafe {
+            std::ptr::drop_in_place(self.as_mut_slice());
+            if self.map_len != 0 {
+                let _ = nix::sys::mman::munmap(self.map, self.map_len);
+                #[cfg(feature = "stats")]
+                crate::stats::record_free(self.map_len);
+            }
+        }
+    }
+}
+
+impl<T> Deref for GuardedAlignedVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for GuardedAlignedVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Return a zeroed buffer of `size` elements aligned to
+/// `align.max(page_size)` and followed by a `PROT_NONE` guard page, so that
+/// writing past the pages of the buffer faults. The buffer is page-backed:
+/// it is mapped with `mmap` and occupies whole pages, an overrun within the
+/// last page of the buffer is only caught when the size in bytes is a
+/// multiple of the page size.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if the mapping fails.
+pub fn guarded_aligned_vec<T: Zeroable>(size: usize, align: usize) -> GuardedAlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_guarded_aligned_vec(size, align).unwrap_or_else(|e| panic!("guarded_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`guarded_aligned_vec`], returns
+/// [`AllocError::OutOfMemory`] if the mapping fails and
+/// [`AllocError::Syscall`] if the guard page cannot be protected.
+pub fn try_guarded_aligned_vec<T: Zeroable>(
+    size: usize,
+    align: usize,
+) -> Result<GuardedAlignedVec<T>, AllocError> {
+    let ps = crate::cached_page_size();
+    let (layout, _) = crate::aligned_layout::<T>(size, 0, align.max(ps))?;
+    if layout.size() == 0 {
+        return Ok(GuardedAlignedVec {
+            ptr: NonNull::new(std::ptr::without_provenance_mut(layout.align())).unwrap(),
+            len: size,
+            map: std::ptr::null_mut(),
+            map_len: 0,
+        });
+    }
+    // whole pages, the guard page follows the last one
+    let buf_len = layout
+        .size()
+        .checked_next_multiple_of(ps)
+        .ok_or(AllocError::LayoutOverflow)?;
+    // the guard page and, mappings being page aligned, `align - ps` bytes of
+    // room to shift the start of the buffer to a larger alignment
+    let map_len = buf_len
+        .checked_add(layout.align())
+        .ok_or(AllocError::LayoutOverflow)?;
+    let map = unsafe {
+        nix::sys::mman::mmap(
+            std::ptr::null_mut(),
+            map_len,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    }
+    .map_err(|_| AllocError::OutOfMemory {
+        layout: Layout::from_size_align(map_len, ps).unwrap(),
+    })?;
+    let offset = map.align_offset(layout.align());
+    // the pages before and after the buffer and its guard page are released
+    let (map, map_len) = unsafe {
+        let start = (map as *mut u8).add(offset) as *mut c_void;
+        let trailing = map_len - offset - (buf_len + ps);
+        if offset != 0 {
+            let _ = nix::sys::mman::munmap(map, offset);
+        }
+        if trailing != 0 {
+            let end = (start as *mut u8).add(buf_len + ps) as *mut c_void;
+            let _ = nix::sys::mman::munmap(end, trailing);
+        }
+        (start, buf_len + ps)
+    };
+    let guard = unsafe { (map as *mut u8).add(buf_len) as *mut c_void };
+    if let Err(e) = unsafe { nix::sys::mman::mprotect(guard, ps, ProtFlags::PROT_NONE) } {
+        unsafe {
+            let _ = nix::sys::mman::munmap(map, map_len);
+        }
+        return Err(AllocError::Syscall {
+            op: "mprotect",
+            source: e.into(),
+        });
+    }
+    #[cfg(feature = "stats")]
+    crate::stats::record_alloc(map_len);
+    Ok(GuardedAlignedVec {
+        ptr: NonNull::new(map as *mut T).expect("mmap returned null"),
+        len: size,
+        map,
+        map_len,
+    })
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // `true` if the page at `p` is mapped
+    fn mapped(p: *const u8) -> bool {
+        let ps = page_size::get();
+        let mut v = 0u8;
+        unsafe { libc::mincore(p as *mut c_void, ps, &mut v as *mut u8 as *mut _) == 0 }
+    }
+    #[test]
+    fn guarded_aligned_vec_test() {
+        let ps = page_size::get();
+        for align in [64, ps, 4 * ps] {
+            let mut v = guarded_aligned_vec::<u32>(3 * ps / 4, align);
+            assert_eq!(v.len(), 3 * ps / 4);
+            assert!((v.as_ptr() as usize).is_multiple_of(align.max(ps)));
+            assert_eq!(v.mapped_bytes(), 4 * ps);
+            assert_eq!(v.guard_page(), unsafe {
+                (v.as_ptr() as *const u8).add(3 * ps)
+            });
+            assert!(v.iter().all(|&x| x == 0));
+            v.fill(5);
+            assert_eq!(v[3 * ps / 4 - 1], 5);
+        }
+        assert!(guarded_aligned_vec::<u8>(0, 64).is_empty());
+        // an overrun faults, checked in a child process
+        let status = crate::child::in_child(|| {
+            let mut v = guarded_aligned_vec::<u8>(ps, 64);
+            unsafe { v.as_mut_ptr().add(ps).write_volatile(1) };
+            true
+        });
+        assert!(libc::WIFSIGNALED(status));
+        assert!([libc::SIGSEGV, libc::SIGBUS].contains(&libc::WTERMSIG(status)));
+        // buffer and guard page are unmapped on drop; in a child process no
+        // other thread can map the range again meanwhile
+        crate::child::assert_in_child(|| {
+            let v = guarded_aligned_vec::<u8>(2 * ps, 4 * ps);
+            let (start, guard) = (v.as_ptr(), v.guard_page());
+            let ok = mapped(start) && mapped(guard);
+            drop(v);
+            ok && !mapped(start) && !mapped(unsafe { start.add(ps) }) && !mapped(guard)
+        });
+    }
+}
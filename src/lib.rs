@@ -2,6 +2,29 @@
 //!
 //! Small set of functions to create and optionally initialise and page-lock
 //! `Vec` objects.
+//!
+//! Disabling the default `std` feature makes the crate `no_std`, only the
+//! `alloc` crate and a global allocator are then required. Page locking,
+//! memory mappings, parallel touching and OS queries need `std`, which is also
+//! the only feature depending on `nix` and the other OS crates: without it the
+//! allocation constructors build on targets these crates do not support, and
+//! requesting page-locked memory is a compile error since `page_aligned_vec`
+//! and `PageLockedVec` are not available.
+//!
+//! Buffers are always aligned to at least `align_of::<T>()`: the alignment
+//! passed to the constructors is raised to the alignment of the element type
+//! when smaller, and [`AlignedVec::alignment`] reports the effective value.
+//! Elements are contiguous and `size_of::<T>()` is a multiple of
+//! `align_of::<T>()`, so with the base aligned every element is aligned to its
+//! type: atomics such as `AtomicU64`, whose alignment equals their size on all
+//! targets, can always be accessed lock-free. Elements of a type aligned to
+//! less than its size, e.g. `[u8; 8]`, are all aligned to their size only if
+//! `align` is a multiple of the size.
+//!
+//! The `capacity` passed to the constructors is a minimum: a capacity smaller
+//! than `size`, `0` in particular, is raised to `size`, so that
+//! `aligned_vec::<u8>(100, 0, 64, None)` holds 100 elements and has a
+//! capacity of at least 100.
 //!```ignore,rust
 //!    fn page_alignedi_test() {
 //!        let ps = page_size::get();
@@ -15,80 +38,1768 @@
 //!        assert_eq!(v[ps], init_value);
 //!    }
 //! ```
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+extern crate alloc;
+mod adopt;
+#[cfg(all(unix, feature = "std"))]
+mod advise;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod affinity;
+#[cfg(feature = "allocator_api")]
+mod allocator;
+mod arena;
+mod backend;
+mod boxed;
+mod builder;
+mod cache;
+#[cfg(all(test, unix, feature = "std"))]
+mod child;
+mod chunks;
+mod collect;
+mod const_aligned;
+#[cfg(all(test, feature = "std"))]
+mod counting;
+#[cfg(all(test, feature = "std"))]
+mod drop_matrix;
+mod endian;
+mod error;
+mod group;
+#[cfg(all(unix, feature = "std"))]
+mod guard;
+#[cfg(feature = "std")]
+mod lock;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod mmap;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod numa;
+#[cfg(feature = "std")]
+mod prefault;
+#[cfg(all(feature = "alloc_registry", debug_assertions))]
+mod registry;
+#[cfg(all(unix, feature = "std"))]
+mod resident;
+#[cfg(all(unix, feature = "std"))]
+mod seal;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod simd;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod thp;
+mod trace;
+mod vec;
+pub use adopt::{adopt_aligned, AdoptedVec};
+#[cfg(all(unix, feature = "std"))]
+pub use advise::{advise, Advice};
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use affinity::{pinned_aligned_vec, try_pinned_aligned_vec};
+use alloc::vec::Vec;
+#[cfg(feature = "allocator_api")]
+pub use allocator::AlignedAllocator;
+#[cfg(all(feature = "allocator_api", feature = "std"))]
+pub use allocator::{scoped_vec, scoped_vec_with_capacity, with_alignment};
+pub use arena::AlignedArena;
+pub use backend::Backend;
+pub use boxed::AlignedBox;
+pub use builder::AlignedVecBuilder;
+pub use bytemuck::{Pod, Zeroable};
+pub use cache::cache_line_size;
+pub use chunks::AlignedChunks;
+pub use collect::AlignedCollect;
+pub use const_aligned::AlignedVecN;
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+pub use endian::{
+    init_aligned_vec_be, init_aligned_vec_le, try_init_aligned_vec_be, try_init_aligned_vec_le,
+    Integer,
+};
+pub use error::AllocError;
+pub use group::{aligned_vec_group, try_aligned_vec_group, AlignedGroup};
+#[cfg(all(unix, feature = "std"))]
+pub use guard::{guarded_aligned_vec, try_guarded_aligned_vec, GuardedAlignedVec};
+#[cfg(all(unix, feature = "std"))]
+pub use lock::{lock_all, memlock_limit, unlock_all, MlockAllFlags};
+#[cfg(feature = "std")]
+pub use lock::{LockError, PageLockedVec, SecureBuffer};
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use mmap::{
+    huge_page_aligned_vec, mmap_aligned_vec, try_huge_page_aligned_vec, try_mmap_aligned_vec,
+    MmapVec,
+};
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use numa::{
+    interleaved_aligned_vec, numa_aligned_vec, try_interleaved_aligned_vec, try_numa_aligned_vec,
+};
+#[cfg(feature = "std")]
+pub use prefault::PrefaultHandle;
+#[cfg(all(unix, feature = "std"))]
+pub use resident::resident_pages;
+#[cfg(all(unix, feature = "std"))]
+pub use seal::{seal_readonly, SealedAlignedVec};
+#[cfg(feature = "serde")]
+pub use serde_impl::DESERIALIZE_ALIGN;
+#[cfg(feature = "stats")]
+pub use stats::{stats, AllocStats};
+#[cfg(all(target_os = "linux", feature = "std"))]
+pub use thp::{thp_aligned_vec, thp_touch_stride, try_thp_aligned_vec};
+use trace::AllocSpan;
+pub use vec::{AlignedVec, IntoIter};
 //-----------------------------------------------------------------------------
-/// Return aligned `Vec`. In order for the buffer to be ready for access
+/// Return aligned `AlignedVec`. In order for the buffer to be ready for access
 /// immediately after the function returns all memory pages must have been
 /// "touched" by accessing at least one element in the page.
 /// When the `touch` parameter is not `None` one element per page is initialised
 /// with the passed value.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
 pub fn aligned_vec<T: Copy>(
     size: usize,
     capacity: usize,
     align: usize,
     touch: Option<T>,
-) -> Vec<T> {
-    unsafe {
-        if size == 0 {
-            Vec::<T>::new()
-        } else {
-            let size = size * std::mem::size_of::<T>();
-            let capacity = (capacity * std::mem::size_of::<T>()).max(size);
-
-            let layout = std::alloc::Layout::from_size_align_unchecked(size, align);
-            let raw_ptr = std::alloc::alloc(layout) as *mut T;
-            if let Some(x) = touch {
-                let mut v = Vec::from_raw_parts(raw_ptr, size, capacity);
-                for i in (0..size).step_by(page_size::get()) {
-                    v[i] = x;
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec(size, capacity, align, touch).unwrap_or_else(|e| panic!("aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    let span = AllocSpan::enter(size, align, touch.is_some());
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    if let Some(x) = touch {
+        span.touch(|| touch_pages(v.as_mut_slice(), x));
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Legacy behaviour of [`aligned_vec`], which used to multiply `size` and
+/// `capacity` by `size_of::<T>()`: the buffer has `size * size_of::<T>()`
+/// elements and room for `capacity * size_of::<T>()`, as observed through
+/// [`AlignedVec::len`] and [`AlignedVec::capacity`] before the fix. Unlike
+/// then, the memory holds that many elements so that all of them can be
+/// accessed. Meant for the transition of callers relying on the inflated
+/// values only.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+#[deprecated(note = "`aligned_vec` now takes and returns element counts, pass the \
+                     number of elements to it and drop the byte conversion")]
+pub fn aligned_vec_bytes<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    let s = core::mem::size_of::<T>();
+    let (Some(size), Some(capacity)) = (size.checked_mul(s), capacity.checked_mul(s)) else {
+        panic!("aligned_vec_bytes: {}", AllocError::LayoutOverflow);
+    };
+    try_aligned_vec(size, capacity, align, touch)
+        .unwrap_or_else(|e| panic!("aligned_vec_bytes: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Same as [`aligned_vec`] touching the pages with `touch`, together with the
+/// indices of the pages, counted from the page holding the first element,
+/// whose touched element had a non-zero byte before being written; see
+/// [`touch_pages_report`]. Memory handed out fresh by the OS is all zeros, a
+/// non-empty report points at memory reused by the allocator.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_touch_report<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+) -> (AlignedVec<T>, Vec<usize>) {
+    assert_valid_alignment(align);
+    try_aligned_vec_touch_report(size, capacity, align, touch)
+        .unwrap_or_else(|e| panic!("aligned_vec_touch_report: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_touch_report`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_touch_report<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+) -> Result<(AlignedVec<T>, Vec<usize>), AllocError> {
+    let span = AllocSpan::enter(size, align, true);
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    let report = span.touch(|| touch_pages_report(v.as_mut_slice(), touch));
+    Ok((v, report))
+}
+//-----------------------------------------------------------------------------
+/// Same as [`aligned_vec`] with memory obtained from `backend` instead of the
+/// global allocator, the buffer releases it through the same backend.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_in<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    backend: Backend,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec_in(size, capacity, align, touch, backend)
+        .unwrap_or_else(|e| panic!("aligned_vec_in: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_in`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_in<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    backend: Backend,
+) -> Result<AlignedVec<T>, AllocError> {
+    let span = AllocSpan::enter(size, align, touch.is_some());
+    let mut v = alloc_aligned_vec_in::<T>(size, capacity, align, false, backend)?;
+    if let Some(x) = touch {
+        span.touch(|| touch_pages(v.as_mut_slice(), x));
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Same as [`aligned_vec`] reporting the progress of touching: `progress` is
+/// called with `(pages_done, pages_total)` every `every` pages and once more
+/// when the last page is touched, so that interactive tools can report on
+/// the seconds it takes to fault in very large buffers. `progress` is never
+/// called when `touch` is `None`. An `every` of zero is treated as one.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_with_progress<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    every: usize,
+    progress: impl FnMut(usize, usize),
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec_with_progress(size, capacity, align, touch, every, progress)
+        .unwrap_or_else(|e| panic!("aligned_vec_with_progress: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_with_progress`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_with_progress<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    every: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<AlignedVec<T>, AllocError> {
+    let span = AllocSpan::enter(size, align, touch.is_some());
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    if let Some(x) = touch {
+        let v = v.as_mut_slice();
+        let total = stride_indices(v, os_page_size()).count();
+        let every = every.max(1);
+        span.touch(|| {
+            for (done, i) in (1..).zip(stride_indices(v, os_page_size())) {
+                v[i] = x;
+                #[cfg(all(test, feature = "std"))]
+                TOUCHES.with(|t| t.set(t.get() + 1));
+                if done % every == 0 || done == total {
+                    progress(done, total);
                 }
-                v
-            } else {
-                //SLOW!
-                Vec::from_raw_parts(raw_ptr, size, capacity)
             }
+        });
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an empty `AlignedVec` with room for `capacity` elements aligned to
+/// `align`, the equivalent of `Vec::with_capacity`. Elements are added by
+/// writing through [`AlignedVec::as_mut_ptr`] and reallocation with
+/// [`realloc_aligned`] keeps the alignment.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_with_capacity<T>(capacity: usize, align: usize) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec_with_capacity(capacity, align)
+        .unwrap_or_else(|e| panic!("aligned_vec_with_capacity: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_with_capacity`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_with_capacity<T>(
+    capacity: usize,
+    align: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    alloc_aligned_vec::<T>(0, capacity, align, false)
+}
+//-----------------------------------------------------------------------------
+/// Copy the elements of `v` into an `AlignedVec` holding exactly `v.len()`
+/// elements aligned to `align`, `v` is then released. An empty `v` gives an
+/// empty buffer, no memory is allocated.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn from_vec_aligned<T: Copy>(v: Vec<T>, align: usize) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_from_vec_aligned(v, align).unwrap_or_else(|e| panic!("from_vec_aligned: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`from_vec_aligned`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_from_vec_aligned<T: Copy>(v: Vec<T>, align: usize) -> Result<AlignedVec<T>, AllocError> {
+    let mut a = try_aligned_vec_with_capacity(v.len(), align)?;
+    a.extend_from_slice(&v);
+    Ok(a)
+}
+//-----------------------------------------------------------------------------
+/// Collect the elements of `iter` into an `AlignedVec` aligned to `align`.
+/// Room for the lower bound of the size hint is allocated upfront, the buffer
+/// then grows as with [`AlignedVec::push`], keeping its alignment.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn from_iter_aligned<T, I: IntoIterator<Item = T>>(iter: I, align: usize) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_from_iter_aligned(iter, align).unwrap_or_else(|e| panic!("from_iter_aligned: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`from_iter_aligned`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+/// The elements collected so far are dropped on failure.
+pub fn try_from_iter_aligned<T, I: IntoIterator<Item = T>>(
+    iter: I,
+    align: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let iter = iter.into_iter();
+    let mut v = try_aligned_vec_with_capacity(iter.size_hint().0, align)?;
+    for x in iter {
+        v.try_reserve(1)?;
+        v.push(x);
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Like [`aligned_vec`] but one element every `touch_stride` bytes is
+/// initialised with `touch` instead of one per page. Use it to match the
+/// granularity of the backing memory, e.g. 2 MiB with transparent huge pages.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if `touch_stride` is zero,
+/// if the size in bytes overflows or if allocation fails.
+pub fn strided_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+    touch_stride: usize,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    assert!(touch_stride != 0, "touch stride must be non-zero");
+    try_strided_aligned_vec(size, capacity, align, touch, touch_stride)
+        .unwrap_or_else(|e| panic!("strided_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`strided_aligned_vec`], returns
+/// [`AllocError::InvalidTouchStride`] if `touch_stride` is zero.
+pub fn try_strided_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+    touch_stride: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    if touch_stride == 0 {
+        return Err(AllocError::InvalidTouchStride);
+    }
+    let span = AllocSpan::enter(size, align, true);
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    span.touch(|| touch_strided(v.as_mut_slice(), touch, touch_stride));
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Like [`aligned_vec`] but the allocation is padded to a multiple of the
+/// alignment, returns the buffer and the number of elements it can really hold.
+/// The returned capacity is at least `capacity.max(size)` and is also reported
+/// by [`AlignedVec::capacity`], so that the slack left by the alignment
+/// rounding can be used.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn padded_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> (AlignedVec<T>, usize) {
+    assert_valid_alignment(align);
+    try_padded_aligned_vec(size, capacity, align, touch)
+        .unwrap_or_else(|e| panic!("padded_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`padded_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_padded_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> Result<(AlignedVec<T>, usize), AllocError> {
+    // padding to the effective alignment, not rounded down to `align`
+    let capacity_align = align.max(core::mem::align_of::<T>());
+    let v = try_capacity_aligned_vec(size, capacity, align, capacity_align, touch)?;
+    let capacity = v.capacity();
+    Ok((v, capacity))
+}
+//-----------------------------------------------------------------------------
+/// Like [`aligned_vec`] but the size of the allocation is rounded up to a
+/// multiple of `capacity_align`, independently of the alignment `align` of
+/// its start. Ring buffers mirror-mapped into consecutive pages need e.g. a
+/// 64 byte aligned buffer whose total size is a multiple of the page size. The
+/// size is also a multiple of `size_of::<T>()`, so that the padding holds
+/// whole elements, and the capacity reported by [`AlignedVec::capacity`]
+/// includes it.
+///
+/// # Panics
+///
+/// Panics if `align` or `capacity_align` is not a non-zero power of two, if
+/// the size in bytes overflows or if allocation fails.
+pub fn capacity_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    capacity_align: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    assert_valid_alignment(capacity_align);
+    try_capacity_aligned_vec(size, capacity, align, capacity_align, touch)
+        .unwrap_or_else(|e| panic!("capacity_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`capacity_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` or `capacity_align` is not a
+/// non-zero power of two.
+pub fn try_capacity_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    capacity_align: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    for align in [align, capacity_align] {
+        if !align.is_power_of_two() {
+            return Err(AllocError::InvalidAlignment { align });
+        }
+    }
+    if capacity.max(size) == 0 || core::mem::size_of::<T>() == 0 {
+        return Ok(zero_sized_vec(size, align));
+    }
+    let (layout, _) = aligned_layout::<T>(size, capacity, align)?;
+    // least common multiple of the element size and `capacity_align`, the
+    // layout can then be rebuilt from the capacity
+    let s = core::mem::size_of::<T>();
+    let step = (capacity_align >> capacity_align.trailing_zeros().min(s.trailing_zeros()))
+        .checked_mul(s)
+        .ok_or(AllocError::LayoutOverflow)?;
+    let bytes = layout
+        .size()
+        .checked_next_multiple_of(step)
+        .ok_or(AllocError::LayoutOverflow)?;
+    let layout =
+        Layout::from_size_align(bytes, layout.align()).map_err(|_| AllocError::LayoutOverflow)?;
+    let capacity = layout.size() / s;
+    let span = AllocSpan::enter(size, align, touch.is_some());
+    let backend = Backend::Rust.for_align(layout.align());
+    let ptr = alloc_layout::<T>(layout, false, backend)?;
+    let mut v = unsafe { AlignedVec::from_raw_parts_in(ptr, size, capacity, layout, backend) };
+    if let Some(x) = touch {
+        span.touch(|| touch_pages(v.as_mut_slice(), x));
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` of `elements` elements allocated with exactly
+/// `layout`, e.g. a layout negotiated over FFI. The capacity is the number of
+/// elements the layout holds. When `touch` is not `None` one element per page
+/// is initialised with the passed value.
+///
+/// Returns [`AllocError::IncompatibleLayout`] if `layout` is smaller than
+/// `elements` elements or less aligned than `T`.
+pub fn aligned_vec_from_layout<T: Copy>(
+    layout: Layout,
+    elements: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    let size = core::mem::size_of::<T>();
+    let fits = elements
+        .checked_mul(size)
+        .is_some_and(|bytes| bytes <= layout.size());
+    if !fits || layout.align() < core::mem::align_of::<T>() {
+        return Err(AllocError::IncompatibleLayout { layout, elements });
+    }
+    if layout.size() == 0 {
+        return Ok(zero_sized_vec(elements, layout.align()));
+    }
+    let capacity = match size {
+        0 => elements,
+        s => layout.size() / s,
+    };
+    let span = AllocSpan::enter(elements, layout.align(), touch.is_some());
+    let backend = Backend::Rust.for_align(layout.align());
+    let ptr = alloc_layout::<T>(layout, false, backend)?;
+    let mut v = unsafe { AlignedVec::from_raw_parts_in(ptr, elements, capacity, layout, backend) };
+    if let Some(x) = touch {
+        span.touch(|| touch_pages(v.as_mut_slice(), x));
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Return aligned `AlignedVec` with pages touched concurrently by `threads`
+/// threads, each thread initialises one element per page in its own contiguous
+/// range of pages. Faulting pages in parallel is faster than touching them from
+/// a single thread for very large buffers.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn par_aligned_vec<T: Copy + Send>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    threads: usize,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_par_aligned_vec(size, capacity, align, touch, threads)
+        .unwrap_or_else(|e| panic!("par_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`par_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_par_aligned_vec<T: Copy + Send>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    threads: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let span = AllocSpan::enter(size, align, touch.is_some());
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    if let Some(x) = touch {
+        let stride = page_stride::<T>();
+        let pages = size.div_ceil(stride);
+        // chunks start on a page boundary
+        let chunk = pages.div_ceil(threads.max(1)).max(1) * stride;
+        span.touch(|| {
+            std::thread::scope(|s| {
+                for c in v.as_mut_slice().chunks_mut(chunk) {
+                    s.spawn(move || touch_pages(c, x));
+                }
+            })
+        });
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` aligned to the CPU cache line size as returned by
+/// [`cache_line_size`], with each page optionally "touched".
+///
+/// # Panics
+///
+/// Panics if allocation fails.
+pub fn cache_line_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    try_cache_line_aligned_vec(size, capacity, touch)
+        .unwrap_or_else(|e| panic!("cache_line_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`cache_line_aligned_vec`].
+pub fn try_cache_line_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    try_aligned_vec(size, capacity, cache_line_size(), touch)
+}
+//-----------------------------------------------------------------------------
+/// Allocate a buffer of `size` uninitialised elements which does not cross a
+/// multiple of `boundary`, e.g. a DMA buffer that must stay within a 64 KiB
+/// region. The buffer is aligned to its size rounded up to a power of two,
+/// which divides `boundary`, rather than to `boundary` itself; alignments
+/// above 64 KiB are obtained by over-allocating as with
+/// [`Backend::OverAligned`]. Growing the buffer past its capacity of `size`
+/// elements may make it cross a boundary.
+///
+/// # Panics
+///
+/// Panics if `boundary` is not a non-zero power of two, if the buffer is
+/// larger than `boundary` or if allocation fails.
+pub fn dma_aligned_vec<T: Copy>(size: usize, boundary: usize) -> AlignedVec<T> {
+    assert_valid_alignment(boundary);
+    try_dma_aligned_vec(size, boundary).unwrap_or_else(|e| panic!("dma_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`dma_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `boundary` is not a non-zero power of
+/// two and [`AllocError::BoundaryTooSmall`] if the buffer is larger than
+/// `boundary`.
+pub fn try_dma_aligned_vec<T: Copy>(
+    size: usize,
+    boundary: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    if !boundary.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align: boundary });
+    }
+    let bytes = size
+        .checked_mul(core::mem::size_of::<T>())
+        .ok_or(AllocError::LayoutOverflow)?;
+    if bytes > boundary {
+        return Err(AllocError::BoundaryTooSmall { bytes, boundary });
+    }
+    // an aligned block of at most its alignment never crosses a multiple of
+    // it, nor of `boundary`
+    alloc_aligned_vec::<T>(size, 0, bytes.next_power_of_two(), false)
+}
+//-----------------------------------------------------------------------------
+/// Elements initialised by [`touched_aligned_vec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TouchMode {
+    /// Nothing is written, pages are faulted in on first access.
+    None,
+    /// One element per page, pages are faulted in but little data is cached.
+    PerPage,
+    /// One element per cache line, see [`cache_line_size`]. Pages are faulted
+    /// in and the buffer is pulled into the cache as far as it fits, at the
+    /// cost of a write every cache line instead of every page, i.e. 64 times
+    /// more writes with 4 KiB pages and 64 byte cache lines.
+    PerCacheLine,
+    /// One element per page is read, nothing is written, see
+    /// [`touch_pages_read`]. Fresh anonymous memory is then backed by the
+    /// shared zero page until written to, use it to fault in memory that must
+    /// not be dirtied.
+    ReadOnly,
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` with elements initialised to `x` as selected
+/// by `mode`, [`TouchMode::ReadOnly`] leaves them uninitialised.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn touched_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+    mode: TouchMode,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_touched_aligned_vec(size, capacity, align, x, mode)
+        .unwrap_or_else(|e| panic!("touched_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`touched_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_touched_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+    mode: TouchMode,
+) -> Result<AlignedVec<T>, AllocError> {
+    let span = AllocSpan::enter(size, align, mode != TouchMode::None);
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    match mode {
+        TouchMode::None => {}
+        TouchMode::PerPage => span.touch(|| touch_pages(v.as_mut_slice(), x)),
+        TouchMode::PerCacheLine => {
+            span.touch(|| touch_strided(v.as_mut_slice(), x, cache_line_size()))
         }
+        TouchMode::ReadOnly => span.touch(|| touch_pages_read(v.as_slice())),
     }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an initialized aligned `AlignedVec`.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn init_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_init_aligned_vec(size, capacity, align, x)
+        .unwrap_or_else(|e| panic!("init_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`init_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_init_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+) -> Result<AlignedVec<T>, AllocError> {
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    v.as_mut_slice().fill(x);
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Same as [`init_aligned_vec`] but the elements are initialised concurrently
+/// by `threads` threads, each filling its own contiguous range starting on a
+/// page boundary. Filling huge buffers from several threads uses more of the
+/// available memory bandwidth.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn init_aligned_vec_parallel<T: Copy + Send>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+    threads: usize,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_init_aligned_vec_parallel(size, capacity, align, x, threads)
+        .unwrap_or_else(|e| panic!("init_aligned_vec_parallel: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`init_aligned_vec_parallel`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_init_aligned_vec_parallel<T: Copy + Send>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    x: T,
+    threads: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    let stride = page_stride::<T>();
+    let chunk = size.div_ceil(stride).div_ceil(threads.max(1)).max(1) * stride;
+    std::thread::scope(|s| {
+        // each thread gets its own disjoint range
+        let mut rest = v.as_mut_slice();
+        while !rest.is_empty() {
+            let n = chunk.min(rest.len());
+            let (head, tail) = core::mem::take(&mut rest).split_at_mut(n);
+            s.spawn(move || head.fill(x));
+            rest = tail;
+        }
+    });
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` with each element initialized by calling
+/// `init` with the element index. Unlike the other constructors `T` does not
+/// need to be `Copy`.
+///
+/// If `init` panics the elements already constructed are dropped and the
+/// memory is released.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_with<T, F: FnMut(usize) -> T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    init: F,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec_with(size, capacity, align, init)
+        .unwrap_or_else(|e| panic!("aligned_vec_with: {e}"))
 }
 //-----------------------------------------------------------------------------
-/// Return an initialized aligned `Vec`.
-pub fn init_aligned_vec<T: Copy>(size: usize, capacity: usize, align: usize, x: T) -> Vec<T> {
+/// Fallible version of [`aligned_vec_with`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_with<T, F: FnMut(usize) -> T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    mut init: F,
+) -> Result<AlignedVec<T>, AllocError> {
+    let mut v = alloc_aligned_vec::<T>(size, capacity, align, false)?;
     unsafe {
-        if size == 0 {
-            Vec::<T>::new()
-        } else {
-            let size = size * std::mem::size_of::<T>();
-            let capacity = (capacity * std::mem::size_of::<T>()).max(size);
-
-            let layout = std::alloc::Layout::from_size_align_unchecked(size, align);
-            let raw_ptr = std::alloc::alloc(layout) as *mut T;
-
-            let mut v = Vec::from_raw_parts(raw_ptr, size, capacity);
-            v.fill(x);
-            v
+        // only initialised elements are visible, if `init` panics the
+        // elements written so far are dropped together with `v`
+        v.set_len(0);
+        for i in 0..size {
+            v.as_mut_ptr().add(i).write(init(i));
+            v.set_len(i + 1);
         }
     }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` of `size` elements set to `f(i)`, e.g. a
+/// ramp or a lookup table. Same as [`aligned_vec_with`], under the name used
+/// by the `fill_with` family of functions.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_vec_fill_with<T, F: FnMut(usize) -> T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    f: F,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_aligned_vec_fill_with(size, capacity, align, f)
+        .unwrap_or_else(|e| panic!("aligned_vec_fill_with: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_vec_fill_with`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_vec_fill_with<T, F: FnMut(usize) -> T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    f: F,
+) -> Result<AlignedVec<T>, AllocError> {
+    try_aligned_vec_with(size, capacity, align, f)
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned, fixed-size `AlignedBox<[T]>` of `len` elements, the
+/// allocation holds exactly `len` elements. When `touch` is not `None` one
+/// element per page is initialised with the passed value.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_boxed_slice<T: Copy>(len: usize, align: usize, touch: Option<T>) -> AlignedBox<[T]> {
+    assert_valid_alignment(align);
+    try_aligned_boxed_slice(len, align, touch)
+        .unwrap_or_else(|e| panic!("aligned_boxed_slice: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_boxed_slice`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_boxed_slice<T: Copy>(
+    len: usize,
+    align: usize,
+    touch: Option<T>,
+) -> Result<AlignedBox<[T]>, AllocError> {
+    Ok(try_aligned_vec(len, len, align, touch)?.into_boxed_slice())
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned, uninitialised `AlignedBox<[MaybeUninit<T>]>` of `len`
+/// elements, to be initialised by the caller and converted with
+/// [`AlignedBox::assume_init`]. Nothing is written to the memory.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn aligned_uninit_boxed_slice<T>(len: usize, align: usize) -> AlignedBox<[MaybeUninit<T>]> {
+    assert_valid_alignment(align);
+    try_aligned_uninit_boxed_slice(len, align)
+        .unwrap_or_else(|e| panic!("aligned_uninit_boxed_slice: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`aligned_uninit_boxed_slice`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_aligned_uninit_boxed_slice<T>(
+    len: usize,
+    align: usize,
+) -> Result<AlignedBox<[MaybeUninit<T>]>, AllocError> {
+    // uninitialised elements are valid `MaybeUninit` values
+    Ok(alloc_aligned_vec::<MaybeUninit<T>>(len, len, align, false)?.into_boxed_slice())
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned copy of `src`, with capacity `src.len()`.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn from_slice_aligned<T: Copy>(src: &[T], align: usize) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_from_slice_aligned(src, align).unwrap_or_else(|e| panic!("from_slice_aligned: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`from_slice_aligned`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_from_slice_aligned<T: Copy>(
+    src: &[T],
+    align: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let mut v = alloc_aligned_vec::<T>(src.len(), 0, align, false)?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr(), v.as_mut_ptr(), src.len());
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return a zero-initialized aligned `AlignedVec`. Memory is obtained with
+/// `std::alloc::alloc_zeroed`, which can hand back pages already zeroed by the
+/// OS instead of writing every element.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn zeroed_aligned_vec<T: Copy + Zeroable>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+) -> AlignedVec<T> {
+    assert_valid_alignment(align);
+    try_zeroed_aligned_vec(size, capacity, align)
+        .unwrap_or_else(|e| panic!("zeroed_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`zeroed_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_zeroed_aligned_vec<T: Copy + Zeroable>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    alloc_aligned_vec::<T>(size, capacity, align, true)
 }
 //-----------------------------------------------------------------------------
-/// Return a page aligned `Vec`with each page optionally "touched" by initializing
+#[cfg(feature = "std")]
+/// Return a page aligned `AlignedVec` with each page optionally "touched" by initializing
 /// a single element per page.
+///
+/// Pages locked with `page_locked = true` are not unlocked when the buffer is
+/// dropped, use [`page_locked_vec`] to have them unlocked automatically.
+///
+/// # Panics
+///
+/// Panics if allocation or `mlock` fails.
 pub fn page_aligned_vec<T: Copy>(
     size: usize,
     capacity: usize,
     touch: Option<T>,
     page_locked: bool,
-) -> Vec<T> {
-    let v = aligned_vec::<T>(size, capacity, page_size::get(), touch);
+) -> AlignedVec<T> {
+    try_page_aligned_vec(size, capacity, touch, page_locked)
+        .unwrap_or_else(|e| panic!("page_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`page_aligned_vec`], returns
+/// [`AllocError::Syscall`] if the pages cannot be locked, the buffer is
+/// released in that case.
+pub fn try_page_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+    page_locked: bool,
+) -> Result<AlignedVec<T>, AllocError> {
+    page_aligned_vec_info(size, capacity, touch, page_locked).map(|(v, _)| v)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Same as [`page_aligned_vec`] with the buffer aligned to `align`, a multiple
+/// of the page size, e.g. a huge page boundary. Pages are still touched and
+/// locked at the granularity of the OS page size, only the pages spanned by
+/// the elements are locked.
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of two multiple of the page size or if
+/// allocation or `mlock` fails.
+pub fn page_aligned_vec_with_align<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    page_locked: bool,
+) -> AlignedVec<T> {
+    try_page_aligned_vec_with_align(size, capacity, align, touch, page_locked)
+        .unwrap_or_else(|e| panic!("page_aligned_vec_with_align: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`page_aligned_vec_with_align`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two,
+/// [`AllocError::BelowPageSize`] if it is smaller than the page size and
+/// [`AllocError::Syscall`] if the pages cannot be locked, the buffer is
+/// released in that case.
+pub fn try_page_aligned_vec_with_align<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    page_locked: bool,
+) -> Result<AlignedVec<T>, AllocError> {
+    let page_size = cached_page_size();
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    // powers of two, a multiple unless smaller
+    if align < page_size {
+        return Err(AllocError::BelowPageSize { align, page_size });
+    }
+    let v = try_aligned_vec::<T>(size, capacity, align, touch)?;
     if page_locked {
-        unsafe {
-            nix::sys::mman::mlock(v.as_ptr() as *const std::ffi::c_void, size).unwrap();
-        }
+        lock::lock_pages(&v)?;
     }
-    v
+    Ok(v)
 }
-
-//=============================================================================
-#[cfg(test)]
-mod tests {
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Page granularity a buffer returned by [`page_aligned_vec_info`] was
+/// aligned, touched and locked with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageInfo {
+    /// Page size in bytes detected at allocation.
+    pub page_size: usize,
+    /// Number of pages spanned by the `len` elements of the buffer.
+    pub pages: usize,
+    /// Whether the pages were locked with `mlock`.
+    pub locked: bool,
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Like [`try_page_aligned_vec`] but also return the page size the buffer was
+/// aligned and touched with and the number of pages it spans.
+pub fn page_aligned_vec_info<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+    page_locked: bool,
+) -> Result<(AlignedVec<T>, PageInfo), AllocError> {
+    let page_size = cached_page_size();
+    let v = try_aligned_vec::<T>(size, capacity, page_size, touch)?;
+    if page_locked {
+        lock::lock_pages(&v)?;
+    }
+    let info = PageInfo {
+        page_size,
+        pages: page_count::<T>(v.len()),
+        locked: page_locked,
+    };
+    Ok((v, info))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Return a page aligned, page-locked `PageLockedVec` with each page optionally
+/// "touched" by initializing a single element per page. Pages are unlocked
+/// when the buffer is dropped.
+///
+/// # Panics
+///
+/// Panics if allocation or `mlock` fails, the message names the
+/// `RLIMIT_MEMLOCK` limit most locking failures are caused by.
+pub fn page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> PageLockedVec<T> {
+    try_page_locked_vec(size, capacity, touch).unwrap_or_else(|e| panic!("page_locked_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`page_locked_vec`], returns [`AllocError::Syscall`]
+/// if the pages cannot be locked, the buffer is released in that case.
+pub fn try_page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<PageLockedVec<T>, AllocError> {
+    let v = try_aligned_vec::<T>(size, capacity, cached_page_size(), touch)?;
+    PageLockedVec::new(v, false, false)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Same as [`page_locked_vec`] but pages are locked when first faulted in
+/// rather than immediately, so that untouched pages of a sparse buffer do not
+/// consume physical memory. Uses `mlock2(MLOCK_ONFAULT)` on Linux 4.4 and
+/// later, elsewhere all the pages are locked immediately.
+///
+/// # Panics
+///
+/// Panics if allocation or locking fails.
+pub fn page_locked_on_fault_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> PageLockedVec<T> {
+    try_page_locked_on_fault_vec(size, capacity, touch)
+        .unwrap_or_else(|e| panic!("page_locked_on_fault_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`page_locked_on_fault_vec`], returns
+/// [`AllocError::Syscall`] if the pages cannot be locked.
+pub fn try_page_locked_on_fault_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<PageLockedVec<T>, AllocError> {
+    let v = try_aligned_vec::<T>(size, capacity, cached_page_size(), None)?;
+    let mut v = PageLockedVec::new(v, false, true)?;
+    // touched pages are faulted in and locked
+    if let Some(x) = touch {
+        touch_pages(v.as_mut_slice(), x);
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+#[cfg(all(unix, feature = "std"))]
+/// Same as [`page_locked_vec`] but the size of the buffer is first checked
+/// against the soft `RLIMIT_MEMLOCK` limit returned by [`memlock_limit`], so
+/// that oversized requests fail before anything is allocated or touched.
+///
+/// Memory already locked by the process is not accounted for, and privileged
+/// processes that may lock beyond the limit are rejected as well.
+///
+/// # Panics
+///
+/// Panics if the limit is exceeded or if allocation or `mlock` fails.
+pub fn checked_page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> PageLockedVec<T> {
+    try_checked_page_locked_vec(size, capacity, touch)
+        .unwrap_or_else(|e| panic!("checked_page_locked_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(all(unix, feature = "std"))]
+/// Fallible version of [`checked_page_locked_vec`], returns
+/// [`AllocError::LockLimitExceeded`] if the limit is exceeded and
+/// [`AllocError::Syscall`] if the pages cannot be locked.
+pub fn try_checked_page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<PageLockedVec<T>, AllocError> {
+    lock::check_memlock_limit::<T>(size)?;
+    try_page_locked_vec(size, capacity, touch)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Same as [`page_locked_vec`] but the whole allocation, spare capacity
+/// included, is overwritten with zeros before being unlocked and released.
+/// Use it for buffers holding secrets that must never be swapped out or left in
+/// freed memory.
+///
+/// # Panics
+///
+/// Panics if allocation or `mlock` fails.
+pub fn secure_page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> PageLockedVec<T> {
+    try_secure_page_locked_vec(size, capacity, touch)
+        .unwrap_or_else(|e| panic!("secure_page_locked_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`secure_page_locked_vec`], returns
+/// [`AllocError::Syscall`] if the pages cannot be locked.
+pub fn try_secure_page_locked_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<PageLockedVec<T>, AllocError> {
+    let v = try_aligned_vec::<T>(size, capacity, cached_page_size(), touch)?;
+    PageLockedVec::new(v, true, false)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Return a zeroed, page-locked buffer of `len` elements aligned to
+/// `align.max(page_size)`, so that its first page is not shared with other
+/// allocations. As with [`secure_page_locked_vec`] the whole allocation is
+/// overwritten with zeros before being unlocked and released; locking faults
+/// in all the pages.
+///
+/// Returns [`AllocError::InvalidAlignment`] if `align` is not a non-zero power
+/// of two and [`AllocError::Syscall`] if the pages cannot be locked, the
+/// buffer is released in that case.
+pub fn secure_buffer<T: Zeroable>(len: usize, align: usize) -> Result<SecureBuffer<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    let v = alloc_aligned_vec::<T>(len, 0, align.max(cached_page_size()), true)?;
+    PageLockedVec::new(v, true, false)
+}
+//-----------------------------------------------------------------------------
+/// Resize `v` into a buffer with the same alignment and backend holding
+/// `new_size` elements with room for `new_capacity.max(new_size)` elements.
+/// The allocation is resized in place if the allocator can, otherwise the
+/// elements are moved into a new allocation.
+///
+/// Elements past the length of `v` are uninitialised, elements past `new_size`
+/// are discarded. The memory of `v` is released with the layout it was
+/// allocated with, also when allocation of the new buffer fails.
+pub fn realloc_aligned<T: Copy>(
+    v: AlignedVec<T>,
+    new_size: usize,
+    new_capacity: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let (layout, capacity) = aligned_layout::<T>(new_size, new_capacity, v.alignment())?;
+    if v.layout().size() != 0 && layout.size() != 0 {
+        let (old, backend) = (v.layout(), v.backend());
+        let (ptr, _, _) = v.into_raw_parts();
+        let p = unsafe { backend.realloc(ptr as *mut u8, old, layout.size()) };
+        let Some(p) = core::ptr::NonNull::new(p as *mut T) else {
+            unsafe { backend.dealloc(ptr as *mut u8, old) };
+            return Err(AllocError::OutOfMemory { layout });
+        };
+        return Ok(unsafe {
+            AlignedVec::from_raw_parts_in(p, new_size, capacity, layout, backend)
+        });
+    }
+    let mut n = alloc_aligned_vec_in::<T>(
+        new_size,
+        new_capacity,
+        v.layout().align(),
+        false,
+        v.backend(),
+    )?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(v.as_ptr(), n.as_mut_ptr(), v.len().min(new_size));
+    }
+    Ok(n)
+}
+//-----------------------------------------------------------------------------
+/// Release the spare capacity of `v` by moving its elements into a new buffer
+/// with the same alignment and backend holding exactly `v.len()` elements.
+/// `v` is returned unchanged if it has no spare capacity.
+///
+/// The memory of `v` is released, also when allocation of the new buffer
+/// fails.
+pub fn shrink_aligned<T>(mut v: AlignedVec<T>) -> Result<AlignedVec<T>, AllocError> {
+    if v.len() == v.capacity() {
+        return Ok(v);
+    }
+    let mut n = alloc_aligned_vec_in::<T>(v.len(), 0, v.alignment(), false, v.backend())?;
+    unsafe {
+        core::ptr::copy_nonoverlapping(v.as_ptr(), n.as_mut_ptr(), v.len());
+        // elements are owned by `n` now
+        v.set_len(0);
+    }
+    Ok(n)
+}
+//-----------------------------------------------------------------------------
+/// Release memory allocated by the constructors with the global allocator,
+/// the counterpart of taking a buffer apart with
+/// [`AlignedVec::into_raw_parts`]. The layout is rebuilt from the element
+/// counts and the alignment as done when allocating. Elements are not dropped.
+///
+/// # Safety
+///
+/// `ptr` must have been returned by [`AlignedVec::into_raw_parts`] for a
+/// buffer allocated by [`Backend::Rust`] together with `size` and `capacity`,
+/// and `align` must be the [`AlignedVec::alignment`] of that buffer. The
+/// allocation must be `capacity * size_of::<T>()` bytes long, which does not
+/// hold for buffers from [`aligned_vec_from_layout`] with a layout that is
+/// not a whole number of elements.
+pub unsafe fn dealloc_aligned<T>(ptr: *mut T, size: usize, capacity: usize, align: usize) {
+    if capacity.max(size) == 0 {
+        return;
+    }
+    let (layout, _) = aligned_layout::<T>(size, capacity, align).expect("dealloc_aligned: layout");
+    if layout.size() != 0 {
+        Backend::Rust
+            .for_align(layout.align())
+            .dealloc(ptr as *mut u8, layout);
+    }
+}
+//-----------------------------------------------------------------------------
+/// Allocate aligned buffer of `size` elements with room for
+/// `capacity.max(size)` elements, memory is uninitialised unless `zeroed` is
+/// `true`.
+fn alloc_aligned_vec<T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    zeroed: bool,
+) -> Result<AlignedVec<T>, AllocError> {
+    alloc_aligned_vec_in(size, capacity, align, zeroed, Backend::Rust)
+}
+//-----------------------------------------------------------------------------
+/// Same as [`alloc_aligned_vec`] with memory obtained from `backend`.
+fn alloc_aligned_vec_in<T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    zeroed: bool,
+    backend: Backend,
+) -> Result<AlignedVec<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    if capacity.max(size) == 0 || core::mem::size_of::<T>() == 0 {
+        return Ok(zero_sized_vec(size, align));
+    }
+    let (layout, capacity) = aligned_layout::<T>(size, capacity, align)?;
+    if backend == Backend::Rust && layout.align() == core::mem::align_of::<T>() && !zeroed {
+        return std_vec_aligned(size, capacity, layout);
+    }
+    let backend = backend.for_align(layout.align());
+    let ptr = alloc_layout::<T>(layout, zeroed, backend)?;
+    Ok(unsafe { AlignedVec::from_raw_parts_in(ptr, size, capacity, layout, backend) })
+}
+//-----------------------------------------------------------------------------
+/// Fast path of [`alloc_aligned_vec_in`] when no alignment beyond that of `T`
+/// is requested: the memory of a `Vec<T>` with room for `capacity` elements,
+/// the buffer can be converted back with
+/// [`AlignedVec::try_into_std_vec_zero_copy`].
+fn std_vec_aligned<T>(
+    size: usize,
+    capacity: usize,
+    layout: Layout,
+) -> Result<AlignedVec<T>, AllocError> {
+    let mut v = Vec::<T>::new();
+    v.try_reserve_exact(capacity)
+        .map_err(|_| AllocError::OutOfMemory { layout })?;
+    let mut v = AlignedVec::from_std_vec(v);
+    // uninitialised as with the other backends
+    unsafe { v.set_len(size) };
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return a buffer of `size` elements without allocating, `T` must be
+/// zero-sized or `size` zero. The elements of zero-sized types occupy no
+/// memory and need no initialisation.
+fn zero_sized_vec<T>(size: usize, align: usize) -> AlignedVec<T> {
+    let mut v = AlignedVec::empty(align);
+    // the capacity of zero-sized types is unbounded
+    unsafe { v.set_len(size) };
+    v
+}
+//-----------------------------------------------------------------------------
+/// Allocate memory for `layout` with `backend`, `layout` must have a non-zero
+/// size.
+fn alloc_layout<T>(
+    layout: Layout,
+    zeroed: bool,
+    backend: Backend,
+) -> Result<core::ptr::NonNull<T>, AllocError> {
+    let raw_ptr = unsafe { backend.alloc(layout, zeroed) } as *mut T;
+    core::ptr::NonNull::new(raw_ptr).ok_or(AllocError::OutOfMemory { layout })
+}
+//-----------------------------------------------------------------------------
+/// Return the layout used by the constructors to allocate `size` elements with
+/// room for `capacity` elements aligned to `align.max(align_of::<T>())`,
+/// together with the element capacity `capacity.max(size)` the layout can
+/// hold.
+///
+/// Returns [`AllocError::InvalidAlignment`] if `align` is not a non-zero power
+/// of two and [`AllocError::LayoutOverflow`] if the byte size overflows.
+pub fn aligned_layout<T>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+) -> Result<(Layout, usize), AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    // elements must stay aligned to their own requirement
+    let align = align.max(core::mem::align_of::<T>());
+    // element counts are handed to `AlignedVec`, byte counts to `Layout`
+    let capacity = capacity.max(size);
+    let capacity_bytes = capacity
+        .checked_mul(core::mem::size_of::<T>())
+        .ok_or(AllocError::LayoutOverflow)?;
+    let layout =
+        Layout::from_size_align(capacity_bytes, align).map_err(|_| AllocError::LayoutOverflow)?;
+    Ok((layout, capacity))
+}
+//-----------------------------------------------------------------------------
+/// Return the number of bytes the constructors request from the allocator for
+/// `size` elements with room for `capacity` elements aligned to `align`, the
+/// size of the layout returned by [`aligned_layout`]. Useful to plan memory
+/// budgets before allocating; [`padded_aligned_vec`] rounds this size up to a
+/// multiple of the alignment.
+///
+/// Returns [`AllocError::InvalidAlignment`] if `align` is not a non-zero power
+/// of two and [`AllocError::LayoutOverflow`] if the byte size overflows.
+pub fn reserved_bytes<T>(size: usize, capacity: usize, align: usize) -> Result<usize, AllocError> {
+    aligned_layout::<T>(size, capacity, align).map(|(layout, _)| layout.size())
+}
+//-----------------------------------------------------------------------------
+/// Return `true` if the first element of `slice` is at an address multiple of
+/// `align`, e.g. to check a slice received from other code before using
+/// aligned SIMD loads on it. The address of an empty slice is checked too.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two.
+pub fn is_aligned_to<T>(slice: &[T], align: usize) -> bool {
+    assert_valid_alignment(align);
+    (slice.as_ptr() as usize).is_multiple_of(align)
+}
+//-----------------------------------------------------------------------------
+/// Return the number of elements to skip from the beginning of `slice` for
+/// the remaining ones to start at an address multiple of `align`, zero if the
+/// slice is already aligned. The offset may exceed the length of the slice,
+/// it is `usize::MAX` if no element boundary is aligned, e.g. for `[u8; 2]`
+/// elements at an odd address.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two.
+pub fn align_offset_elems<T>(slice: &[T], align: usize) -> usize {
+    assert_valid_alignment(align);
+    // exact outside of const evaluation
+    slice.as_ptr().align_offset(align)
+}
+//-----------------------------------------------------------------------------
+/// Return `value` rounded up to the next multiple of `align`, e.g. the offset
+/// of a buffer placed after `value` bytes in a combined allocation. Usable in
+/// `const` contexts to size such allocations at compile time.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two or if the result
+/// overflows, at compile time in `const` contexts.
+pub const fn align_up(value: usize, align: usize) -> usize {
+    assert!(
+        align.is_power_of_two(),
+        "alignment must be a non-zero power of two"
+    );
+    match value.checked_next_multiple_of(align) {
+        Some(v) => v,
+        None => panic!("align_up: result overflows"),
+    }
+}
+//-----------------------------------------------------------------------------
+/// Return the number of bytes to add to `value` to reach a multiple of
+/// `align`, zero if it is one already. Never overflows, unlike [`align_up`].
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two.
+pub const fn padding_needed(value: usize, align: usize) -> usize {
+    assert!(
+        align.is_power_of_two(),
+        "alignment must be a non-zero power of two"
+    );
+    value.wrapping_neg() & (align - 1)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// System page size, queried once and cached: on some platforms
+/// `page_size::get` is a system call. The constructors and the touch loops
+/// all use this value.
+pub fn cached_page_size() -> usize {
+    static PAGE_SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *PAGE_SIZE.get_or_init(page_size::get)
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Return the number of pages spanned by `size` elements starting on a page
+/// boundary, the number of pages the constructors touch and lock for a page
+/// aligned buffer, e.g. to budget `RLIMIT_MEMLOCK` or size the progress
+/// reports of [`aligned_vec_with_progress`] before allocating. A buffer not
+/// starting on a page boundary may span one more page.
+pub fn page_count<T>(size: usize) -> usize {
+    size.saturating_mul(core::mem::size_of::<T>())
+        .div_ceil(os_page_size())
+}
+//-----------------------------------------------------------------------------
+/// Page size used as the touch stride, overridden in tests with
+/// [`with_page_size`].
+#[cfg(feature = "std")]
+fn os_page_size() -> usize {
+    #[cfg(test)]
+    if let Some(ps) = PAGE_SIZE_OVERRIDE.with(|p| p.get()) {
+        return ps;
+    }
+    cached_page_size()
+}
+#[cfg(all(test, feature = "std"))]
+std::thread_local! {
+    // touch stride replacing the page size on this thread
+    static PAGE_SIZE_OVERRIDE: core::cell::Cell<Option<usize>> = const { core::cell::Cell::new(None) };
+}
+//-----------------------------------------------------------------------------
+/// Run `f` with the touch stride of the current thread set to `ps` bytes.
+#[cfg(all(test, feature = "std"))]
+fn with_page_size<R>(ps: usize, f: impl FnOnce() -> R) -> R {
+    let prev = PAGE_SIZE_OVERRIDE.with(|p| p.replace(Some(ps)));
+    let r = f();
+    PAGE_SIZE_OVERRIDE.with(|p| p.set(prev));
+    r
+}
+/// Without `std` a 4 KiB page is assumed.
+#[cfg(not(feature = "std"))]
+fn os_page_size() -> usize {
+    4096
+}
+//-----------------------------------------------------------------------------
+/// Number of elements per page, at least one.
+#[cfg(feature = "std")]
+fn page_stride<T>() -> usize {
+    (os_page_size() / core::mem::size_of::<T>().max(1)).max(1)
+}
+//-----------------------------------------------------------------------------
+/// Fault in every page spanned by the elements of `v` without changing its
+/// contents: one element per page is read and written back with volatile
+/// accesses, so that the stores are not optimised away. Pages that only
+/// partially hold elements, at either end of the slice, are faulted in too.
+///
+/// Use it to commit memory whose existing contents matter, e.g. zeroed
+/// buffers, where touching with a value is not an option.
+pub fn force_commit<T: Copy>(v: &mut [T]) {
+    let p = v.as_mut_ptr();
+    for i in stride_indices(v, os_page_size()) {
+        unsafe { p.add(i).write_volatile(p.add(i).read_volatile()) };
+    }
+}
+//-----------------------------------------------------------------------------
+/// Fault in every page spanned by the elements of `v` by reading one byte per
+/// page with volatile loads, which cannot be optimised away, and writing
+/// nothing. Use it on read-mostly file or copy-on-write mappings, e.g. a
+/// mapped file wrapped with [`adopt_aligned`], where touching with a write
+/// would dirty or copy every page. The bytes are read as `MaybeUninit`, `v` may hold
+/// uninitialised elements.
+pub fn touch_pages_read<T>(v: &[T]) {
+    let p = v.as_ptr();
+    for i in stride_indices(v, os_page_size()) {
+        unsafe { (p.add(i) as *const core::mem::MaybeUninit<u8>).read_volatile() };
+    }
+}
+//-----------------------------------------------------------------------------
+/// Write `x` to one element per page as the constructors do when touching
+/// pages and return the indices of the pages, counted from the page holding
+/// the first element, where the element had a non-zero byte before being
+/// overwritten. A diagnostic for uninitialised memory hunts: the bytes are
+/// read whatever they hold, padding included, by inline assembly on x86-64
+/// and AArch64 since Rust code may not inspect uninitialised bytes. On other
+/// targets and under Miri nothing is read and no page is reported.
+pub fn touch_pages_report<T: Copy>(v: &mut [T], x: T) -> Vec<usize> {
+    let mut report = Vec::new();
+    for (page, i) in stride_indices(v, os_page_size()).enumerate() {
+        let p = v.as_mut_ptr().wrapping_add(i) as *const MaybeUninit<u8>;
+        let dirty = (0..core::mem::size_of::<T>())
+            .any(|b| unsafe { read_raw_byte(p.add(b)) }.is_some_and(|b| b != 0));
+        if dirty {
+            report.push(page);
+        }
+        v[i] = x;
+    }
+    report
+}
+//-----------------------------------------------------------------------------
+/// Return the byte at `p`, initialised or not, read by an assembly load whose
+/// result is an arbitrary but initialised value for the compiler. `None`
+/// where no such load is implemented.
+///
+/// # Safety
+///
+/// `p` must be valid for reads.
+#[cfg(all(target_arch = "x86_64", not(miri)))]
+unsafe fn read_raw_byte(p: *const MaybeUninit<u8>) -> Option<u8> {
+    let b: u8;
+    core::arch::asm!(
+        "mov {b}, byte ptr [{p}]",
+        p = in(reg) p,
+        b = out(reg_byte) b,
+        options(nostack, readonly, preserves_flags)
+    );
+    Some(b)
+}
+#[cfg(all(target_arch = "aarch64", not(miri)))]
+unsafe fn read_raw_byte(p: *const MaybeUninit<u8>) -> Option<u8> {
+    let b: u32;
+    core::arch::asm!(
+        "ldrb {b:w}, [{p}]",
+        p = in(reg) p,
+        b = out(reg) b,
+        options(nostack, readonly, preserves_flags)
+    );
+    Some(b as u8)
+}
+#[cfg(any(miri, not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+unsafe fn read_raw_byte(_: *const MaybeUninit<u8>) -> Option<u8> {
+    None
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Allocate `size` elements aligned to `align` without touching them, time a
+/// pass touching every page with zeros as the constructors do and release the
+/// buffer. A diagnostic of the cost of first-touch page faults on the system,
+/// to decide whether prefaulting is worth it. The pages of a buffer reusing
+/// memory freed earlier may already be faulted in, multi-megabyte buffers are
+/// usually fresh mappings.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn measure_first_touch<T: Copy + Zeroable>(size: usize, align: usize) -> std::time::Duration {
+    assert_valid_alignment(align);
+    try_measure_first_touch::<T>(size, align).unwrap_or_else(|e| panic!("measure_first_touch: {e}"))
+}
+//-----------------------------------------------------------------------------
+#[cfg(feature = "std")]
+/// Fallible version of [`measure_first_touch`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_measure_first_touch<T: Copy + Zeroable>(
+    size: usize,
+    align: usize,
+) -> Result<std::time::Duration, AllocError> {
+    let mut v = alloc_aligned_vec::<T>(size, 0, align, false)?;
+    let start = std::time::Instant::now();
+    touch_pages(v.as_mut_slice(), T::zeroed());
+    Ok(start.elapsed())
+}
+//-----------------------------------------------------------------------------
+/// Write `x` to one element per page, the pages only partially holding
+/// elements at either end of `v` included.
+fn touch_pages<T: Copy>(v: &mut [T], x: T) {
+    touch_strided(v, x, os_page_size());
+}
+//-----------------------------------------------------------------------------
+/// Pages written while touching a buffer, returned by
+/// [`AlignedVecBuilder::build_with_report`] to correlate construction time
+/// with page fault counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TouchReport {
+    /// Number of pages an element was written to.
+    pub pages: usize,
+    /// Bytes of the buffer in those pages: `pages` times the page size,
+    /// clamped to the size of the elements.
+    pub bytes: usize,
+}
+//-----------------------------------------------------------------------------
+/// Same as [`touch_pages`], returning the pages written to.
+fn touch_pages_counted<T: Copy>(v: &mut [T], x: T) -> TouchReport {
+    let ps = os_page_size();
+    let mut pages = 0;
+    for i in stride_indices(v, ps) {
+        v[i] = x;
+        pages += 1;
+        #[cfg(all(test, feature = "std"))]
+        TOUCHES.with(|t| t.set(t.get() + 1));
+    }
+    TouchReport {
+        pages,
+        bytes: pages.saturating_mul(ps).min(core::mem::size_of_val(v)),
+    }
+}
+//-----------------------------------------------------------------------------
+/// Write `x` to one element every `stride` bytes as returned by
+/// [`stride_indices`], to every element if `T` is larger than `stride`.
+fn touch_strided<T: Copy>(v: &mut [T], x: T, stride: usize) {
+    for i in stride_indices(v, stride) {
+        v[i] = x;
+        #[cfg(all(test, feature = "std"))]
+        TOUCHES.with(|t| t.set(t.get() + 1));
+    }
+}
+//-----------------------------------------------------------------------------
+/// Indices, in increasing order and without repetitions, of the element
+/// holding the first byte of `v` and of the elements holding an address
+/// multiple of `stride` within `v`. With `stride` equal to the page size
+/// these are one element in each page spanned by `v`, whether or not `v`
+/// starts on a page boundary and down to the page holding the last element.
+/// Nothing is returned for zero-sized types, which occupy no memory.
+///
+/// `stride` must be non-zero.
+fn stride_indices<T>(v: &[T], stride: usize) -> impl Iterator<Item = usize> {
+    let size = core::mem::size_of::<T>();
+    let bytes = core::mem::size_of_val(v);
+    let start = v.as_ptr() as usize;
+    // offsets are relative to `v`, there is no address arithmetic to overflow
+    let first = stride - start % stride;
+    // boundaries are counted rather than stepped over, so that no offset past
+    // `bytes` is ever computed, even for buffers close to the size of the
+    // address space on 32-bit targets
+    let boundaries = match bytes.checked_sub(first) {
+        Some(n) if n != 0 => (n - 1) / stride + 1,
+        _ => 0,
+    };
+    let mut last = None;
+    // the first offset is skipped for empty slices and zero-sized types
+    let head = usize::from(bytes != 0);
+    core::iter::once(0)
+        .take(head)
+        .chain((0..boundaries).map(move |k| {
+            k.checked_mul(stride)
+                .and_then(|o| o.checked_add(first))
+                .filter(|&o| o < bytes)
+                .expect("stride_indices: offset out of bounds")
+        }))
+        .map(move |offset| offset / size)
+        .filter(move |&i| last.replace(i) != Some(i))
+}
+#[cfg(all(test, feature = "std"))]
+std::thread_local! {
+    // number of elements written by `touch_strided` on this thread
+    static TOUCHES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+//-----------------------------------------------------------------------------
+/// Number of elements touched by the current thread.
+#[cfg(all(test, feature = "std"))]
+fn touch_count() -> usize {
+    TOUCHES.with(|t| t.get())
+}
+//-----------------------------------------------------------------------------
+/// Panic with a descriptive message if `align` is not a valid alignment.
+fn assert_valid_alignment(align: usize) {
+    assert!(
+        align.is_power_of_two(),
+        "alignment must be a non-zero power of two, got {align}"
+    );
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
     use super::*;
     #[test]
     fn page_alignedi_test() {
@@ -102,4 +1813,846 @@ mod tests {
         assert_eq!(v.capacity(), capacity);
         assert_eq!(v[ps], init_value);
     }
+    #[test]
+    fn aligned_vec_drop_test() {
+        // run under Miri to check that memory is released with the
+        // allocation layout
+        let align = 64;
+        let v = init_aligned_vec::<u8>(256, 512, align, 7);
+        assert_eq!(v.as_ptr() as usize % align, 0);
+        assert!(v.as_slice().iter().all(|&x| x == 7));
+        drop(v);
+        let v = aligned_vec::<u8>(256, 256, align, None);
+        assert_eq!(v.as_ptr() as usize % align, 0);
+    }
+    #[test]
+    #[allow(deprecated)]
+    fn aligned_vec_bytes_test() {
+        // legacy counts multiplied by the element size, corrected ones not
+        let v = aligned_vec_bytes::<u64>(100, 150, 64, Some(3));
+        assert_eq!((v.len(), v.capacity()), (800, 1200));
+        assert!(v.is_aligned_to(64));
+        assert_eq!(v.allocated_bytes(), 1200 * 8);
+        assert_eq!(v[0], 3);
+        let v = aligned_vec::<u64>(100, 150, 64, Some(3));
+        assert_eq!((v.len(), v.capacity()), (100, 150));
+        assert_eq!(v.allocated_bytes(), 150 * 8);
+        // same results for bytes
+        let v = aligned_vec_bytes::<u8>(100, 0, 64, None);
+        assert_eq!((v.len(), v.capacity()), (100, 100));
+        assert!(aligned_vec_bytes::<()>(10, 0, 1, None).is_empty());
+        let r = std::panic::catch_unwind(|| aligned_vec_bytes::<u64>(usize::MAX / 4, 0, 64, None));
+        assert!(r.is_err());
+    }
+    #[test]
+    fn element_count_test() {
+        let size = 1000;
+        let capacity = 1500;
+        let mut v = aligned_vec::<u64>(size, capacity, 64, Some(3));
+        assert_eq!(v.len(), size);
+        assert_eq!(v.capacity(), capacity);
+        v[size - 1] = 5;
+        assert_eq!(v[size - 1], 5);
+        assert_eq!(v[0], 3);
+        let mut v = init_aligned_vec::<[u8; 7]>(size, 0, 16, [1; 7]);
+        assert_eq!(v.len(), size);
+        assert_eq!(v.capacity(), size);
+        assert_eq!(v[size - 1], [1; 7]);
+        v[size - 1] = [2; 7];
+        assert_eq!(v[size - 1], [2; 7]);
+    }
+    #[test]
+    fn out_of_memory_test() {
+        let r = try_aligned_vec::<u8>(usize::MAX / 4, 0, 64, None);
+        assert!(matches!(r, Err(AllocError::OutOfMemory { .. })));
+        let r = try_init_aligned_vec::<u8>(usize::MAX / 4, 0, 64, 0);
+        assert!(matches!(r, Err(AllocError::OutOfMemory { .. })));
+    }
+    #[test]
+    fn invalid_alignment_test() {
+        let r = try_aligned_vec::<u8>(16, 16, 0, None);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 0 })));
+        let r = try_init_aligned_vec::<u8>(16, 16, 3, 0);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+        let v = try_aligned_vec::<u8>(16, 16, 64, None).unwrap();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+    }
+    #[test]
+    #[should_panic(expected = "alignment must be a non-zero power of two")]
+    fn invalid_alignment_panic_test() {
+        aligned_vec::<u8>(16, 16, 24, None);
+    }
+    #[test]
+    fn zeroed_aligned_vec_test() {
+        let size = 4 * page_size::get();
+        let v = zeroed_aligned_vec::<u32>(size, size, 64);
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v.len(), size);
+        assert!(v.as_slice().iter().all(|&x| x == 0));
+    }
+    #[test]
+    fn page_locked_vec_test() {
+        // total locked memory is well above the default memlock limit, it
+        // only works if every buffer is unlocked on drop
+        let ps = page_size::get();
+        for i in 0..256 {
+            let mut v = page_locked_vec::<u8>(64 * ps, 0, Some(1));
+            assert_eq!(v.as_ptr() as usize % ps, 0);
+            assert_eq!(v.locked_bytes(), 64 * ps);
+            assert_eq!(v[ps], 1);
+            v[0] = i as u8;
+        }
+    }
+    #[test]
+    fn page_locked_bytes_test() {
+        // 10 pages and a half worth of u64 elements: the whole range including
+        // the last partial page must be locked
+        let ps = page_size::get();
+        let size = (10 * ps + ps / 2) / std::mem::size_of::<u64>();
+        let v = page_locked_vec::<u64>(size, 0, Some(1));
+        assert_eq!(v.locked_bytes(), 11 * ps);
+        assert_eq!(v[0], 1);
+        let v = page_aligned_vec::<u64>(size, 0, Some(1), true);
+        assert_eq!(v.len(), size);
+        unsafe {
+            lock::sys::unlock(v.as_ptr() as *const std::ffi::c_void, 11 * ps).unwrap();
+        }
+    }
+    #[test]
+    fn page_aligned_vec_with_align_test() {
+        let ps = page_size::get();
+        let align = 2 << 20;
+        let v = page_aligned_vec_with_align::<u64>(4 * ps / 8 + 1, 0, align, Some(1), true);
+        assert!(v.is_aligned_to(align));
+        assert_eq!(v[ps / 8], 1);
+        #[cfg(unix)]
+        assert_eq!(resident_pages(&v), 5);
+        // the elements span 5 pages, all of which were locked
+        unsafe {
+            lock::sys::unlock(v.as_ptr() as *const std::ffi::c_void, 5 * ps).unwrap();
+        }
+        let r = try_page_aligned_vec_with_align::<u8>(1, 0, ps / 2, None, false);
+        assert!(matches!(r, Err(AllocError::BelowPageSize { .. })));
+        let r = try_page_aligned_vec_with_align::<u8>(1, 0, 3 * ps, None, false);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { .. })));
+    }
+    #[test]
+    fn par_aligned_vec_test() {
+        let ps = page_size::get();
+        let stride = ps / std::mem::size_of::<u32>();
+        // number of pages is not a multiple of the number of threads and the
+        // last page is partial
+        let size = 13 * stride + 5;
+        let v = par_aligned_vec::<u32>(size, 0, ps, Some(9), 4);
+        assert_eq!(v.len(), size);
+        for i in (0..size).step_by(stride) {
+            assert_eq!(v[i], 9);
+        }
+        let v = par_aligned_vec::<u32>(3, 0, ps, Some(9), 8);
+        assert_eq!(v[0], 9);
+    }
+    #[test]
+    fn aligned_vec_with_test() {
+        let v = aligned_vec_with(100, 0, 64, |i| i.to_string());
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v.len(), 100);
+        assert_eq!(v[42], "42");
+        // elements constructed before the panic are dropped
+        let counter = std::rc::Rc::new(());
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            aligned_vec_with(10, 0, 64, |i| {
+                assert!(i < 5);
+                counter.clone()
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(std::rc::Rc::strong_count(&counter), 1);
+    }
+    #[test]
+    fn aligned_vec_fill_with_test() {
+        let v = aligned_vec_fill_with(1000, 0, 128, |i| i * i);
+        assert_eq!(v.as_ptr() as usize % 128, 0);
+        assert_eq!(v.len(), 1000);
+        assert!(v.iter().enumerate().all(|(i, &x)| x == i * i));
+        let r = try_aligned_vec_fill_with(1, 0, 3, |i| i);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+    }
+    #[cfg(unix)]
+    #[test]
+    fn touch_pages_read_test() {
+        let ps = page_size::get();
+        let len = 16 * ps;
+        // fresh private mapping, read faults map the shared zero page
+        let v = unsafe {
+            let p = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(p, libc::MAP_FAILED);
+            adopt_aligned(p as *mut u8, len, len, ps, move |p, _| {
+                libc::munmap(p as *mut libc::c_void, len);
+            })
+        };
+        assert_eq!(resident_pages(&v), 0);
+        let touches = touch_count();
+        touch_pages_read(&v[..]);
+        assert_eq!(touch_count(), touches);
+        assert_eq!(resident_pages(&v), 16);
+        assert!(v.iter().all(|&x| x == 0));
+        let v = touched_aligned_vec::<u64>(1000, 0, 64, 1, TouchMode::ReadOnly);
+        assert_eq!(v.len(), 1000);
+    }
+    #[test]
+    fn zero_capacity_test() {
+        // the capacity is raised to the size in elements, the layout is in
+        // bytes
+        let mut v = aligned_vec::<u64>(10, 0, 64, None);
+        assert_eq!(v.capacity(), 10);
+        assert_eq!(v.layout().size(), 80);
+        v.as_mut_slice().fill(u64::MAX);
+        v[9] = 9;
+        assert_eq!(v.iter().filter(|&&x| x == u64::MAX).count(), 9);
+        assert!(v.spare_capacity_mut().is_empty());
+        drop(v);
+        let v = init_aligned_vec::<u64>(10, 0, 4096, 3);
+        assert_eq!((v.len(), v.capacity()), (10, 10));
+        let v = page_aligned_vec::<u64>(10, 0, Some(1), false);
+        assert_eq!(v.capacity(), 10);
+    }
+    #[test]
+    fn slice_alignment_test() {
+        let v = init_aligned_vec::<u32>(64, 0, 64, 1);
+        assert!(is_aligned_to(&v, 64));
+        assert_eq!(align_offset_elems(&v, 64), 0);
+        // 4 bytes short of the next boundary
+        let s = &v[15..];
+        assert!(is_aligned_to(s, 4));
+        assert!(!is_aligned_to(s, 64));
+        assert_eq!(align_offset_elems(s, 64), 1);
+        assert_eq!(align_offset_elems(&v[1..], 64), 15);
+        assert!(is_aligned_to(&v[16..16], 64));
+        // element boundaries never reach an aligned address
+        let b = init_aligned_vec::<u8>(8, 0, 8, 0);
+        let odd = unsafe { core::slice::from_raw_parts(b.as_ptr().add(1) as *const [u8; 2], 2) };
+        assert_eq!(align_offset_elems(odd, 4), usize::MAX);
+        assert_eq!(align_offset_elems(&b[3..], 8), 5);
+    }
+    #[test]
+    fn measure_first_touch_test() {
+        let ps = page_size::get();
+        let t = measure_first_touch::<u64>(64 << 20, ps);
+        assert!(t > std::time::Duration::ZERO);
+        // nothing to touch
+        let t = measure_first_touch::<u64>(0, 64);
+        assert!(t < std::time::Duration::from_millis(10));
+        let r = try_measure_first_touch::<u8>(1, 0);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 0 })));
+    }
+    #[test]
+    fn touch_report_test() {
+        let ps = page_size::get();
+        // zeroed memory, nothing to report
+        let mut v = zeroed_aligned_vec::<u64>(16 * ps, 0, ps);
+        assert!(touch_pages_report(v.as_mut_slice(), 1).is_empty());
+        assert_eq!(v[ps / 8], 1);
+        // dirtied memory, every page is reported
+        let mut v = init_aligned_vec::<u64>(16 * ps, 0, ps, 5);
+        v[ps] = 0;
+        let report = touch_pages_report(v.as_mut_slice(), 1);
+        assert_eq!(report, (0..128).filter(|&p| p != 8).collect::<Vec<_>>());
+        assert!(v.iter().step_by(ps / 8).all(|&x| x == 1));
+        // large enough to be mapped fresh by the OS
+        let (v, report) = aligned_vec_touch_report::<u8>(64 << 20, 0, ps, 7);
+        assert!(report.is_empty());
+        assert!(v.iter().step_by(ps).all(|&x| x == 7));
+    }
+    #[test]
+    fn aligned_layout_test() {
+        let (layout, capacity) = aligned_layout::<u64>(10, 4, 64).unwrap();
+        assert_eq!(capacity, 10);
+        assert_eq!(layout.size(), 80);
+        assert_eq!(layout.align(), 64);
+        let (layout, capacity) = aligned_layout::<u16>(10, 40, 8).unwrap();
+        assert_eq!(capacity, 40);
+        assert_eq!(layout.size(), 80);
+        let r = aligned_layout::<u64>(usize::MAX / 4, 0, 64);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = aligned_layout::<u8>(usize::MAX, 0, 64);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = aligned_layout::<u8>(1, 0, 6);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 6 })));
+    }
+    #[test]
+    fn init_aligned_vec_parallel_test() {
+        let ps = page_size::get();
+        for threads in [0, 1, 3, 8, 1000] {
+            let size = 37 * ps / 8 + 5;
+            let v = init_aligned_vec_parallel::<u64>(size, 2 * size, 256, 0x0123_4567, threads);
+            assert_eq!(v.len(), size);
+            assert_eq!(v.capacity(), 2 * size);
+            assert_eq!(v.as_ptr().align_offset(256), 0);
+            assert!(v.as_slice().iter().all(|&x| x == 0x0123_4567));
+        }
+        let v = init_aligned_vec_parallel::<u8>(0, 0, 64, 1, 4);
+        assert!(v.is_empty());
+    }
+    #[test]
+    fn stride_indices_test() {
+        let ps = page_size::get();
+        // one page plus one byte, the last byte is alone in its page
+        let mut v = aligned_vec::<u8>(ps + 1, 0, ps, None);
+        assert_eq!(
+            stride_indices(v.as_slice(), ps).collect::<Vec<_>>(),
+            [0, ps]
+        );
+        let touches = touch_count();
+        touch_pages(v.as_mut_slice(), 1);
+        assert_eq!(touch_count() - touches, 2);
+        assert_eq!((v[0], v[ps]), (1, 1));
+        // unaligned start, the elements of the last page follow a boundary
+        // that is not a multiple of the page size from the start
+        let s = &v.as_slice()[100..];
+        assert_eq!(stride_indices(s, ps).collect::<Vec<_>>(), [0, ps - 100]);
+        let s = &v.as_slice()[100..ps];
+        assert_eq!(stride_indices(s, ps).collect::<Vec<_>>(), [0]);
+        // elements larger than the stride are all touched
+        let v = aligned_vec::<[u8; 5]>(7, 0, 4, None);
+        assert_eq!(
+            stride_indices(v.as_slice(), 4).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4, 5, 6]
+        );
+        // elements straddling page boundaries
+        let v = aligned_vec::<[u8; 3]>(ps, 0, ps, None);
+        let i: Vec<_> = stride_indices(v.as_slice(), ps).collect();
+        assert_eq!(i, [0, ps / 3, 2 * ps / 3]);
+        assert!(stride_indices(&[(); 10], ps).next().is_none());
+        assert!(stride_indices::<u8>(&[], ps).next().is_none());
+    }
+    // strides and slice offsets simulating buffers as large as the address
+    // space, no index may reach the length
+    #[test]
+    fn stride_indices_bounds_test() {
+        let v = aligned_vec::<u16>(1000, 0, 4096, None);
+        for offset in [0, 1, 7, 500, 999, 1000] {
+            let s = &v.as_slice()[offset..];
+            for stride in [1, 2, 3, 4096, 1999, 2000, 2001, usize::MAX / 2, usize::MAX] {
+                let bytes = std::mem::size_of_val(s);
+                let i: Vec<_> = stride_indices(s, stride).collect();
+                assert!(i.iter().all(|&i| i < s.len()));
+                assert!(i.windows(2).all(|w| w[0] < w[1]));
+                assert_eq!(i.is_empty(), s.is_empty());
+                // one index per boundary, capped by the number of elements
+                let first = stride - s.as_ptr() as usize % stride;
+                let boundaries = if first < bytes {
+                    (bytes - first - 1) / stride + 1
+                } else {
+                    0
+                };
+                assert!(i.len() <= (boundaries + 1).min(s.len()));
+            }
+        }
+    }
+    #[test]
+    fn page_count_test() {
+        let ps = page_size::get();
+        for n in [1, 2, 5] {
+            assert_eq!(page_count::<u8>(n * ps), n);
+            assert_eq!(page_count::<u8>(n * ps + 1), n + 1);
+            assert_eq!(page_count::<u64>(n * ps / 8), n);
+            assert_eq!(page_count::<u64>(n * ps / 8 + 1), n + 1);
+            // the count of a page aligned buffer as touched
+            let touches = touch_count();
+            let v = aligned_vec::<u8>(n * ps + 1, 0, ps, Some(1));
+            assert_eq!(touch_count() - touches, page_count::<u8>(v.len()));
+        }
+        assert_eq!(page_count::<u32>(0), 0);
+        assert_eq!(page_count::<u8>(1), 1);
+        assert_eq!(page_count::<()>(1000), 0);
+        with_page_size(64, || assert_eq!(page_count::<u32>(17), 2));
+    }
+    #[test]
+    fn capacity_aligned_vec_test() {
+        let ps = page_size::get();
+        let v = capacity_aligned_vec::<u32>(1000, 0, 64, ps, Some(1));
+        assert!(v.is_aligned_to(64));
+        assert_eq!(v.alignment(), 64);
+        assert_eq!(v.layout().size(), ps);
+        assert_eq!(v.capacity(), ps / 4);
+        assert_eq!(v.len(), 1000);
+        // a whole number of elements, the layout is rebuilt on release
+        let v = capacity_aligned_vec::<[u8; 3]>(5000, 6000, 64, 2 * ps, None);
+        assert_eq!(v.layout().size(), 18000_usize.next_multiple_of(6 * ps));
+        assert_eq!(v.capacity() * 3, v.layout().size());
+        let v = capacity_aligned_vec::<[u8; 3]>(10, 0, 64, 4096, None);
+        assert_eq!((v.capacity(), v.layout().size()), (4096, 3 * 4096));
+        let (ptr, size, capacity) = v.into_raw_parts();
+        unsafe { dealloc_aligned(ptr, size, capacity, 64) };
+        let (v, capacity) = padded_aligned_vec::<[u8; 3]>(10, 0, 64, None);
+        assert_eq!((capacity, v.layout().size()), (64, 192));
+        // already a multiple
+        let v = capacity_aligned_vec::<u8>(ps, 0, 64, ps, None);
+        assert_eq!(v.layout().size(), ps);
+        assert!(capacity_aligned_vec::<u8>(0, 0, 64, ps, None).is_empty());
+        let r = try_capacity_aligned_vec::<u8>(1, 0, 64, 3 * ps, None);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { .. })));
+        let r = try_capacity_aligned_vec::<u8>(1, 0, 0, ps, None);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 0 })));
+        let r = try_capacity_aligned_vec::<u8>(isize::MAX as usize - 10, 0, 1, ps, None);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+    }
+    #[test]
+    fn page_aligned_vec_info_test() {
+        let ps = page_size::get();
+        for size in [0, 1, ps / 8, ps / 8 + 1, 10 * ps / 8] {
+            let (v, info) = page_aligned_vec_info::<u64>(size, 0, Some(1), false).unwrap();
+            assert_eq!(v.len(), size);
+            assert_eq!(info.page_size, ps);
+            assert_eq!(info.pages, (size * 8).div_ceil(ps));
+            assert!(!info.locked);
+        }
+        let (v, info) = page_aligned_vec_info::<u8>(ps, 0, Some(1), true).unwrap();
+        assert!(info.locked);
+        assert_eq!(info.pages, 1);
+        assert!(v.is_aligned_to(ps));
+    }
+    #[test]
+    fn aligned_vec_with_progress_test() {
+        let ps = page_size::get();
+        let mut calls = Vec::new();
+        let v = aligned_vec_with_progress::<u32>(10 * ps / 4, 0, ps, Some(7), 3, |done, total| {
+            calls.push((done, total))
+        });
+        assert_eq!(calls, [(3, 10), (6, 10), (9, 10), (10, 10)]);
+        assert!((0..10).all(|p| v[p * ps / 4] == 7));
+        // the last report is not repeated when it falls on a multiple
+        calls.clear();
+        aligned_vec_with_progress::<u8>(4 * ps, 0, ps, Some(1), 2, |done, total| {
+            calls.push((done, total))
+        });
+        assert_eq!(calls, [(2, 4), (4, 4)]);
+        // unaligned start, the partial pages at both ends count
+        calls.clear();
+        aligned_vec_with_progress::<u8>(2 * ps, 0, 64, Some(1), 0, |done, total| {
+            calls.push((done, total))
+        });
+        let total = calls.last().unwrap().1;
+        assert!(total == 2 || total == 3);
+        assert!(calls.iter().map(|c| c.0).eq(1..=total));
+        let mut called = false;
+        aligned_vec_with_progress::<u8>(4 * ps, 0, ps, None, 1, |_, _| called = true);
+        aligned_vec_with_progress::<u8>(0, 0, ps, Some(1), 1, |_, _| called = true);
+        assert!(!called);
+    }
+    #[test]
+    fn capacity_less_than_size_test() {
+        let v = init_aligned_vec::<u32>(100, 0, 64, 7);
+        assert_eq!(v.len(), 100);
+        assert!(v.capacity() >= 100);
+        assert!(v.iter().all(|&x| x == 7));
+        let mut v = aligned_vec::<u8>(100, 10, 64, Some(1));
+        assert!(v.capacity() >= 100);
+        v.as_mut_slice().fill(2);
+        v.push(3);
+        assert_eq!(v.len(), 101);
+        assert_eq!(v[99..], [2, 3]);
+        let v = zeroed_aligned_vec::<u64>(100, 99, 64);
+        assert!(v.capacity() >= 100);
+        assert!(v.iter().all(|&x| x == 0));
+    }
+    #[test]
+    fn aligned_vec_from_layout_test() {
+        let ps = page_size::get();
+        let layout = Layout::from_size_align(3 * ps + 100, 128).unwrap();
+        let v = aligned_vec_from_layout::<u64>(layout, 10, Some(9)).unwrap();
+        assert_eq!(v.layout(), layout);
+        assert!(v.is_aligned_to(128));
+        assert_eq!((v.len(), v.capacity()), (10, (3 * ps + 100) / 8));
+        assert_eq!(v[0], 9);
+        let mut v = aligned_vec_from_layout::<u8>(layout, 3 * ps + 100, None).unwrap();
+        v.as_mut_slice().fill(1);
+        let e = aligned_vec_from_layout::<u8>(layout, 3 * ps + 101, None);
+        assert!(
+            matches!(e, Err(AllocError::IncompatibleLayout { elements, .. })
+            if elements == 3 * ps + 101)
+        );
+        let e = aligned_vec_from_layout::<u64>(Layout::from_size_align(64, 4).unwrap(), 1, None);
+        assert!(matches!(e, Err(AllocError::IncompatibleLayout { .. })));
+        let e = aligned_vec_from_layout::<u64>(layout, usize::MAX, None);
+        assert!(matches!(e, Err(AllocError::IncompatibleLayout { .. })));
+        let v = aligned_vec_from_layout::<u32>(Layout::from_size_align(0, 64).unwrap(), 0, None);
+        assert!(v.unwrap().is_empty());
+    }
+    #[test]
+    fn cached_page_size_test() {
+        assert_eq!(cached_page_size(), page_size::get());
+        assert_eq!(os_page_size(), cached_page_size());
+        // the touch loop strides by the cached value, mocked here
+        let v = with_page_size(64, || aligned_vec::<u8>(4096, 0, 4096, Some(1)));
+        assert_eq!(os_page_size(), cached_page_size());
+        for (i, &x) in v.iter().enumerate() {
+            if i % 64 == 0 {
+                assert_eq!(x, 1);
+            }
+        }
+        let touches = touch_count();
+        let mut v = aligned_vec::<u32>(1024, 0, 4096, None);
+        with_page_size(1024, || touch_pages(v.as_mut_slice(), 2));
+        assert_eq!(touch_count() - touches, 4);
+    }
+    #[test]
+    fn from_vec_aligned_test() {
+        let v = from_vec_aligned(vec![1u64, 2, 3], 128);
+        assert!(v.is_aligned_to(128));
+        assert_eq!(v.as_slice(), [1, 2, 3]);
+        assert_eq!(v.capacity(), 3);
+        let v = from_vec_aligned(Vec::<u64>::with_capacity(100), 128);
+        assert!(v.is_empty() && v.is_aligned_to(128));
+        assert!(try_from_vec_aligned(vec![1u8], 100).is_err());
+    }
+    #[test]
+    fn atomic_element_alignment_test() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        // the alignment requested is raised to the one of `AtomicU64`
+        let v = from_iter_aligned((0..1000).map(AtomicU64::new), 1);
+        assert_eq!(v.alignment(), 8);
+        for (i, x) in v.iter().enumerate() {
+            assert!((x as *const AtomicU64 as usize).is_multiple_of(8));
+            x.fetch_add(1, Ordering::Relaxed);
+            assert_eq!(x.load(Ordering::Relaxed), i as u64 + 1);
+        }
+        // 8-byte elements aligned to 1 are aligned to their size when `align`
+        // is a multiple of it
+        let v = aligned_vec::<[u8; 8]>(1000, 0, 8, None);
+        assert!(v.iter().all(|x| (x.as_ptr() as usize).is_multiple_of(8)));
+    }
+    #[test]
+    fn send_sync_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<AlignedVec<u8>>();
+        assert_send_sync::<IntoIter<u8>>();
+        assert_send_sync::<AlignedBox<[u64]>>();
+        assert_send_sync::<AlignedVecN<f32, 64>>();
+        assert_send_sync::<AlignedGroup<u8>>();
+        assert_send_sync::<AdoptedVec<u8>>();
+        assert_send_sync::<PageLockedVec<u8>>();
+        #[cfg(unix)]
+        assert_send_sync::<GuardedAlignedVec<u8>>();
+        #[cfg(unix)]
+        assert_send_sync::<SealedAlignedVec<u8>>();
+        #[cfg(target_os = "linux")]
+        assert_send_sync::<MmapVec<u8>>();
+        // moved across threads with its page locks
+        let v = page_locked_vec::<u8>(100, 0, Some(1));
+        let v = std::thread::spawn(move || v).join().unwrap();
+        assert_eq!(v[0], 1);
+    }
+    #[test]
+    fn zero_sized_test() {
+        std::thread_local! {
+            static DROPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+        // zero-sized but still dropped once per element
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.with(|d| d.set(d.get() + 1));
+            }
+        }
+        let (v, counts) = crate::counting::count_allocs(|| {
+            let mut v = aligned_vec::<()>(1000, 0, 4096, Some(()));
+            v.push(());
+            v.reserve(1 << 40);
+            v
+        });
+        assert_eq!(counts.allocs, 0);
+        assert_eq!(v.len(), 1001);
+        assert_eq!(v.capacity(), usize::MAX);
+        assert!(v.is_aligned_to(4096));
+        assert_eq!(v.layout().size(), 0);
+        let ((), counts) = crate::counting::count_allocs(|| drop(v));
+        assert_eq!(counts.frees, 0);
+        let (v, counts) = crate::counting::count_allocs(|| {
+            let v = try_capacity_aligned_vec::<()>(10, 20, 64, 4096, None).unwrap();
+            let w = aligned_vec_from_layout::<()>(Layout::new::<()>(), 5, None).unwrap();
+            (v, w)
+        });
+        assert_eq!(counts.allocs, 0);
+        assert_eq!((v.0.len(), v.1.len()), (10, 5));
+        let v = aligned_vec_with(3, 0, 64, |_| Counted);
+        drop(v);
+        assert_eq!(DROPS.with(|d| d.get()), 3);
+    }
+    #[test]
+    fn std_vec_fast_path_test() {
+        // alignment of `T` at most, the memory of a `Vec`
+        for align in [1, 8] {
+            let (v, counts) =
+                crate::counting::count_allocs(|| aligned_vec::<u64>(10, 20, align, Some(3)));
+            assert_eq!(counts.allocs, 1);
+            assert_eq!((v.len(), v.capacity(), v.alignment()), (10, 20, 8));
+            assert_eq!(v.layout(), Layout::array::<u64>(20).unwrap());
+            assert_eq!(v[0], 3);
+            let p = v.as_ptr();
+            let s = v.try_into_std_vec_zero_copy().unwrap();
+            assert_eq!((s.as_ptr(), s.len(), s.capacity()), (p, 10, 20));
+        }
+        let mut v = init_aligned_vec::<u32>(3, 0, 4, 1);
+        v.extend_from_slice(&[2; 100]);
+        assert_eq!(v.len(), 103);
+        assert_eq!(v.alignment(), 4);
+        // larger alignments and zeroed memory take the regular path
+        let v = aligned_vec::<u64>(10, 0, 16, None);
+        assert!(v.is_aligned_to(16));
+        assert!(v.try_into_std_vec_zero_copy().is_err());
+        let v = zeroed_aligned_vec::<u64>(10, 0, 8);
+        assert_eq!(v.as_slice(), [0; 10]);
+    }
+    #[test]
+    fn dma_aligned_vec_test() {
+        fn check<T: Copy>(size: usize, boundary: usize) {
+            let v = dma_aligned_vec::<T>(size, boundary);
+            let (start, bytes) = (v.as_ptr() as usize, size * std::mem::size_of::<T>());
+            assert_eq!(v.len(), size);
+            assert!(v.is_aligned_to(std::mem::align_of::<T>()));
+            if bytes != 0 {
+                assert_eq!(start / boundary, (start + bytes - 1) / boundary);
+            }
+        }
+        for boundary in [64, 4096, 64 << 10, 1 << 20] {
+            for bytes in [0, 1, 24, 64, 100, 4095, 4096, 40000, 64 << 10, 1 << 20] {
+                if bytes <= boundary {
+                    check::<u8>(bytes, boundary);
+                    check::<u64>(bytes / 8, boundary);
+                }
+            }
+            check::<[u8; 3]>(boundary / 3, boundary);
+        }
+        check::<()>(10, 1);
+        // the whole region, over-allocated above 64 KiB
+        let v = dma_aligned_vec::<u8>(1 << 20, 1 << 20);
+        assert_eq!(v.backend(), Backend::OverAligned);
+        let r = try_dma_aligned_vec::<u32>(1025, 4096);
+        assert!(matches!(
+            r,
+            Err(AllocError::BoundaryTooSmall {
+                bytes: 4100,
+                boundary: 4096
+            })
+        ));
+        let r = try_dma_aligned_vec::<u8>(10, 48);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 48 })));
+        let r = try_dma_aligned_vec::<u64>(usize::MAX / 4, 1 << 20);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+    }
+    #[test]
+    fn reserved_bytes_test() {
+        fn check<T: Copy>(size: usize, capacity: usize, align: usize) {
+            let v = aligned_vec::<T>(size, capacity, align, None);
+            assert_eq!(
+                reserved_bytes::<T>(size, capacity, align).unwrap(),
+                v.layout().size()
+            );
+        }
+        for align in [1, 8, 64, 4096, 1 << 17] {
+            check::<u8>(1000, 0, align);
+            check::<u64>(10, 100, align);
+            check::<[u16; 3]>(7, 3, align);
+            check::<u32>(0, 0, align);
+            check::<()>(5, 0, align);
+        }
+        assert_eq!(reserved_bytes::<u64>(3, 0, 64).unwrap(), 24);
+        let r = reserved_bytes::<u64>(usize::MAX / 4, 0, 64);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = reserved_bytes::<u8>(1, 0, 3);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+    }
+    #[test]
+    fn align_up_test() {
+        for (value, align, up) in [
+            (0, 1, 0),
+            (0, 64, 0),
+            (1, 64, 64),
+            (63, 64, 64),
+            (64, 64, 64),
+            (65, 64, 128),
+            (4097, 4096, 8192),
+            (usize::MAX, 1, usize::MAX),
+        ] {
+            assert_eq!(align_up(value, align), up);
+            assert_eq!(padding_needed(value, align), up - value);
+        }
+        assert_eq!(padding_needed(usize::MAX, 64), 1);
+        // buffers of 100 u32 and 10 u64 at 64 bytes in one allocation
+        const OFFSET: usize = align_up(100 * 4, 64);
+        const SIZE: usize = OFFSET + 10 * 8;
+        assert_eq!((OFFSET, SIZE), (448, 528));
+        assert_eq!(const { padding_needed(400, 64) }, 48);
+        assert_eq!(const { padding_needed(448, 64) }, 0);
+        let r = std::panic::catch_unwind(|| align_up(usize::MAX, 64));
+        assert!(r.is_err());
+        let r = std::panic::catch_unwind(|| padding_needed(10, 3));
+        assert!(r.is_err());
+    }
+    #[test]
+    fn element_alignment_test() {
+        #[repr(align(32))]
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Wide(u8);
+        let (layout, _) = aligned_layout::<Wide>(4, 0, 8).unwrap();
+        assert_eq!(layout.align(), 32);
+        let vecs = [
+            aligned_vec::<Wide>(100, 0, 8, Some(Wide(1))),
+            aligned_vec_with_capacity::<Wide>(100, 8),
+            init_aligned_vec::<Wide>(100, 0, 16, Wide(2)),
+            padded_aligned_vec::<Wide>(3, 0, 8, None).0,
+            from_slice_aligned(&[Wide(3); 7], 1),
+            aligned_vec::<Wide>(0, 0, 8, None),
+        ];
+        for v in vecs {
+            assert_eq!(v.alignment(), 32);
+            assert_eq!(v.as_ptr().align_offset(32), 0);
+        }
+        let v = AlignedVecBuilder::<Wide>::new()
+            .size(10)
+            .align(8)
+            .build()
+            .unwrap();
+        assert_eq!(v.as_ptr().align_offset(32), 0);
+        let b = aligned_boxed_slice::<Wide>(5, 8, Some(Wide(4)));
+        assert_eq!(b.as_ptr().align_offset(32), 0);
+        let (ptr, size, capacity) = init_aligned_vec::<Wide>(9, 0, 8, Wide(5)).into_raw_parts();
+        unsafe { dealloc_aligned(ptr, size, capacity, 8) };
+    }
+    #[test]
+    fn layout_overflow_test() {
+        let size = usize::MAX / 4;
+        let r = try_aligned_vec::<u128>(size, 0, 64, None);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = try_init_aligned_vec::<u128>(1, size, 64, 0);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = try_zeroed_aligned_vec::<u128>(size, size, 64);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let r = try_page_locked_vec::<u128>(size, 0, None);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+    }
+    #[test]
+    #[should_panic(expected = "aligned_vec: buffer size in bytes overflows")]
+    fn layout_overflow_panic_test() {
+        aligned_vec::<u128>(usize::MAX / 4, 0, 64, None);
+    }
+    #[test]
+    fn aligned_vec_with_capacity_test() {
+        let v = aligned_vec_with_capacity::<u64>(100, 512);
+        assert_eq!(v.as_ptr() as usize % 512, 0);
+        assert_eq!(v.len(), 0);
+        assert_eq!(v.capacity(), 100);
+        assert_eq!(v.layout().align(), 512);
+        let v = realloc_aligned(v, 10, 1000).unwrap();
+        assert_eq!(v.as_ptr() as usize % 512, 0);
+        assert_eq!(v.capacity(), 1000);
+        let v = aligned_vec_with_capacity::<u64>(0, 512);
+        assert_eq!(v.capacity(), 0);
+    }
+    #[test]
+    fn touched_aligned_vec_test() {
+        let ps = page_size::get();
+        let cl = cache_line_size();
+        let v = touched_aligned_vec::<u32>(4 * ps, 0, ps, 11, TouchMode::PerCacheLine);
+        for offset in (0..ps).step_by(cl) {
+            assert_eq!(v[offset / std::mem::size_of::<u32>()], 11);
+        }
+        let v = touched_aligned_vec::<u8>(4 * ps, 0, ps, 12, TouchMode::PerPage);
+        assert_eq!(v[3 * ps], 12);
+        let v = touched_aligned_vec::<u8>(4 * ps, 0, ps, 12, TouchMode::None);
+        assert_eq!(v.len(), 4 * ps);
+    }
+    #[test]
+    fn from_slice_aligned_test() {
+        let src: Vec<u32> = (0..1000).collect();
+        let v = from_slice_aligned(&src, 4096);
+        assert_eq!(v.as_ptr() as usize % 4096, 0);
+        assert_eq!(v.as_slice(), &src[..]);
+        let v = from_slice_aligned::<u32>(&[], 64);
+        assert!(v.is_empty());
+        assert_eq!(v.capacity(), 0);
+    }
+    #[test]
+    fn shrink_aligned_test() {
+        let v = aligned_vec_with(100, 400, 256, |i| i.to_string());
+        assert_eq!(v.capacity(), 400);
+        let v = shrink_aligned(v).unwrap();
+        assert_eq!(v.capacity(), v.len());
+        assert_eq!(v.len(), 100);
+        assert!(v.is_aligned_to(256));
+        assert_eq!(v[99], "99");
+        let p = v.as_ptr();
+        let v = shrink_aligned(v).unwrap();
+        assert_eq!(v.as_ptr(), p);
+    }
+    #[test]
+    fn dealloc_aligned_test() {
+        for align in [8, 4096, 1 << 20] {
+            let v = aligned_vec::<u64>(100, 300, align, Some(1));
+            let align = v.alignment();
+            let (ptr, len, capacity) = v.into_raw_parts();
+            assert_eq!(ptr as usize % align, 0);
+            unsafe {
+                assert_eq!(*ptr, 1);
+                dealloc_aligned(ptr, len, capacity, align);
+            }
+        }
+        let (v, _) = padded_aligned_vec::<u8>(10, 0, 64, None);
+        let (ptr, len, capacity) = v.into_raw_parts();
+        unsafe { dealloc_aligned(ptr, len, capacity, 64) };
+        let (ptr, len, capacity) = aligned_vec_with_capacity::<u8>(0, 64).into_raw_parts();
+        unsafe { dealloc_aligned(ptr, len, capacity, 64) };
+    }
+    #[test]
+    fn strided_aligned_vec_test() {
+        let stride = 2 << 20;
+        let size = 5 * stride + stride / 2;
+        let v = strided_aligned_vec::<u16>(size / 2, 0, stride, 9, stride);
+        assert_eq!(v.as_ptr() as usize % stride, 0);
+        for i in (0..v.len()).step_by(stride / 2) {
+            assert_eq!(v[i], 9);
+        }
+        let r = try_strided_aligned_vec::<u8>(16, 0, 64, 1, 0);
+        assert!(matches!(r, Err(AllocError::InvalidTouchStride)));
+    }
+    #[test]
+    fn padded_aligned_vec_test() {
+        let (v, capacity) = padded_aligned_vec::<u32>(10, 20, 256, Some(3));
+        assert_eq!(v.as_ptr() as usize % 256, 0);
+        assert!(capacity >= 20);
+        assert_eq!(capacity, 64);
+        assert_eq!(v.capacity(), capacity);
+        assert_eq!(v.layout().size(), capacity * std::mem::size_of::<u32>());
+        assert_eq!(v.layout().size() % 256, 0);
+        assert_eq!(v[0], 3);
+        let (v, capacity) = padded_aligned_vec::<u64>(0, 0, 64, None);
+        assert!(v.is_empty());
+        assert_eq!(capacity, 0);
+    }
+    #[test]
+    fn realloc_aligned_test() {
+        let v = aligned_vec_with::<f32, _>(100, 0, 64, |i| i as f32);
+        let v = realloc_aligned(v, 1000, 2000).unwrap();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v.len(), 1000);
+        assert_eq!(v.capacity(), 2000);
+        assert!((0..100).all(|i| v[i] == i as f32));
+        // capacity smaller than the size
+        let v = realloc_aligned(v, 10, 0).unwrap();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        assert_eq!(v.capacity(), 10);
+        assert!((0..10).all(|i| v[i] == i as f32));
+        let v = realloc_aligned(v, 0, 0).unwrap();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+        let v = realloc_aligned(v, 3, 0).unwrap();
+        assert_eq!(v.as_ptr() as usize % 64, 0);
+    }
 }
@@ -0,0 +1,749 @@
+//! Page-locked buffers.
+//!
+//! Memory locked with `mlock` stays accounted against `RLIMIT_MEMLOCK` until
+//! it is unlocked, freeing the buffer is not enough. `PageLockedVec` unlocks
+//! the range it locked before the memory is released. Buffers created with
+//! [`crate::secure_page_locked_vec`] are also overwritten with zeros before
+//! being unlocked, so that secrets do not linger in freed memory.
+//!
+//! Pages are locked with `mlock`/`munlock` on Unix and with
+//! `VirtualLock`/`VirtualUnlock` on Windows.
+use crate::{AlignedVec, AllocError};
+use std::ffi::c_void;
+use std::ops::{Deref, Index, IndexMut, Range};
+use std::slice::SliceIndex;
+
+//-----------------------------------------------------------------------------
+/// Page-locked `AlignedVec`, memory is unlocked when the value is dropped.
+pub struct PageLockedVec<T> {
+    vec: AlignedVec<T>,
+    // byte ranges locked, relative to the beginning of the buffer
+    locked: Vec<Range<usize>>,
+    // overwrite the whole allocation with zeros on drop
+    zeroize: bool,
+}
+
+impl<T> PageLockedVec<T> {
+    /// Lock the pages holding the elements of `vec`, if `zeroize` is `true`
+    /// the allocation is overwritten with zeros when dropped.
+    ///
+    /// Pages are locked when they are first faulted in if `on_fault` is
+    /// `true`, see [`lock_pages_on_fault`].
+    ///
+    /// Returns [`AllocError::Syscall`] if the pages cannot be locked, `vec` is
+    /// released in that case.
+    pub(crate) fn new(
+        vec: AlignedVec<T>,
+        zeroize: bool,
+        on_fault: bool,
+    ) -> Result<Self, AllocError> {
+        let bytes = if on_fault {
+            lock_pages_on_fault(&vec)?
+        } else {
+            lock_pages(&vec)?
+        };
+        let mut locked = Vec::new();
+        if bytes != 0 {
+            locked.push(0..bytes);
+        }
+        #[cfg(feature = "stats")]
+        crate::stats::record_locked(false);
+        Ok(Self {
+            vec,
+            locked,
+            zeroize,
+        })
+    }
+    /// Return `true` if memory is overwritten with zeros on drop.
+    pub fn zeroize_on_drop(&self) -> bool {
+        self.zeroize
+    }
+    /// Number of locked bytes.
+    pub fn locked_bytes(&self) -> usize {
+        self.locked.iter().map(|r| r.len()).sum()
+    }
+    /// Unlock the pages holding elements in `range` only, to give back part
+    /// of the `RLIMIT_MEMLOCK` budget while the rest of the buffer stays
+    /// locked. The range is rounded inward to page boundaries, except at the
+    /// ends of the buffer: the first and last pages are unlocked if no element
+    /// outside `range` lies in them. Pages already unlocked are skipped and
+    /// only the pages still locked are unlocked on drop.
+    ///
+    /// Returns [`AllocError::Syscall`] if unlocking fails, the pages
+    /// unlocked until then are accounted for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds or if its start is past its end.
+    pub fn unlock_range(&mut self, range: Range<usize>) -> Result<(), AllocError> {
+        let len = self.vec.len();
+        assert!(
+            range.start <= range.end && range.end <= len,
+            "unlock_range: range {range:?} out of bounds for length {len}"
+        );
+        let ps = crate::cached_page_size();
+        let size = std::mem::size_of::<T>();
+        // the buffer starts on a page boundary
+        let start = match range.start {
+            0 => 0,
+            i => (i * size).next_multiple_of(ps),
+        };
+        let end = match range.end {
+            i if i == len => (i * size).next_multiple_of(ps),
+            i => i * size / ps * ps,
+        };
+        let mut locked = Vec::with_capacity(self.locked.len() + 1);
+        let mut result = Ok(());
+        for r in std::mem::take(&mut self.locked) {
+            let (lo, hi) = (r.start.max(start), r.end.min(end));
+            if lo >= hi || result.is_err() {
+                locked.push(r);
+                continue;
+            }
+            let p = unsafe { (self.vec.as_ptr() as *const u8).add(lo) };
+            if let Err(e) = unsafe { sys::unlock(p as *const c_void, hi - lo) } {
+                result = Err(AllocError::Syscall {
+                    op: sys::UNLOCK,
+                    source: e,
+                });
+                locked.push(r);
+                continue;
+            }
+            locked.extend(
+                [r.start..lo, hi..r.end]
+                    .into_iter()
+                    .filter(|r| !r.is_empty()),
+            );
+        }
+        self.locked = locked;
+        result
+    }
+    /// Lock again the pages holding the elements that are not locked, e.g.
+    /// after [`PageLockedVec::unlock_range`] released them under memory
+    /// pressure. Under a tight `RLIMIT_MEMLOCK` or with little free memory
+    /// locking may fail partway, the pages are then locked one by one to count
+    /// how many could be: the error reports that count so that the caller can
+    /// decide to retry later or to proceed with the pages unlocked.
+    ///
+    /// Returns a [`LockError`] if a page cannot be locked, the pages locked by
+    /// this call are unlocked again in that case and the buffer is left as it
+    /// was.
+    pub fn try_lock(&mut self) -> Result<(), LockError> {
+        let ps = crate::cached_page_size();
+        let end = page_rounded_bytes::<T>(self.vec.len());
+        let mut ranges = self.locked.clone();
+        ranges.sort_by_key(|r| r.start);
+        // pages not locked, ranges are page aligned
+        let mut gaps = Vec::new();
+        let mut at = 0;
+        for r in ranges.into_iter().chain(core::iter::once(end..end)) {
+            if r.start > at {
+                gaps.push(at..r.start);
+            }
+            at = at.max(r.end);
+        }
+        let base = self.vec.as_ptr() as *const u8;
+        let mut done: Vec<Range<usize>> = Vec::with_capacity(gaps.len());
+        let mut failure = None;
+        for gap in gaps {
+            // one call for the whole range, counted page by page on failure
+            let p = unsafe { base.add(gap.start) } as *const c_void;
+            if unsafe { sys::lock(p, gap.len()) }.is_ok() {
+                done.push(gap);
+                continue;
+            }
+            for page in gap.step_by(ps) {
+                let p = unsafe { base.add(page) } as *const c_void;
+                if let Err(e) = unsafe { sys::lock(p, ps) } {
+                    failure = Some(e.raw_os_error().unwrap_or(0));
+                    break;
+                }
+                match done.last_mut() {
+                    Some(r) if r.end == page => r.end += ps,
+                    _ => done.push(page..page + ps),
+                }
+            }
+            if failure.is_some() {
+                break;
+            }
+        }
+        let Some(errno) = failure else {
+            self.locked.extend(done);
+            return Ok(());
+        };
+        let pages_locked = done.iter().map(|r| r.len() / ps).sum();
+        for r in done {
+            unsafe {
+                // the pages were locked by this call, unlocking them is
+                // expected to succeed
+                let _ = sys::unlock(base.add(r.start) as *const c_void, r.len());
+            }
+        }
+        Err(LockError {
+            pages_locked,
+            errno,
+        })
+    }
+    /// View elements as mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.vec.as_mut_slice()
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Error returned by [`PageLockedVec::try_lock`] when a page cannot be locked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockError {
+    /// Number of pages that could be locked before the failure, unlocked
+    /// again since.
+    pub pages_locked: usize,
+    /// `errno` of the failed lock, usually `ENOMEM` or `EAGAIN`.
+    pub errno: i32,
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cannot lock pages in memory (errno {}) after locking {} pages, \
+             check the RLIMIT_MEMLOCK limit (ulimit -l)",
+            self.errno, self.pages_locked
+        )
+    }
+}
+
+impl std::error::Error for LockError {}
+
+// Callers not interested in the partial count propagate a plain lock failure.
+impl From<LockError> for AllocError {
+    fn from(e: LockError) -> Self {
+        AllocError::Syscall {
+            op: sys::LOCK,
+            source: std::io::Error::from_raw_os_error(e.errno),
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Zeroed page-locked buffer overwritten with zeros before being unlocked and
+/// released, returned by [`crate::secure_buffer`].
+pub type SecureBuffer<T> = PageLockedVec<T>;
+
+//-----------------------------------------------------------------------------
+/// Byte length of `len` elements of type `T` rounded up to a page boundary.
+pub(crate) fn page_rounded_bytes<T>(len: usize) -> usize {
+    let ps = crate::cached_page_size();
+    (len * std::mem::size_of::<T>()).div_ceil(ps) * ps
+}
+//-----------------------------------------------------------------------------
+/// Lock all the pages spanned by the elements of `vec` and return the number
+/// of locked bytes, returns [`AllocError::Syscall`] if locking fails.
+pub(crate) fn lock_pages<T>(vec: &AlignedVec<T>) -> Result<usize, AllocError> {
+    lock_range(vec, sys::lock, sys::LOCK)
+}
+//-----------------------------------------------------------------------------
+/// Same as [`lock_pages`] but pages are only locked once faulted in, with
+/// `mlock2(MLOCK_ONFAULT)` on Linux. Falls back to locking all the pages
+/// immediately where `MLOCK_ONFAULT` is not supported.
+pub(crate) fn lock_pages_on_fault<T>(vec: &AlignedVec<T>) -> Result<usize, AllocError> {
+    lock_range(vec, sys::lock_on_fault, sys::LOCK_ON_FAULT)
+}
+/// Lock the pages spanned by the elements of `vec` with `lock`, the system
+/// call `op`.
+fn lock_range<T>(
+    vec: &AlignedVec<T>,
+    lock: unsafe fn(*const c_void, usize) -> std::io::Result<()>,
+    op: &'static str,
+) -> Result<usize, AllocError> {
+    let locked = page_rounded_bytes::<T>(vec.len());
+    if locked != 0 {
+        unsafe { lock(vec.as_ptr() as *const c_void, locked) }
+            .map_err(|source| AllocError::Syscall { op, source })?;
+    }
+    Ok(locked)
+}
+//-----------------------------------------------------------------------------
+/// Return the soft and hard `RLIMIT_MEMLOCK` limits in bytes, `RLIM_INFINITY`
+/// stands for no limit.
+///
+/// # Panics
+///
+/// Panics if `getrlimit` fails.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub fn memlock_limit() -> (u64, u64) {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let r = unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) };
+    assert_eq!(r, 0, "getrlimit: {}", std::io::Error::last_os_error());
+    (limit.rlim_cur as u64, limit.rlim_max as u64)
+}
+//-----------------------------------------------------------------------------
+/// Memory locked by [`lock_all`], flags of `mlockall`.
+#[cfg(unix)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MlockAllFlags {
+    /// `MCL_CURRENT`: the pages currently mapped in the process.
+    Current,
+    /// `MCL_FUTURE`: the pages mapped from now on, allocations may then fail
+    /// once `RLIMIT_MEMLOCK` is reached.
+    Future,
+    /// `MCL_ONFAULT`: with `Current` or `Future`, pages are only locked once
+    /// faulted in.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    OnFault,
+}
+//-----------------------------------------------------------------------------
+/// Lock the whole address space of the process with `mlockall`, for
+/// real-time code that must never page fault on swapped out memory, rather
+/// than the pages of single buffers. `flags` are combined, they must include
+/// [`MlockAllFlags::Current`] or [`MlockAllFlags::Future`].
+///
+/// Returns [`AllocError::Syscall`] if `mlockall` fails, e.g. with `EPERM`
+/// without the privilege to lock memory or `ENOMEM` above `RLIMIT_MEMLOCK`.
+#[cfg(unix)]
+pub fn lock_all(flags: &[MlockAllFlags]) -> Result<(), AllocError> {
+    let flags = flags.iter().fold(0, |f, flag| {
+        f | match flag {
+            MlockAllFlags::Current => libc::MCL_CURRENT,
+            MlockAllFlags::Future => libc::MCL_FUTURE,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            MlockAllFlags::OnFault => libc::MCL_ONFAULT,
+        }
+    });
+    if unsafe { libc::mlockall(flags) } != 0 {
+        return Err(AllocError::Syscall {
+            op: "mlockall",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+//-----------------------------------------------------------------------------
+/// Unlock the whole address space of the process with `munlockall`, also the
+/// pages locked by page-locked buffers, which still unlock their range when
+/// dropped.
+///
+/// Returns [`AllocError::Syscall`] if `munlockall` fails.
+#[cfg(unix)]
+pub fn unlock_all() -> Result<(), AllocError> {
+    if unsafe { libc::munlockall() } != 0 {
+        return Err(AllocError::Syscall {
+            op: "munlockall",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    Ok(())
+}
+//-----------------------------------------------------------------------------
+/// Return [`AllocError::LockLimitExceeded`] if locking the pages spanned by
+/// `len` elements of type `T` would exceed the soft `RLIMIT_MEMLOCK` limit on
+/// its own, memory already locked by the process is not accounted for.
+#[cfg(unix)]
+pub(crate) fn check_memlock_limit<T>(len: usize) -> Result<(), AllocError> {
+    let requested = page_rounded_bytes::<T>(len);
+    let (limit, _) = memlock_limit();
+    if requested as u64 > limit {
+        return Err(AllocError::LockLimitExceeded { requested, limit });
+    }
+    Ok(())
+}
+/// Platform specific page locking.
+#[cfg(unix)]
+pub(crate) mod sys {
+    use std::ffi::c_void;
+    // names of the system calls behind `lock`, `lock_on_fault` and `unlock`
+    pub(crate) const LOCK: &str = "mlock";
+    pub(crate) const UNLOCK: &str = "munlock";
+    #[cfg(target_os = "linux")]
+    pub(crate) const LOCK_ON_FAULT: &str = "mlock2";
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) const LOCK_ON_FAULT: &str = LOCK;
+    pub(crate) unsafe fn lock(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        nix::sys::mman::mlock(ptr, len).map_err(std::io::Error::from)
+    }
+    #[cfg(target_os = "linux")]
+    pub(crate) unsafe fn lock_on_fault(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        // called through `syscall`, not every libc exports `mlock2`
+        if libc::syscall(libc::SYS_mlock2, ptr, len, libc::MLOCK_ONFAULT) == 0 {
+            return Ok(());
+        }
+        let e = std::io::Error::last_os_error();
+        match e.raw_os_error() {
+            // kernels older than 4.4
+            Some(libc::ENOSYS) | Some(libc::EINVAL) => lock(ptr, len),
+            _ => Err(e),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    pub(crate) unsafe fn lock_on_fault(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        lock(ptr, len)
+    }
+    pub(crate) unsafe fn unlock(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        nix::sys::mman::munlock(ptr, len).map_err(std::io::Error::from)
+    }
+}
+
+/// Platform specific page locking.
+#[cfg(windows)]
+pub(crate) mod sys {
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Memory::{VirtualLock, VirtualUnlock};
+    pub(crate) const LOCK: &str = "VirtualLock";
+    pub(crate) const UNLOCK: &str = "VirtualUnlock";
+    pub(crate) const LOCK_ON_FAULT: &str = LOCK;
+    pub(crate) unsafe fn lock(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        if VirtualLock(ptr, len) == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+    pub(crate) unsafe fn lock_on_fault(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        lock(ptr, len)
+    }
+    pub(crate) unsafe fn unlock(ptr: *const c_void, len: usize) -> std::io::Result<()> {
+        if VirtualUnlock(ptr, len) == 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Overwrite `len` bytes starting at `ptr` with zeros, the writes are volatile
+/// so that they are not optimised away even though the memory is not read
+/// afterwards.
+///
+/// # Safety
+///
+/// The range must be valid for writes.
+pub(crate) unsafe fn zeroize(ptr: *mut u8, len: usize) {
+    for i in 0..len {
+        ptr.add(i).write_volatile(0);
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+impl<T> Drop for PageLockedVec<T> {
+    fn drop(&mut self) {
+        if self.zeroize {
+            unsafe {
+                // elements must be dropped before their memory is cleared
+                std::ptr::drop_in_place(self.vec.as_mut_slice());
+                self.vec.set_len(0);
+                // the whole allocation, spare capacity included
+                zeroize(self.vec.as_mut_ptr() as *mut u8, self.vec.layout().size());
+            }
+        }
+        for r in &self.locked {
+            unsafe {
+                // nothing sensible to do on failure, memory is released
+                // anyway
+                let p = (self.vec.as_ptr() as *const u8).add(r.start);
+                let _ = sys::unlock(p as *const c_void, r.len());
+            }
+        }
+        #[cfg(feature = "stats")]
+        crate::stats::record_locked(true);
+    }
+}
+
+// Only shared access to the inner buffer: growing it would move the memory
+// out of the locked range.
+impl<T> Deref for PageLockedVec<T> {
+    type Target = AlignedVec<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.vec
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for PageLockedVec<T> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.vec[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for PageLockedVec<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.vec[index]
+    }
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(windows)]
+    #[test]
+    fn virtual_lock_test() {
+        let v = crate::page_locked_vec::<u8>(page_size::get(), 0, Some(1));
+        assert_eq!(v.locked_bytes(), page_size::get());
+        assert_eq!(v[0], 1);
+        drop(v);
+    }
+    #[test]
+    fn zeroize_test() {
+        // freed memory cannot be inspected, check the scrubbing on a live
+        // buffer and that dropping a secure buffer works
+        let mut v = crate::init_aligned_vec::<u8>(1000, 2000, 64, 0xff);
+        unsafe {
+            zeroize(v.as_mut_ptr(), v.layout().size());
+            v.set_len(2000);
+        }
+        assert!(v.as_slice().iter().all(|&x| x == 0));
+        let mut v = crate::secure_page_locked_vec::<u64>(1000, 2000, Some(7));
+        assert!(v.zeroize_on_drop());
+        v.as_mut_slice().fill(0x5ec12e7);
+        drop(v);
+    }
+    // locked memory of the process in KiB
+    #[cfg(target_os = "linux")]
+    fn locked_kib() -> usize {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|l| l.starts_with("VmLck:")).unwrap();
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+    // locked memory is accounted per process, measured in a child process so
+    // that tests locking memory concurrently are not counted
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn secure_buffer_test() {
+        let ps = page_size::get();
+        crate::child::assert_in_child(|| {
+            let before = locked_kib();
+            let r = crate::secure_buffer::<u64>(10 * ps / 8, 64);
+            let mut ok = r.is_ok();
+            if let Ok(mut v) = r {
+                ok &= v.zeroize_on_drop() && v.locked_bytes() == 10 * ps;
+                ok &= v.is_aligned_to(ps) && v.len() == 10 * ps / 8;
+                ok &= v.iter().all(|&x| x == 0);
+                ok &= crate::resident_pages(&v) == 10;
+                ok &= locked_kib() == before + 10 * ps / 1024;
+                v.as_mut_slice().fill(0x5ec12e7);
+                drop(v);
+                ok &= locked_kib() == before;
+            }
+            ok
+        });
+        let r = crate::secure_buffer::<u8>(1, 3);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+        assert_eq!(crate::secure_buffer::<u8>(0, 64).unwrap().locked_bytes(), 0);
+    }
+    // locking the whole process faults in the memory of every test, done in a
+    // child process
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lock_all_test() {
+        crate::child::assert_in_child(|| {
+            let mut ok = true;
+            match lock_all(&[MlockAllFlags::Current]) {
+                Ok(()) => {
+                    ok &= locked_kib() > 0;
+                    ok &= unlock_all().is_ok();
+                    ok &= locked_kib() == 0;
+                    let flags = [MlockAllFlags::Future, MlockAllFlags::OnFault];
+                    ok &= lock_all(&flags).is_ok();
+                    ok &= unlock_all().is_ok();
+                }
+                // not allowed to lock that much memory
+                Err(AllocError::Syscall {
+                    op: "mlockall",
+                    source,
+                }) => {
+                    let errno = source.raw_os_error();
+                    ok &= [libc::EPERM, libc::ENOMEM, libc::EAGAIN]
+                        .map(Some)
+                        .contains(&errno);
+                }
+                Err(_) => ok = false,
+            }
+            let r = lock_all(&[MlockAllFlags::OnFault]);
+            ok &= matches!(r, Err(AllocError::Syscall { op: "mlockall", source })
+                if source.raw_os_error() == Some(libc::EINVAL));
+            ok
+        });
+    }
+    #[test]
+    fn unlock_range_test() {
+        let ps = page_size::get();
+        let mut v = crate::page_locked_vec::<u32>(10 * ps / 4, 0, Some(1));
+        // the tail, rounded inward at its start
+        v.unlock_range(6 * ps / 4 - 1..v.len()).unwrap();
+        assert_eq!(v.locked_bytes(), 6 * ps);
+        // a hole in the middle, then overlapping it
+        v.unlock_range(ps / 4 + 1..3 * ps / 4).unwrap();
+        assert_eq!(v.locked_bytes(), 5 * ps);
+        v.unlock_range(ps / 4..5 * ps / 4).unwrap();
+        assert_eq!(v.locked_bytes(), 2 * ps);
+        // less than a page, nothing to unlock
+        v.unlock_range(ps / 4 + 1..ps / 4 + 2).unwrap();
+        assert_eq!(v.locked_bytes(), 2 * ps);
+        assert!(v.iter().step_by(ps / 4).all(|&x| x == 1));
+        drop(v);
+        // the head stays locked, checked in a child process
+        #[cfg(target_os = "linux")]
+        crate::child::assert_in_child(|| {
+            let before = locked_kib();
+            let mut v = crate::page_locked_vec::<u8>(8 * ps, 0, Some(1));
+            let mut ok = locked_kib() == before + 8 * ps / 1024;
+            ok &= v.unlock_range(3 * ps..8 * ps).is_ok();
+            ok &= v.locked_bytes() == 3 * ps;
+            ok &= locked_kib() == before + 3 * ps / 1024;
+            drop(v);
+            ok &= locked_kib() == before;
+            ok
+        });
+    }
+    #[test]
+    #[should_panic(expected = "unlock_range: range 0..2 out of bounds for length 1")]
+    fn unlock_range_out_of_bounds_test() {
+        let _ = crate::page_locked_vec::<u8>(1, 0, None).unlock_range(0..2);
+    }
+    // the limit is process wide, it is lowered in a child process so that
+    // tests running concurrently are not affected
+    #[cfg(unix)]
+    #[test]
+    fn lock_failed_test() {
+        crate::child::assert_in_child(|| unsafe {
+            let limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            let mut ok = libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) == 0;
+            // the limit does not apply to privileged processes
+            if libc::geteuid() == 0 {
+                ok &= libc::setuid(65534) == 0;
+            }
+            let r = crate::try_page_locked_vec::<u8>(page_size::get(), 0, Some(1));
+            ok &= matches!(&r, Err(AllocError::Syscall { op: "mlock", source })
+                if source.raw_os_error().is_some_and(|e| e != 0));
+            ok &= r.is_err_and(|e| e.to_string().starts_with("mlock failed: "));
+            let r = crate::try_page_aligned_vec::<u8>(page_size::get(), 0, None, true);
+            ok &= matches!(r, Err(AllocError::Syscall { op: "mlock", .. }));
+            #[cfg(target_os = "linux")]
+            {
+                let r = crate::try_page_locked_on_fault_vec::<u8>(page_size::get(), 0, None);
+                ok &= matches!(&r, Err(AllocError::Syscall { op: "mlock2", .. }));
+                ok &= r.is_err_and(|e| {
+                    std::error::Error::source(&e).is_some_and(|s| s.is::<std::io::Error>())
+                });
+            }
+            ok
+        });
+    }
+    #[test]
+    fn try_lock_test() {
+        let ps = page_size::get();
+        let mut v = crate::page_locked_vec::<u8>(4 * ps, 0, Some(1));
+        v.try_lock().unwrap();
+        assert_eq!(v.locked_bytes(), 4 * ps);
+        v.unlock_range(ps..3 * ps).unwrap();
+        assert_eq!(v.locked_bytes(), 2 * ps);
+        v.try_lock().unwrap();
+        assert_eq!(v.locked_bytes(), 4 * ps);
+        // a failure partway under a tight limit, in a child process as in
+        // `lock_failed_test`
+        #[cfg(target_os = "linux")]
+        crate::child::assert_in_child(|| unsafe {
+            let mut v = crate::page_locked_vec::<u8>(10 * ps, 0, Some(1));
+            let mut ok = v.unlock_range(2 * ps..v.len()).is_ok();
+            let limit = libc::rlimit {
+                rlim_cur: 6 * ps as libc::rlim_t,
+                rlim_max: 6 * ps as libc::rlim_t,
+            };
+            ok &= libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) == 0;
+            if libc::geteuid() == 0 {
+                ok &= libc::setuid(65534) == 0;
+            }
+            let before = locked_kib();
+            // room for 4 of the 8 unlocked pages, all unlocked again
+            let r = v.try_lock();
+            ok &= matches!(r, Err(LockError { pages_locked: 4, errno })
+                if errno == libc::ENOMEM || errno == libc::EAGAIN);
+            ok &= v.locked_bytes() == 2 * ps;
+            ok &= locked_kib() == before && before == 2 * ps / 1024;
+            let e = r.unwrap_err();
+            let errno = e.errno;
+            ok &= matches!(AllocError::from(e), AllocError::Syscall { op: "mlock", source }
+                if source.raw_os_error() == Some(errno));
+            // the buffer is still usable, with its head locked
+            v.as_mut_slice().fill(2);
+            ok &= v.unlock_range(0..v.len()).is_ok() && locked_kib() == 0;
+            ok
+        });
+    }
+    #[cfg(unix)]
+    #[test]
+    fn lock_limit_exceeded_test() {
+        let (soft, hard) = memlock_limit();
+        assert!(soft <= hard);
+        let ps = page_size::get();
+        crate::child::assert_in_child(|| unsafe {
+            let limit = libc::rlimit {
+                rlim_cur: (16 * ps as libc::rlim_t).min(hard as libc::rlim_t),
+                rlim_max: hard as libc::rlim_t,
+            };
+            let mut ok = libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) == 0;
+            let (soft, _) = memlock_limit();
+            ok &= soft == limit.rlim_cur as u64;
+            let r = crate::try_checked_page_locked_vec::<u8>(soft as usize + 1, 0, None);
+            ok &= matches!(r, Err(AllocError::LockLimitExceeded { requested, limit })
+                if requested == soft as usize + ps && limit == soft);
+            // far too large to be allocated, the limit is checked first
+            let r = crate::try_checked_page_locked_vec::<u8>(1 << 46, 0, None);
+            ok &= matches!(r, Err(AllocError::LockLimitExceeded { .. }));
+            let r = crate::try_checked_page_locked_vec::<u8>(ps, 0, Some(1));
+            ok &= r.is_ok_and(|v| v.locked_bytes() == ps);
+            ok
+        });
+    }
+    // pages of a fresh anonymous mapping are not resident until written
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lock_on_fault_test() {
+        let ps = page_size::get();
+        let v = crate::page_locked_on_fault_vec::<u8>(16 * ps, 0, Some(1));
+        assert_eq!(v.locked_bytes(), 16 * ps);
+        assert_eq!(crate::resident_pages(&v), 16);
+        let len = 64 * ps;
+        unsafe {
+            let p = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(p, libc::MAP_FAILED);
+            // never dropped, the mapping is released with `munmap`
+            let mut v = std::mem::ManuallyDrop::new(AlignedVec::from_raw_parts_in(
+                std::ptr::NonNull::new(p as *mut u8).unwrap(),
+                len,
+                len,
+                std::alloc::Layout::from_size_align(len, ps).unwrap(),
+                crate::Backend::Rust,
+            ));
+            assert_eq!(lock_pages_on_fault(&v).unwrap(), len);
+            assert_eq!(crate::resident_pages(&v), 0);
+            for i in 0..8 {
+                v[i * 8 * ps] = 1;
+            }
+            assert_eq!(crate::resident_pages(&v), 8);
+            sys::unlock(p, len).unwrap();
+            assert_eq!(libc::munmap(p, len), 0);
+        }
+    }
+    #[test]
+    fn unlock_test() {
+        let v = crate::page_aligned_vec::<u8>(page_size::get(), 0, Some(1), true);
+        unsafe {
+            sys::unlock(v.as_ptr() as *const c_void, page_size::get()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,475 @@
+//! Buffers backed by anonymous memory mappings.
+//!
+//! Memory obtained with `mmap` cannot be released through the global
+//! allocator, `MmapVec` unmaps it when dropped.
+use crate::AllocError;
+use nix::sys::mman::{MRemapFlags, MapFlags, ProtFlags};
+use std::alloc::Layout;
+use std::ffi::c_void;
+use std::ops::{Index, IndexMut};
+use std::ptr::NonNull;
+use std::slice::SliceIndex;
+
+//-----------------------------------------------------------------------------
+/// Buffer of `T` elements stored in an anonymous memory mapping, the mapping is
+/// removed when the value is dropped.
+pub struct MmapVec<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+    // size in bytes of the mapping
+    map_len: usize,
+    // alignment of the mapping, the size of its pages
+    align: usize,
+    // huge page flags of the mapping
+    flags: MapFlags,
+}
+
+impl<T> MmapVec<T> {
+    /// Empty buffer, no memory is mapped. Memory mapped when growing it is
+    /// aligned to `align` and mapped with `flags`.
+    pub(crate) fn empty(align: usize, flags: MapFlags) -> Self {
+        Self {
+            // dangling pointer aligned to `align`, as for an empty `AlignedVec`
+            ptr: NonNull::new(std::ptr::without_provenance_mut(align)).unwrap(),
+            len: 0,
+            // zero-sized elements never need a mapping
+            capacity: if std::mem::size_of::<T>() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
+            map_len: 0,
+            align,
+            flags,
+        }
+    }
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Return `true` if the buffer contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Size in bytes of the memory mapping.
+    pub fn mapped_bytes(&self) -> usize {
+        self.map_len
+    }
+    /// Pointer to the first element.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+    /// Mutable pointer to the first element.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+    /// View elements as slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+    /// View elements as mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+    /// Grow the buffer to `size` elements, nothing is done if `size` is not
+    /// larger than the length. A mapping too small is resized with `mremap`,
+    /// which may move it without copying its physical pages. The elements are
+    /// copied to a new mapping only if the buffer was empty, if the kernel
+    /// cannot resize the mapping or if it moved it to a misaligned address.
+    /// When `touch` is not `None` one element per page of the new elements is
+    /// initialised with the passed value, the pages already touched are left
+    /// alone.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if mapping memory fails.
+    pub fn grow(&mut self, size: usize, touch: Option<T>)
+    where
+        T: Copy,
+    {
+        self.try_grow(size, touch)
+            .unwrap_or_else(|e| panic!("MmapVec::grow: {e}"))
+    }
+    /// Fallible version of [`MmapVec::grow`], returns
+    /// [`AllocError::OutOfMemory`] or, for huge pages, [`AllocError::Syscall`]
+    /// if mapping memory fails. The
+    /// buffer is left unchanged on failure.
+    pub fn try_grow(&mut self, size: usize, touch: Option<T>) -> Result<(), AllocError>
+    where
+        T: Copy,
+    {
+        if size <= self.len {
+            return Ok(());
+        }
+        if size > self.capacity {
+            let (layout, capacity) = crate::aligned_layout::<T>(size, 0, self.align)?;
+            let map_len = layout.pad_to_align().size();
+            let remapped = match self.map_len {
+                0 => None,
+                len => unsafe {
+                    nix::sys::mman::mremap(
+                        self.ptr.as_ptr() as *mut c_void,
+                        len,
+                        map_len,
+                        MRemapFlags::MREMAP_MAYMOVE,
+                        None,
+                    )
+                }
+                .ok(),
+            };
+            let moved = remapped.map(|p| NonNull::new(p as *mut T).expect("mremap returned null"));
+            match moved {
+                Some(p) if p.as_ptr().align_offset(self.align) == 0 => {
+                    #[cfg(feature = "stats")]
+                    {
+                        crate::stats::record_free(self.map_len);
+                        crate::stats::record_alloc(map_len);
+                    }
+                    self.ptr = p;
+                    self.map_len = map_len;
+                }
+                _ => {
+                    let mut v = match map_anonymous::<T>(self.len, capacity, layout, self.flags) {
+                        Ok(v) => v,
+                        Err(errno) => {
+                            if let Some(p) = moved {
+                                self.remap_back(p, map_len);
+                            }
+                            return Err(self.map_error(layout, errno));
+                        }
+                    };
+                    let src = moved.unwrap_or(self.ptr);
+                    unsafe {
+                        std::ptr::copy_nonoverlapping(src.as_ptr(), v.as_mut_ptr(), self.len);
+                    }
+                    if let Some(p) = moved {
+                        // the original mapping is gone, only the misaligned
+                        // one is unmapped
+                        let _ =
+                            unsafe { nix::sys::mman::munmap(p.as_ptr() as *mut c_void, map_len) };
+                        #[cfg(feature = "stats")]
+                        crate::stats::record_free(self.map_len);
+                        self.map_len = 0;
+                    }
+                    // `T` is `Copy`, the old buffer is only unmapped
+                    *self = v;
+                }
+            }
+            self.capacity = capacity;
+        }
+        let len = std::mem::replace(&mut self.len, size);
+        if let Some(x) = touch {
+            let align = self.align;
+            crate::touch_strided(&mut self.as_mut_slice()[len..], x, align);
+        }
+        Ok(())
+    }
+    // Move the mapping of `map_len` bytes `mremap` placed at the misaligned
+    // address `p` back to the address and size of the buffer. Should that fail
+    // the buffer keeps the mapping at `p`, its elements are not lost.
+    fn remap_back(&mut self, p: NonNull<T>, map_len: usize) {
+        let r = unsafe {
+            nix::sys::mman::mremap(
+                p.as_ptr() as *mut c_void,
+                map_len,
+                self.map_len,
+                MRemapFlags::MREMAP_MAYMOVE | MRemapFlags::MREMAP_FIXED,
+                Some(self.ptr.as_ptr() as *mut c_void),
+            )
+        };
+        if r.is_err() {
+            #[cfg(feature = "stats")]
+            {
+                crate::stats::record_free(self.map_len);
+                crate::stats::record_alloc(map_len);
+            }
+            self.ptr = p;
+            self.map_len = map_len;
+        }
+    }
+    // Error returned when mapping `layout` failed with `errno`.
+    fn map_error(&self, layout: Layout, errno: i32) -> AllocError {
+        if self.flags.contains(MapFlags::MAP_HUGETLB) {
+            AllocError::Syscall {
+                op: "mmap",
+                source: std::io::Error::from_raw_os_error(errno),
+            }
+        } else {
+            AllocError::OutOfMemory { layout }
+        }
+    }
+}
+
+// The mapping belongs to the buffer alone and is remapped or unmapped only
+// through `&mut self` or on drop, mappings are not tied to a thread.
+unsafe impl<T: Send> Send for MmapVec<T> {}
+unsafe impl<T: Sync> Sync for MmapVec<T> {}
+
+impl<T> Drop for MmapVec<T> {
+    fn drop(&mut self) {
+        unsafe {
+            std::ptr::drop_in_place(self.as_mut_slice());
+            if self.map_len != 0 {
+                let _ = nix::sys::mman::munmap(self.ptr.as_ptr() as *mut c_void, self.map_len);
+                #[cfg(feature = "stats")]
+                crate::stats::record_free(self.map_len);
+            }
+        }
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for MmapVec<T> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for MmapVec<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Return a buffer backed by huge pages of `huge_page_size` bytes, aligned to
+/// the huge page size. When `touch` is not `None` one element per huge page is
+/// initialised with the passed value.
+///
+/// Requires huge pages to be reserved by the kernel, see
+/// `/proc/sys/vm/nr_hugepages`.
+///
+/// # Panics
+///
+/// Panics if the huge page size is not supported or if the mapping fails.
+pub fn huge_page_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    huge_page_size: usize,
+    touch: Option<T>,
+) -> MmapVec<T> {
+    try_huge_page_aligned_vec(size, capacity, huge_page_size, touch)
+        .unwrap_or_else(|e| panic!("huge_page_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`huge_page_aligned_vec`], returns
+/// [`AllocError::Syscall`] if the kernel cannot provide the huge pages,
+/// usually because not enough are reserved.
+pub fn try_huge_page_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    huge_page_size: usize,
+    touch: Option<T>,
+) -> Result<MmapVec<T>, AllocError> {
+    let size_flag =
+        huge_page_flag(huge_page_size).ok_or(AllocError::InvalidHugePageSize { huge_page_size })?;
+    let flags = MapFlags::MAP_HUGETLB | size_flag;
+    if size == 0 || std::mem::size_of::<T>() == 0 {
+        let mut v = MmapVec::empty(huge_page_size, flags);
+        v.len = size;
+        return Ok(v);
+    }
+    let (layout, capacity) = crate::aligned_layout::<T>(size, capacity, huge_page_size)?;
+    let mut v =
+        map_anonymous::<T>(size, capacity, layout, flags).map_err(|errno| AllocError::Syscall {
+            op: "mmap",
+            source: std::io::Error::from_raw_os_error(errno),
+        })?;
+    if let Some(x) = touch {
+        crate::touch_strided(v.as_mut_slice(), x, huge_page_size);
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return a page aligned buffer stored in an anonymous memory mapping. When
+/// `touch` is not `None` the mapping is created with `MAP_POPULATE`, all pages
+/// are faulted in by the kernel in a single call, much faster than touching
+/// them one by one, and one element per page is then initialised with the
+/// passed value.
+///
+/// # Panics
+///
+/// Panics if the size in bytes overflows or if the mapping fails.
+pub fn mmap_aligned_vec<T: Copy>(size: usize, capacity: usize, touch: Option<T>) -> MmapVec<T> {
+    try_mmap_aligned_vec(size, capacity, touch).unwrap_or_else(|e| panic!("mmap_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`mmap_aligned_vec`], returns
+/// [`AllocError::OutOfMemory`] if the mapping fails.
+pub fn try_mmap_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> Result<MmapVec<T>, AllocError> {
+    let ps = crate::cached_page_size();
+    if size == 0 || std::mem::size_of::<T>() == 0 {
+        let mut v = MmapVec::empty(ps, MapFlags::empty());
+        v.len = size;
+        return Ok(v);
+    }
+    let flags = if touch.is_some() {
+        MapFlags::MAP_POPULATE
+    } else {
+        MapFlags::empty()
+    };
+    let (layout, capacity) = crate::aligned_layout::<T>(size, capacity, ps)?;
+    let mut v = map_anonymous::<T>(size, capacity, layout, flags)
+        .map_err(|_| AllocError::OutOfMemory { layout })?;
+    if let Some(x) = touch {
+        crate::touch_pages(v.as_mut_slice(), x);
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Map `size` elements with room for `capacity` elements, `layout` rounded up
+/// to its alignment is the length of the mapping. `flags` are added to the
+/// anonymous private mapping flags, returns the `errno` of a failed `mmap`.
+/// The buffer grows with the same flags, `MAP_POPULATE` excepted.
+fn map_anonymous<T>(
+    size: usize,
+    capacity: usize,
+    layout: Layout,
+    flags: MapFlags,
+) -> Result<MmapVec<T>, i32> {
+    // whole number of pages
+    let map_len = layout.pad_to_align().size();
+    let ptr = unsafe {
+        nix::sys::mman::mmap(
+            std::ptr::null_mut(),
+            map_len,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_PRIVATE | MapFlags::MAP_ANONYMOUS | flags,
+            -1,
+            0,
+        )
+    }
+    .map_err(|e| e as i32)?;
+    #[cfg(feature = "stats")]
+    crate::stats::record_alloc(map_len);
+    Ok(MmapVec {
+        ptr: NonNull::new(ptr as *mut T).expect("mmap returned null"),
+        len: size,
+        capacity,
+        map_len,
+        align: layout.align(),
+        flags: flags - MapFlags::MAP_POPULATE,
+    })
+}
+//-----------------------------------------------------------------------------
+/// `mmap` flag selecting the huge page size.
+fn huge_page_flag(huge_page_size: usize) -> Option<MapFlags> {
+    let flags = [
+        (64 << 10, MapFlags::MAP_HUGE_64KB),
+        (512 << 10, MapFlags::MAP_HUGE_512KB),
+        (1 << 20, MapFlags::MAP_HUGE_1MB),
+        (2 << 20, MapFlags::MAP_HUGE_2MB),
+        (8 << 20, MapFlags::MAP_HUGE_8MB),
+        (16 << 20, MapFlags::MAP_HUGE_16MB),
+        (32 << 20, MapFlags::MAP_HUGE_32MB),
+        (256 << 20, MapFlags::MAP_HUGE_256MB),
+        (512 << 20, MapFlags::MAP_HUGE_512MB),
+        (1 << 30, MapFlags::MAP_HUGE_1GB),
+        (2 << 30, MapFlags::MAP_HUGE_2GB),
+        (16 << 30, MapFlags::MAP_HUGE_16GB),
+    ];
+    flags
+        .iter()
+        .find(|(s, _)| *s == huge_page_size)
+        .map(|(_, f)| *f)
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn invalid_huge_page_size_test() {
+        let r = try_huge_page_aligned_vec::<u8>(16, 0, 3 << 20, None);
+        assert!(matches!(
+            r,
+            Err(AllocError::InvalidHugePageSize {
+                huge_page_size: 0x30_0000
+            })
+        ));
+    }
+    // number of resident pages of `v`
+    fn resident<T>(v: &MmapVec<T>) -> usize {
+        let ps = page_size::get();
+        let mut pages = vec![0u8; v.mapped_bytes() / ps];
+        let r = unsafe {
+            libc::mincore(
+                v.as_ptr() as *mut libc::c_void,
+                v.mapped_bytes(),
+                pages.as_mut_ptr(),
+            )
+        };
+        assert_eq!(r, 0);
+        pages.iter().filter(|&&p| p & 1 != 0).count()
+    }
+    #[test]
+    fn mmap_aligned_vec_test() {
+        let ps = page_size::get();
+        let v = mmap_aligned_vec::<u32>(64 * ps, 0, Some(3));
+        assert_eq!(v.as_ptr() as usize % ps, 0);
+        assert_eq!(v.mapped_bytes(), 64 * ps * 4);
+        assert_eq!(resident(&v), 256);
+        assert_eq!(v[ps / 4], 3);
+        let v = mmap_aligned_vec::<u32>(64 * ps, 0, None);
+        assert!(resident(&v) < 256);
+        assert!(mmap_aligned_vec::<u32>(0, 0, Some(1)).is_empty());
+    }
+    #[test]
+    fn grow_test() {
+        let ps = page_size::get();
+        // 4 MiB and 16 MiB of `u64`
+        let (size, grown) = (1 << 19, 1 << 21);
+        let mut v = mmap_aligned_vec::<u64>(size, 0, None);
+        v.as_mut_slice()
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, x)| *x = i as u64);
+        v.grow(grown, Some(7));
+        assert_eq!(v.as_ptr() as usize % ps, 0);
+        assert_eq!(v.len(), grown);
+        assert!(v.capacity() >= grown);
+        assert_eq!(v.mapped_bytes(), 16 << 20);
+        assert!(v[..size].iter().enumerate().all(|(i, &x)| x == i as u64));
+        // one element per new page is touched
+        assert_eq!(v[size], 7);
+        assert_eq!(v[grown - ps / 8], 7);
+        v.as_mut_slice()[size..].fill(9);
+        assert_eq!(resident(&v), (16 << 20) / ps);
+        // smaller than the length, nothing changes
+        v.grow(10, Some(1));
+        assert_eq!(v.len(), grown);
+        // nothing mapped yet, a mapping is created
+        let mut v = mmap_aligned_vec::<u32>(0, 0, None);
+        v.grow(3 * ps, Some(2));
+        assert_eq!(v.as_ptr() as usize % ps, 0);
+        assert_eq!(v.mapped_bytes(), 12 * ps);
+        assert_eq!(v[3 * ps - 1], 0);
+        assert_eq!(v[ps], 2);
+        let r = v.try_grow(usize::MAX, None);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        assert_eq!(v.len(), 3 * ps);
+    }
+    // requires reserved huge pages, e.g. `echo 8 > /proc/sys/vm/nr_hugepages`
+    #[test]
+    fn huge_page_aligned_vec_test() {
+        if std::env::var_os("ALIGNED_VEC_TEST_HUGE_PAGES").is_none() {
+            return;
+        }
+        let hps = 2 << 20;
+        let size = 3 * hps;
+        let v = huge_page_aligned_vec::<u8>(size, 0, hps, Some(1));
+        assert_eq!(v.as_ptr() as usize % hps, 0);
+        assert_eq!(v.len(), size);
+        assert_eq!(v.mapped_bytes(), size);
+        assert_eq!(v[hps], 1);
+    }
+}
@@ -0,0 +1,284 @@
+//! NUMA-aware allocation.
+//!
+//! Pages are bound to a node with `mbind` and, when requested, touched so that
+//! they are faulted in on that node right away. Alternatively pages are
+//! spread round-robin across all the nodes by touching them from one thread
+//! pinned to each node, relying on the first-touch placement policy.
+use crate::{AlignedVec, AllocError};
+use std::ffi::{c_long, c_ulong};
+
+// From <linux/mempolicy.h>, not exported by `libc`.
+const MPOL_MF_MOVE: c_ulong = 1 << 1;
+// Maximum number of nodes supported by the node mask.
+const MAX_NODES: usize = 1024;
+const MASK_WORDS: usize = MAX_NODES / c_ulong::BITS as usize;
+
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` whose pages are bound to NUMA node `node`.
+/// When `touch` is not `None` one element per page is initialised with the
+/// passed value so that all pages are allocated on the node immediately.
+///
+/// The effective alignment is at least the page size, as required by `mbind`,
+/// and the capacity is rounded up to whole pages so that binding them leaves
+/// the neighbouring allocations alone.
+///
+/// # Panics
+///
+/// Panics if allocation or binding fails.
+pub fn numa_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    node: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    try_numa_aligned_vec(size, capacity, align, node, touch)
+        .unwrap_or_else(|e| panic!("numa_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`numa_aligned_vec`], returns
+/// [`AllocError::InvalidNumaNode`] if the node does not exist and
+/// [`AllocError::Syscall`] if the pages cannot be bound.
+pub fn try_numa_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    node: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    if node >= MAX_NODES || !node_exists(node) {
+        return Err(AllocError::InvalidNumaNode { node });
+    }
+    let ps = crate::cached_page_size();
+    let mut v = crate::try_capacity_aligned_vec::<T>(size, capacity, align.max(ps), ps, None)?;
+    if v.capacity() == 0 {
+        return Ok(v);
+    }
+    let mut mask: [c_ulong; MASK_WORDS] = [0; MASK_WORDS];
+    mask[node / c_ulong::BITS as usize] |= 1 << (node % c_ulong::BITS as usize);
+    // bind the whole allocation, made of whole pages
+    let len = v.allocated_bytes();
+    let r = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            v.as_mut_ptr(),
+            len,
+            libc::MPOL_BIND as c_long,
+            mask.as_ptr(),
+            // the kernel ignores the last bit of the mask
+            (MAX_NODES + 1) as c_ulong,
+            MPOL_MF_MOVE,
+        )
+    };
+    if r != 0 {
+        return Err(AllocError::Syscall {
+            op: "mbind",
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    if let Some(x) = touch {
+        crate::touch_pages(v.as_mut_slice(), x);
+    }
+    Ok(v)
+}
+//-----------------------------------------------------------------------------
+/// Return an aligned `AlignedVec` with its pages spread round-robin across
+/// the NUMA nodes: page `i` is touched with `touch` by a thread pinned to the
+/// CPUs of node `i % nodes`, so that under the default first-touch policy it
+/// is placed on that node. A single thread touching a large buffer would
+/// place all of it on its own node. On single node systems pages are touched
+/// by the calling thread.
+///
+/// The effective alignment is at least the page size, so that no page is
+/// shared with other allocations.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if allocation fails or
+/// if a thread cannot be pinned.
+pub fn interleaved_aligned_vec<T: Copy + Send + Sync>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+) -> AlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_interleaved_aligned_vec(size, capacity, align, touch)
+        .unwrap_or_else(|e| panic!("interleaved_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`interleaved_aligned_vec`], returns
+/// [`AllocError::Syscall`] if a thread cannot be pinned to the CPUs
+/// of a node.
+pub fn try_interleaved_aligned_vec<T: Copy + Send + Sync>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: T,
+) -> Result<AlignedVec<T>, AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlignment { align });
+    }
+    // pages are placed by the kernel, the stride is its page size even when
+    // the touch stride is overridden
+    let ps = crate::cached_page_size();
+    let mut v = crate::try_aligned_vec::<T>(size, capacity, align.max(ps), None)?;
+    // nodes with CPUs, memory-only nodes cannot run a touching thread
+    let cpus: Vec<Vec<usize>> = (0..MAX_NODES)
+        .filter(|&n| node_exists(n))
+        .filter_map(node_cpus)
+        .filter(|c| !c.is_empty())
+        .collect();
+    if cpus.len() < 2 {
+        crate::touch_pages(v.as_mut_slice(), touch);
+        return Ok(v);
+    }
+    let nodes = cpus.len();
+    let elements = SendPtr(v.as_mut_ptr());
+    let indices = |k| {
+        crate::stride_indices(v.as_slice(), ps)
+            .enumerate()
+            .filter(move |(page, _)| page % nodes == k)
+            .map(|(_, i)| i)
+    };
+    std::thread::scope(|s| {
+        let threads: Vec<_> = cpus
+            .iter()
+            .enumerate()
+            .map(|(k, cpus)| {
+                let indices = indices(k);
+                let elements = &elements;
+                s.spawn(move || {
+                    pin_to(cpus)?;
+                    for i in indices {
+                        // each page is written by a single thread
+                        unsafe { elements.0.add(i).write(touch) };
+                    }
+                    Ok::<_, AllocError>(())
+                })
+            })
+            .collect();
+        threads.into_iter().try_for_each(|t| {
+            t.join()
+                .expect("interleaved_aligned_vec: touching thread panicked")
+        })
+    })?;
+    Ok(v)
+}
+// Pointer to the elements shared by the touching threads, which write
+// disjoint elements.
+struct SendPtr<T>(*mut T);
+unsafe impl<T: Send> Sync for SendPtr<T> {}
+//-----------------------------------------------------------------------------
+/// Pin the calling thread to `cpus`.
+fn pin_to(cpus: &[usize]) -> Result<(), AllocError> {
+    // node CPUs are parsed within the size of `cpu_set_t`
+    let set = crate::affinity::cpu_set(cpus)?;
+    crate::affinity::set_affinity(&set)
+}
+//-----------------------------------------------------------------------------
+/// CPUs of `node`, `None` if they cannot be read.
+fn node_cpus(node: usize) -> Option<Vec<usize>> {
+    let path = format!("/sys/devices/system/node/node{node}/cpulist");
+    parse_cpu_list(std::fs::read_to_string(path).ok()?.trim())
+}
+//-----------------------------------------------------------------------------
+/// Parse a kernel CPU list such as `0-3,8,10-11`, CPUs past the size of
+/// `cpu_set_t` are skipped.
+fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+    let mut cpus = Vec::new();
+    for range in list.split(',').filter(|r| !r.is_empty()) {
+        let (first, last): (usize, usize) = match range.split_once('-') {
+            Some((a, b)) => (a.parse().ok()?, b.parse().ok()?),
+            None => {
+                let c = range.parse().ok()?;
+                (c, c)
+            }
+        };
+        cpus.extend((first..=last).filter(|&c| c < max));
+    }
+    Some(cpus)
+}
+//-----------------------------------------------------------------------------
+/// Return `true` if the node is known to the kernel.
+fn node_exists(node: usize) -> bool {
+    std::path::Path::new(&format!("/sys/devices/system/node/node{node}")).exists()
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // From <linux/mempolicy.h>.
+    const MPOL_F_NODE: c_ulong = 1 << 0;
+    const MPOL_F_ADDR: c_ulong = 1 << 1;
+    // Return the node holding the page at `addr`.
+    fn node_of(addr: *const u8) -> usize {
+        let mut node: libc::c_int = -1;
+        let r = unsafe {
+            libc::syscall(
+                libc::SYS_get_mempolicy,
+                &mut node as *mut libc::c_int,
+                std::ptr::null_mut::<c_ulong>(),
+                0 as c_ulong,
+                addr,
+                MPOL_F_NODE | MPOL_F_ADDR,
+            )
+        };
+        assert_eq!(r, 0);
+        node as usize
+    }
+    #[test]
+    fn invalid_numa_node_test() {
+        let r = try_numa_aligned_vec::<u8>(16, 0, 64, MAX_NODES, None);
+        assert!(matches!(r, Err(AllocError::InvalidNumaNode { .. })));
+    }
+    #[test]
+    fn parse_cpu_list_test() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+    #[test]
+    fn interleaved_aligned_vec_test() {
+        let ps = page_size::get();
+        let v = interleaved_aligned_vec::<u8>(64 * ps + 10, 0, 64, 7);
+        assert!(v.is_aligned_to(ps));
+        assert!((0..v.len()).step_by(ps).all(|i| v[i] == 7));
+        assert_eq!(v[64 * ps], 7);
+        let nodes = (0..MAX_NODES)
+            .filter(|&n| node_exists(n) && node_cpus(n).is_some_and(|c| !c.is_empty()))
+            .count();
+        if nodes < 2 {
+            return;
+        }
+        let mut placed = std::collections::BTreeSet::new();
+        for i in (0..v.len()).step_by(ps) {
+            placed.insert(node_of(&v[i]));
+        }
+        assert!(placed.len() >= 2);
+    }
+    #[test]
+    fn numa_aligned_vec_test() {
+        let ps = page_size::get();
+        let v = numa_aligned_vec::<u8>(10, 0, 64, 0, None);
+        assert_eq!((v.capacity(), v.allocated_bytes()), (ps, ps));
+        let nodes = (0..MAX_NODES).filter(|&n| node_exists(n)).count();
+        if nodes < 2 {
+            return;
+        }
+        let v = numa_aligned_vec::<u8>(16 * ps, 0, 64, 0, Some(1));
+        assert_eq!(v.as_ptr() as usize % ps, 0);
+        for i in (0..v.len()).step_by(ps) {
+            assert_eq!(node_of(&v[i]), 0);
+        }
+    }
+}
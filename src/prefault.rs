@@ -0,0 +1,70 @@
+//! Faulting in the pages of a buffer on a background thread.
+//!
+//! The buffer is moved to the thread and handed back by
+//! [`PrefaultHandle::join`], it cannot be accessed or released while its pages
+//! are being touched.
+use crate::AlignedVec;
+use std::thread::JoinHandle;
+
+//-----------------------------------------------------------------------------
+/// Buffer whose pages are being faulted in by a background thread, returned
+/// by [`AlignedVec::prefault_async`].
+pub struct PrefaultHandle<T> {
+    thread: JoinHandle<AlignedVec<T>>,
+}
+
+impl<T> PrefaultHandle<T> {
+    /// Return `true` once all the pages have been faulted in, without
+    /// blocking.
+    pub fn is_done(&self) -> bool {
+        self.thread.is_finished()
+    }
+    /// Wait for all the pages to be faulted in and return the buffer.
+    pub fn join(self) -> AlignedVec<T> {
+        self.thread
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+impl<T: Copy + Send + 'static> AlignedVec<T> {
+    /// Fault in the pages holding the elements on a background thread, see
+    /// [`crate::force_commit`], so that a large buffer is committed off the
+    /// critical path. The contents are unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the thread cannot be spawned.
+    pub fn prefault_async(mut self) -> PrefaultHandle<T> {
+        let thread = std::thread::Builder::new()
+            .name("aligned_vec prefault".into())
+            .spawn(move || {
+                crate::force_commit(self.as_mut_slice());
+                self
+            })
+            .unwrap_or_else(|e| panic!("AlignedVec::prefault_async: {e}"));
+        PrefaultHandle { thread }
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, unix))]
+mod tests {
+    #[test]
+    fn prefault_async_test() {
+        let ps = page_size::get();
+        let mut v = crate::aligned_vec::<u64>(8 << 20, 0, ps, None);
+        v[1] = 3;
+        assert!(crate::resident_pages(&v) < (64 << 20) / ps);
+        let handle = v.prefault_async();
+        let v = handle.join();
+        assert_eq!(crate::resident_pages(&v), (64 << 20) / ps);
+        assert_eq!(v[1], 3);
+        assert_eq!(v.len(), 8 << 20);
+        let handle = crate::aligned_vec::<u8>(0, 0, 64, None).prefault_async();
+        while !handle.is_done() {
+            std::thread::yield_now();
+        }
+        assert!(handle.join().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! Registry of live allocations, used to catch double frees and layout
+//! mismatches in debug builds with the `alloc_registry` feature.
+//!
+//! Every allocation made by a [`crate::Backend`] is recorded with its layout
+//! and checked when released, before the memory is handed back to the
+//! allocator.
+use std::alloc::Layout;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Layout of each live allocation, indexed by address.
+static LIVE: Mutex<BTreeMap<usize, Layout>> = Mutex::new(BTreeMap::new());
+
+//-----------------------------------------------------------------------------
+/// Record the allocation of `layout` at `ptr`.
+pub(crate) fn register(ptr: *mut u8, layout: Layout) {
+    if !ptr.is_null() {
+        live().insert(ptr as usize, layout);
+    }
+}
+//-----------------------------------------------------------------------------
+/// Remove the allocation at `ptr` from the registry.
+///
+/// # Panics
+///
+/// Panics if `ptr` is not a live allocation or was allocated with a different
+/// layout.
+pub(crate) fn unregister(ptr: *mut u8, layout: Layout) {
+    // the lock is released before panicking
+    let recorded = live().remove(&(ptr as usize));
+    match recorded {
+        None => panic!(
+            "double free or invalid free of {ptr:p} ({} bytes aligned to {})",
+            layout.size(),
+            layout.align()
+        ),
+        Some(l) if l != layout => panic!(
+            "layout mismatch freeing {ptr:p}: allocated {} bytes aligned to {}, \
+             freed as {} bytes aligned to {}",
+            l.size(),
+            l.align(),
+            layout.size(),
+            layout.align()
+        ),
+        Some(_) => {}
+    }
+}
+//-----------------------------------------------------------------------------
+/// Locked registry, a poisoned lock is recovered since the map is always left
+/// consistent.
+fn live() -> std::sync::MutexGuard<'static, BTreeMap<usize, Layout>> {
+    LIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use crate::Backend;
+    use std::alloc::Layout;
+    #[test]
+    #[should_panic(expected = "double free")]
+    fn double_free_test() {
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            let p = Backend::Rust.alloc(layout, false);
+            Backend::Rust.dealloc(p, layout);
+            // the registry panics before the memory is released again
+            Backend::Rust.dealloc(p, layout);
+        }
+    }
+    #[test]
+    #[should_panic(expected = "layout mismatch")]
+    fn layout_mismatch_test() {
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        unsafe {
+            let p = Backend::Rust.alloc(layout, false);
+            Backend::Rust.dealloc(p, Layout::from_size_align(64, 8).unwrap());
+        }
+    }
+}
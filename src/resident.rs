@@ -0,0 +1,69 @@
+//! Page residency queries with `mincore`.
+use crate::AlignedVec;
+use std::ffi::c_void;
+
+//-----------------------------------------------------------------------------
+/// Return the number of pages spanned by the elements of `v` that are
+/// currently resident in RAM. The range starts at the page holding the first
+/// element and ends at the end of the page holding the last element, pages
+/// shared with neighbouring allocations are counted.
+///
+/// Useful to check that locking or touching faulted all the pages in.
+///
+/// # Panics
+///
+/// Panics if `mincore` fails.
+pub fn resident_pages<T>(v: &AlignedVec<T>) -> usize {
+    let ps = crate::cached_page_size();
+    let bytes = v.len() * std::mem::size_of::<T>();
+    if bytes == 0 {
+        return 0;
+    }
+    let start = v.as_ptr() as usize / ps * ps;
+    let end = (v.as_ptr() as usize + bytes).div_ceil(ps) * ps;
+    let mut pages = vec![0u8; (end - start) / ps];
+    let r = unsafe {
+        // round the pointer down without losing its provenance
+        let p = (v.as_ptr() as *const u8).sub(v.as_ptr() as usize - start);
+        libc::mincore(p as *mut c_void, end - start, pages.as_mut_ptr() as *mut _)
+    };
+    assert_eq!(r, 0, "mincore: {}", std::io::Error::last_os_error());
+    // the least significant bit is set for resident pages
+    pages.iter().filter(|&&p| p & 1 != 0).count()
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn resident_pages_test() {
+        let ps = page_size::get();
+        let v = crate::page_locked_vec::<u8>(8 * ps, 0, Some(1));
+        assert_eq!(resident_pages(&v), 8);
+        // above the largest mmap threshold of glibc, never reused heap memory
+        let v = crate::aligned_vec::<u8>(64 << 20, 0, ps, None);
+        assert!(resident_pages(&v) < (64 << 20) / ps);
+        let v = crate::aligned_vec::<u8>(0, 0, ps, None);
+        assert_eq!(resident_pages(&v), 0);
+    }
+    #[test]
+    fn force_commit_test() {
+        let ps = page_size::get();
+        let spanned = |start: usize, bytes: usize| (start + bytes).div_ceil(ps) - start / ps;
+        // large enough to be mapped fresh, pages not yet faulted in
+        let mut v = crate::zeroed_aligned_vec::<u64>(5 << 20, 0, 64);
+        crate::force_commit(v.as_mut_slice());
+        assert_eq!(resident_pages(&v), spanned(v.as_ptr() as usize, 5 << 23));
+        assert!(v.as_slice().iter().all(|&x| x == 0));
+        // elements straddle page boundaries and the buffer ends in a partial
+        // page
+        let mut v = crate::zeroed_aligned_vec::<[u8; 3]>((40 << 20) / 3 + 1, 0, 1);
+        crate::force_commit(v.as_mut_slice());
+        assert_eq!(
+            resident_pages(&v),
+            spanned(v.as_ptr() as usize, v.len() * 3)
+        );
+        assert!(v.as_slice().iter().all(|&x| x == [0; 3]));
+    }
+}
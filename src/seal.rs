@@ -0,0 +1,151 @@
+//! Read-only sealing of initialised buffers with `mprotect`.
+//!
+//! A lookup table filled once can be made read-only so that accidental writes
+//! fault instead of silently corrupting it. Protection applies to whole pages,
+//! the buffer must start on a page boundary and is made writable again before
+//! its memory is released.
+use crate::{AlignedVec, AllocError};
+use nix::sys::mman::ProtFlags;
+use std::ffi::c_void;
+use std::ops::Deref;
+
+//-----------------------------------------------------------------------------
+/// Read-only `AlignedVec`, created with [`seal_readonly`]. Only shared access
+/// to the elements is possible, the pages are made writable again when the
+/// value is dropped.
+pub struct SealedAlignedVec<T> {
+    vec: AlignedVec<T>,
+    // number of protected bytes starting at the beginning of the buffer
+    sealed: usize,
+}
+
+impl<T> SealedAlignedVec<T> {
+    /// Number of bytes made read-only, a trailing partial page of the
+    /// allocation is left writable since it can be shared with other
+    /// allocations.
+    pub fn sealed_bytes(&self) -> usize {
+        self.sealed
+    }
+    /// View elements as slice.
+    pub fn as_slice(&self) -> &[T] {
+        self.vec.as_slice()
+    }
+    /// Make the pages writable again and return the buffer.
+    ///
+    /// Returns [`AllocError::Syscall`] if the protection cannot be
+    /// restored, the memory is leaked in that case: handing read-only pages
+    /// back to the allocator would crash when it writes to them.
+    pub fn unseal(self) -> Result<AlignedVec<T>, AllocError> {
+        let mut s = core::mem::ManuallyDrop::new(self);
+        let vec = unsafe { std::ptr::read(&s.vec) };
+        match unsafe { s.restore() } {
+            Ok(()) => Ok(vec),
+            Err(e) => {
+                std::mem::forget(vec);
+                Err(e)
+            }
+        }
+    }
+    /// Restore read and write access to the sealed pages.
+    unsafe fn restore(&mut self) -> Result<(), AllocError> {
+        protect(
+            self.vec.as_ptr() as *mut c_void,
+            self.sealed,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        )?;
+        self.sealed = 0;
+        Ok(())
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Make the pages of `vec` read-only with `mprotect(PROT_READ)` and return a
+/// buffer granting only shared access to its elements. The whole pages of the
+/// allocation are protected; for a buffer to be protected in full its
+/// allocation size must be a multiple of the page size, see
+/// [`crate::capacity_aligned_vec`].
+///
+/// Returns [`AllocError::NotPageAligned`] if `vec` does not start on a page
+/// boundary and [`AllocError::Syscall`] if `mprotect` fails, `vec` is
+/// released in both cases. Empty buffers are sealed without system calls.
+pub fn seal_readonly<T>(vec: AlignedVec<T>) -> Result<SealedAlignedVec<T>, AllocError> {
+    let ps = crate::cached_page_size();
+    let sealed = vec.layout().size() / ps * ps;
+    if sealed != 0 {
+        if !(vec.as_ptr() as usize).is_multiple_of(ps) {
+            return Err(AllocError::NotPageAligned);
+        }
+        unsafe { protect(vec.as_ptr() as *mut c_void, sealed, ProtFlags::PROT_READ)? };
+    }
+    Ok(SealedAlignedVec { vec, sealed })
+}
+//-----------------------------------------------------------------------------
+/// `mprotect` `len` bytes at `ptr`, nothing is done if `len` is zero.
+unsafe fn protect(ptr: *mut c_void, len: usize, prot: ProtFlags) -> Result<(), AllocError> {
+    if len == 0 {
+        return Ok(());
+    }
+    nix::sys::mman::mprotect(ptr, len, prot).map_err(|e| AllocError::Syscall {
+        op: "mprotect",
+        source: e.into(),
+    })
+}
+
+impl<T> Drop for SealedAlignedVec<T> {
+    fn drop(&mut self) {
+        // elements may write to their memory when dropped, and so does the
+        // allocator when the memory is released
+        if unsafe { self.restore() }.is_err() {
+            let vec = core::mem::replace(&mut self.vec, AlignedVec::empty(1));
+            std::mem::forget(vec);
+        }
+    }
+}
+
+impl<T> Deref for SealedAlignedVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.vec.as_slice()
+    }
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn seal_readonly_test() {
+        let ps = page_size::get();
+        let v = crate::init_aligned_vec::<u32>(4 * ps / 4, 0, ps, 7);
+        let sealed = seal_readonly(v).unwrap();
+        assert_eq!(sealed.sealed_bytes(), 4 * ps);
+        assert_eq!(sealed.len(), ps);
+        assert!(sealed.iter().all(|&x| x == 7));
+        assert_eq!(sealed.as_slice()[ps - 1], 7);
+        // writes fault, checked in a child process
+        let status = crate::child::in_child(|| unsafe {
+            (sealed.as_ptr() as *mut u32).add(ps / 2).write_volatile(1);
+            true
+        });
+        assert!(libc::WIFSIGNALED(status));
+        assert!([libc::SIGSEGV, libc::SIGBUS].contains(&libc::WTERMSIG(status)));
+        // writable again once unsealed
+        let mut v = seal_readonly(sealed.unseal().unwrap())
+            .unwrap()
+            .unseal()
+            .unwrap();
+        v.as_mut_slice().fill(3);
+        v.push(4);
+        assert_eq!(v[ps], 4);
+        // dropped while sealed, the memory is made writable and released
+        let v = crate::from_iter_aligned((0..ps).map(|i| i.to_string()), ps);
+        let sealed = seal_readonly(v).unwrap();
+        assert!(sealed.sealed_bytes() >= ps);
+        drop(sealed);
+        drop(seal_readonly(crate::aligned_vec::<u8>(0, 0, ps, None)).unwrap());
+        let v = crate::aligned_vec::<u8>(ps, 0, 64, None);
+        if !(v.as_ptr() as usize).is_multiple_of(ps) {
+            assert!(matches!(seal_readonly(v), Err(AllocError::NotPageAligned)));
+        }
+    }
+}
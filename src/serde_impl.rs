@@ -0,0 +1,92 @@
+//! `serde` support, enabled by the `serde` feature.
+//!
+//! Buffers are serialized as sequences of their elements, the alignment is
+//! not part of the data. [`AlignedVec`] is deserialized into a buffer aligned
+//! to [`DESERIALIZE_ALIGN`] bytes, [`crate::AlignedVecN`] to its `ALIGN`.
+use crate::AlignedVec;
+use core::fmt;
+use core::marker::PhantomData;
+use serde::de::{Deserialize, Deserializer, Error, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+/// Alignment of the buffers returned when deserializing an [`AlignedVec`],
+/// the cache line size of most CPUs and the AVX-512 register width.
+pub const DESERIALIZE_ALIGN: usize = 64;
+
+// Elements reserved up front from the length announced by the input, more
+// are only allocated as they arrive.
+const MAX_PREALLOCATED_BYTES: usize = 1 << 20;
+
+impl<T: Serialize> Serialize for AlignedVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.as_slice())
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for AlignedVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut v =
+            crate::try_aligned_vec_with_capacity(0, DESERIALIZE_ALIGN).map_err(D::Error::custom)?;
+        deserialize_into(deserializer, &mut v)?;
+        Ok(v)
+    }
+}
+//-----------------------------------------------------------------------------
+/// Append the elements of a deserialized sequence to `vec`, which keeps its
+/// alignment.
+pub(crate) fn deserialize_into<'de, D, T>(
+    deserializer: D,
+    vec: &mut AlignedVec<T>,
+) -> Result<(), D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct Elements<'a, T>(&'a mut AlignedVec<T>, PhantomData<T>);
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for Elements<'_, T> {
+        type Value = ();
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a sequence")
+        }
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+            // the announced length is not trusted with large allocations
+            let hint = seq.size_hint().unwrap_or(0);
+            let cautious = MAX_PREALLOCATED_BYTES / core::mem::size_of::<T>().max(1);
+            self.0
+                .try_reserve(hint.min(cautious))
+                .map_err(A::Error::custom)?;
+            while let Some(x) = seq.next_element()? {
+                self.0.try_reserve(1).map_err(A::Error::custom)?;
+                self.0.push(x);
+            }
+            Ok(())
+        }
+    }
+    deserializer.deserialize_seq(Elements(vec, PhantomData))
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::{AlignedVec, AlignedVecN};
+    #[test]
+    fn serde_round_trip_test() {
+        let v = crate::aligned_vec_with(1000, 0, 4096, |i| i as u32 * 7);
+        let json = serde_json::to_string(&v).unwrap();
+        assert!(json.starts_with("[0,7,14,"));
+        let w: AlignedVec<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(w, v);
+        assert_eq!(w.alignment(), super::DESERIALIZE_ALIGN);
+        assert!(w.is_aligned_to(super::DESERIALIZE_ALIGN));
+        let n: AlignedVecN<u32, 4096> = serde_json::from_str(&json).unwrap();
+        assert!(n.is_aligned_to(4096));
+        assert_eq!(n.as_slice(), v.as_slice());
+        assert_eq!(serde_json::to_string(&n).unwrap(), json);
+        let s: AlignedVec<String> = serde_json::from_str(r#"["a","bc"]"#).unwrap();
+        assert_eq!(s.as_slice(), ["a", "bc"]);
+        let e: AlignedVec<u8> = serde_json::from_str("[]").unwrap();
+        assert!(e.is_empty());
+        assert!(serde_json::from_str::<AlignedVec<u8>>("[1, 256]").is_err());
+        assert!(serde_json::from_str::<AlignedVec<u8>>("{}").is_err());
+    }
+}
@@ -0,0 +1,58 @@
+//! Alignments required by SIMD instruction sets and matching constructors.
+//!
+//! Aligned loads and stores require the address to be a multiple of the
+//! register width.
+use crate::{aligned_vec, AlignedVec};
+
+/// Alignment of SSE (x86, 128 bit registers) aligned loads and stores.
+pub const SSE_ALIGN: usize = 16;
+/// Alignment of AVX and AVX2 (x86, 256 bit registers) aligned loads and stores.
+pub const AVX_ALIGN: usize = 32;
+/// Alignment of AVX-512 (x86, 512 bit registers) aligned loads and stores.
+pub const AVX512_ALIGN: usize = 64;
+/// Alignment of NEON (ARM, 128 bit registers) loads and stores.
+pub const NEON_ALIGN: usize = 16;
+
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` aligned to [`SSE_ALIGN`], see [`aligned_vec`].
+pub fn sse_aligned_vec<T: Copy>(size: usize, capacity: usize, touch: Option<T>) -> AlignedVec<T> {
+    aligned_vec(size, capacity, SSE_ALIGN, touch)
+}
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` aligned to [`AVX_ALIGN`], see [`aligned_vec`].
+pub fn avx_aligned_vec<T: Copy>(size: usize, capacity: usize, touch: Option<T>) -> AlignedVec<T> {
+    aligned_vec(size, capacity, AVX_ALIGN, touch)
+}
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` aligned to [`AVX512_ALIGN`], see [`aligned_vec`].
+pub fn avx512_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    aligned_vec(size, capacity, AVX512_ALIGN, touch)
+}
+//-----------------------------------------------------------------------------
+/// Return an `AlignedVec` aligned to [`NEON_ALIGN`], see [`aligned_vec`].
+pub fn neon_aligned_vec<T: Copy>(size: usize, capacity: usize, touch: Option<T>) -> AlignedVec<T> {
+    aligned_vec(size, capacity, NEON_ALIGN, touch)
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn simd_aligned_vec_test() {
+        let v = sse_aligned_vec::<u8>(100, 0, Some(1));
+        assert_eq!(v.as_ptr() as usize % SSE_ALIGN, 0);
+        let v = avx_aligned_vec::<u8>(100, 0, Some(1));
+        assert_eq!(v.as_ptr() as usize % AVX_ALIGN, 0);
+        let v = avx512_aligned_vec::<u8>(100, 0, Some(1));
+        assert_eq!(v.as_ptr() as usize % AVX512_ALIGN, 0);
+        let v = neon_aligned_vec::<u8>(100, 0, Some(1));
+        assert_eq!(v.as_ptr() as usize % NEON_ALIGN, 0);
+        assert!(avx_aligned_vec::<f32>(0, 0, None).is_empty());
+        assert!(avx512_aligned_vec::<f64>(0, 16, None).is_empty());
+    }
+}
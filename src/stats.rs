@@ -0,0 +1,98 @@
+//! Process-wide allocation statistics, enabled by the `stats` feature.
+//!
+//! The backends count the bytes they obtain and release, over-aligned blocks
+//! with their padding, the memory mapped buffers count their mappings and
+//! page-locked buffers are counted while alive.
+//! Counters are relaxed atomics, a snapshot taken while other threads allocate
+//! may mix values from before and after an allocation.
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static LOCKED: AtomicUsize = AtomicUsize::new(0);
+
+//-----------------------------------------------------------------------------
+/// Snapshot of the allocation statistics, returned by [`stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AllocStats {
+    /// Total number of bytes allocated, a reallocation counts its new size.
+    pub allocated_bytes: usize,
+    /// Number of bytes currently allocated.
+    pub live_bytes: usize,
+    /// Largest value reached by `live_bytes`.
+    pub peak_bytes: usize,
+    /// Number of live page-locked buffers.
+    pub locked_buffers: usize,
+}
+
+//-----------------------------------------------------------------------------
+/// Return the allocation statistics of the process.
+pub fn stats() -> AllocStats {
+    AllocStats {
+        allocated_bytes: ALLOCATED.load(Relaxed),
+        live_bytes: LIVE.load(Relaxed),
+        peak_bytes: PEAK.load(Relaxed),
+        locked_buffers: LOCKED.load(Relaxed),
+    }
+}
+//-----------------------------------------------------------------------------
+/// Count the allocation of `bytes` bytes.
+pub(crate) fn record_alloc(bytes: usize) {
+    ALLOCATED.fetch_add(bytes, Relaxed);
+    let live = LIVE.fetch_add(bytes, Relaxed) + bytes;
+    PEAK.fetch_max(live, Relaxed);
+}
+//-----------------------------------------------------------------------------
+/// Count the release of `bytes` bytes.
+pub(crate) fn record_free(bytes: usize) {
+    LIVE.fetch_sub(bytes, Relaxed);
+}
+//-----------------------------------------------------------------------------
+/// Count a page-locked buffer being created, `false`, or dropped, `true`.
+#[cfg(feature = "std")]
+pub(crate) fn record_locked(released: bool) {
+    if released {
+        LOCKED.fetch_sub(1, Relaxed);
+    } else {
+        LOCKED.fetch_add(1, Relaxed);
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, unix, feature = "std"))]
+mod tests {
+    use super::*;
+    // the counters are shared by all the tests, measured in a child process
+    // where no other thread allocates
+    #[test]
+    fn stats_test() {
+        crate::child::assert_in_child(|| {
+            let before = stats();
+            // larger than any usage so far, the peak is reached below
+            let big = before.peak_bytes - before.live_bytes + (4 << 20);
+            let a = crate::aligned_vec::<u8>(big, 0, 64, None);
+            let b = crate::aligned_vec::<u64>(500, 1000, 4096, Some(1));
+            let mut ok = stats().live_bytes == before.live_bytes + big + 8000;
+            drop(a);
+            let c = crate::aligned_vec_in::<u8>(50, 0, 1 << 20, None, crate::Backend::OverAligned);
+            drop((b, c));
+            let d = crate::page_locked_vec::<u8>(100, 0, None);
+            ok &= stats().locked_buffers == before.locked_buffers + 1;
+            drop(d);
+            // handed over to `Vec`, no longer counted
+            let e = crate::aligned_vec::<u64>(100, 0, 8, None);
+            ok &= stats().live_bytes == before.live_bytes + 800;
+            let e = e.try_into_std_vec_zero_copy();
+            ok &= e.is_ok() && stats().live_bytes == before.live_bytes;
+            drop(e);
+            let after = stats();
+            ok &= after.live_bytes == before.live_bytes;
+            ok &= after.locked_buffers == before.locked_buffers;
+            ok &= after.allocated_bytes >= before.allocated_bytes + big + 8000 + 50 + 100;
+            ok &= after.peak_bytes == before.live_bytes + big + 8000;
+            ok
+        });
+    }
+}
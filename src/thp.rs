@@ -0,0 +1,143 @@
+//! Touching at the fault granularity of transparent huge pages.
+//!
+//! When transparent huge pages are enabled for all anonymous memory the kernel
+//! backs every huge-page-aligned region of a mapping with a huge page on the
+//! first fault, one write per huge page then commits the whole region and the
+//! other writes of a per-page touch loop are wasted. Regions only partially
+//! covered by the buffer are still faulted in with regular pages. When no huge
+//! page is available the kernel falls back to regular pages and only the
+//! touched ones are committed, the others fault on first access.
+use crate::{AlignedVec, AllocError};
+
+const ENABLED: &str = "/sys/kernel/mm/transparent_hugepage/enabled";
+const PMD_SIZE: &str = "/sys/kernel/mm/transparent_hugepage/hpage_pmd_size";
+
+//-----------------------------------------------------------------------------
+/// Return the granularity at which anonymous memory is faulted in: the
+/// transparent huge page size, usually 2 MiB, if transparent huge pages are
+/// enabled as `always`, the page size otherwise. Detected once and cached.
+pub fn thp_touch_stride() -> usize {
+    static STRIDE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *STRIDE.get_or_init(|| {
+        let ps = crate::cached_page_size();
+        match std::fs::read_to_string(ENABLED) {
+            Ok(enabled) if thp_always(&enabled) => std::fs::read_to_string(PMD_SIZE)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .filter(|&s: &usize| s.is_power_of_two() && s > ps)
+                .unwrap_or(2 << 20),
+            _ => ps,
+        }
+    })
+}
+//-----------------------------------------------------------------------------
+/// Return `true` if the mode selected in the contents of the `enabled` file,
+/// e.g. `always [madvise] never`, is `always`.
+fn thp_always(enabled: &str) -> bool {
+    enabled.split_whitespace().any(|mode| mode == "[always]")
+}
+//-----------------------------------------------------------------------------
+/// Same as [`crate::aligned_vec`] touching at the granularity returned by
+/// [`thp_touch_stride`]. Buffers of at least one huge page are aligned to the
+/// huge page size so that they can be backed by huge pages, their whole huge
+/// pages are touched once each and the remaining tail once per page.
+///
+/// # Panics
+///
+/// Panics if `align` is not a non-zero power of two, if the size in bytes
+/// overflows or if allocation fails.
+pub fn thp_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> AlignedVec<T> {
+    crate::assert_valid_alignment(align);
+    try_thp_aligned_vec(size, capacity, align, touch)
+        .unwrap_or_else(|e| panic!("thp_aligned_vec: {e}"))
+}
+//-----------------------------------------------------------------------------
+/// Fallible version of [`thp_aligned_vec`], returns
+/// [`AllocError::InvalidAlignment`] if `align` is not a non-zero power of two.
+pub fn try_thp_aligned_vec<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+) -> Result<AlignedVec<T>, AllocError> {
+    aligned_vec_strided(size, capacity, align, touch, thp_touch_stride())
+}
+//-----------------------------------------------------------------------------
+/// Implementation of [`try_thp_aligned_vec`] with a fault granularity of
+/// `stride` bytes.
+fn aligned_vec_strided<T: Copy>(
+    size: usize,
+    capacity: usize,
+    align: usize,
+    touch: Option<T>,
+    stride: usize,
+) -> Result<AlignedVec<T>, AllocError> {
+    let bytes = crate::reserved_bytes::<T>(size, capacity, align)?;
+    let huge = stride > crate::cached_page_size() && bytes >= stride;
+    let align = if huge { align.max(stride) } else { align };
+    let span = crate::AllocSpan::enter(size, align, touch.is_some());
+    let mut v = crate::alloc_aligned_vec::<T>(size, capacity, align, false)?;
+    if let Some(x) = touch {
+        span.touch(|| {
+            // elements starting before the last huge page boundary, `T` is
+            // not zero-sized if the buffer spans a huge page
+            let split = if huge {
+                let s = core::mem::size_of::<T>();
+                size * s / stride * stride / s
+            } else {
+                0
+            };
+            let (head, tail) = v.as_mut_slice().split_at_mut(split);
+            crate::touch_strided(head, x, stride);
+            crate::touch_pages(tail, x);
+        });
+    }
+    Ok(v)
+}
+
+//=============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn thp_always_test() {
+        assert!(thp_always("[always] madvise never\n"));
+        assert!(!thp_always("always [madvise] never\n"));
+        assert!(!thp_always("always madvise [never]"));
+        assert!(!thp_always(""));
+        let ps = page_size::get();
+        let stride = thp_touch_stride();
+        assert!(stride == ps || stride > ps && stride.is_power_of_two());
+    }
+    #[test]
+    fn thp_aligned_vec_test() {
+        let ps = page_size::get();
+        // huge pages of 4 pages: two whole ones and a tail of 2.5 pages
+        let touches = crate::touch_count();
+        let v = aligned_vec_strided::<u8>(10 * ps + ps / 2, 0, 64, Some(1), 4 * ps).unwrap();
+        assert_eq!(crate::touch_count() - touches, 2 + 3);
+        assert!(v.is_aligned_to(4 * ps));
+        for i in [0, 4, 8, 9, 10] {
+            assert_eq!(v[i * ps], 1);
+        }
+        // smaller than a huge page, touched once per page
+        let touches = crate::touch_count();
+        let v = aligned_vec_strided::<u64>(ps / 2, 0, ps, Some(1), 8 * ps).unwrap();
+        assert_eq!(crate::touch_count() - touches, 4);
+        assert_eq!(v.alignment(), ps);
+        let r = try_thp_aligned_vec::<u8>(1, 0, 48, None);
+        assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 48 })));
+        // with transparent huge pages always on every page is faulted in
+        let stride = thp_touch_stride();
+        if stride > ps {
+            let v = thp_aligned_vec::<u8>(4 * stride + 3 * ps, 0, 64, Some(1));
+            assert!(v.is_aligned_to(stride));
+            assert_eq!(crate::resident_pages(&v), 4 * stride / ps + 3);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Optional `tracing` instrumentation of allocation and touching.
+//!
+//! Without the `tracing` feature `AllocSpan` is empty and all its methods
+//! compile to nothing.
+
+//-----------------------------------------------------------------------------
+/// Span covering the allocation and touching of a buffer, with fields `size`,
+/// `align`, `touched` and `touch_us`, the time spent touching pages in
+/// microseconds.
+pub(crate) struct AllocSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl AllocSpan {
+    /// Enter a span for the allocation of `size` elements aligned to `align`.
+    #[inline(always)]
+    #[allow(unused_variables)]
+    pub(crate) fn enter(size: usize, align: usize, touched: bool) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "aligned_vec",
+                size,
+                align,
+                touched,
+                touch_us = tracing::field::Empty
+            )
+            .entered(),
+        }
+    }
+    /// Run `touch`, recording its duration.
+    #[inline(always)]
+    pub(crate) fn touch<R>(&self, touch: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        let r = touch();
+        #[cfg(feature = "tracing")]
+        self.span
+            .record("touch_us", start.elapsed().as_micros() as u64);
+        r
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+    // Fields of the captured spans, in creation order.
+    type Spans = Arc<Mutex<Vec<BTreeMap<String, String>>>>;
+    struct Capture(Spans);
+    struct Fields<'a>(&'a mut BTreeMap<String, String>);
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.0.lock().unwrap();
+            let mut fields = BTreeMap::new();
+            span.record(&mut Fields(&mut fields));
+            fields.insert("name".into(), span.metadata().name().into());
+            spans.push(fields);
+            Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1]));
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+    #[test]
+    fn alloc_span_test() {
+        let spans = Spans::default();
+        let ps = page_size::get();
+        tracing::subscriber::with_default(Capture(spans.clone()), || {
+            crate::page_aligned_vec::<u8>(4 * ps, 0, Some(1), false);
+            crate::aligned_vec::<u8>(16, 0, 64, None);
+        });
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["name"], "aligned_vec");
+        assert_eq!(spans[0]["size"], (4 * ps).to_string());
+        assert_eq!(spans[0]["align"], ps.to_string());
+        assert_eq!(spans[0]["touched"], "true");
+        assert!(spans[0].contains_key("touch_us"));
+        assert_eq!(spans[1]["touched"], "false");
+        assert!(!spans[1].contains_key("touch_us"));
+    }
+}
@@ -0,0 +1,1729 @@
+//! Owning aligned buffer.
+//!
+//! A `Vec` built with `Vec::from_raw_parts` frees its buffer with a layout
+//! derived from `T`, which is undefined behaviour when the memory was
+//! allocated with a larger alignment. `AlignedVec` records the exact `Layout`
+//! used for the allocation and releases the memory with it.
+use crate::{AlignedBox, AllocError, Backend};
+use alloc::vec::Vec;
+use bytemuck::{Pod, Zeroable};
+use core::alloc::Layout;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut, Index, IndexMut, Range};
+use core::ptr::NonNull;
+use core::slice::SliceIndex;
+
+//-----------------------------------------------------------------------------
+/// Aligned buffer of `T` elements deallocated with the layout it was
+/// allocated with.
+pub struct AlignedVec<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    capacity: usize,
+    layout: Layout,
+    backend: Backend,
+}
+
+impl<T> AlignedVec<T> {
+    /// Empty buffer, no memory is allocated. The alignment is recorded so that
+    /// the buffer can be reallocated with it. Like `Vec`, buffers of
+    /// zero-sized types have a capacity of `usize::MAX` and never allocate.
+    ///
+    /// `align` must be a non-zero power of two.
+    pub(crate) fn empty(align: usize) -> Self {
+        let align = align.max(core::mem::align_of::<T>());
+        Self {
+            // dangling pointer aligned to `align`
+            ptr: NonNull::new(core::ptr::without_provenance_mut(align)).unwrap(),
+            len: 0,
+            capacity: if core::mem::size_of::<T>() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
+            layout: Layout::from_size_align(0, align).unwrap(),
+            backend: Backend::Rust,
+        }
+    }
+    /// Take ownership of memory returned by `backend.alloc(layout, _)`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by `backend` with `layout`, `layout` must
+    /// be able to hold `capacity` elements and `len` must not exceed
+    /// `capacity`.
+    pub(crate) unsafe fn from_raw_parts_in(
+        ptr: NonNull<T>,
+        len: usize,
+        capacity: usize,
+        layout: Layout,
+        backend: Backend,
+    ) -> Self {
+        Self {
+            ptr,
+            len,
+            capacity,
+            layout,
+            backend,
+        }
+    }
+    /// Set the number of elements, e.g. after writing elements into the
+    /// spare capacity through [`AlignedVec::as_mut_ptr`]. Same as
+    /// `Vec::set_len`, no element is dropped or initialised.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not exceed the capacity and the first `len` elements must be
+    /// initialised.
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if `len` exceeds the capacity.
+    pub unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(
+            len <= self.capacity,
+            "set_len: length {len} exceeds capacity {}",
+            self.capacity
+        );
+        self.len = len;
+    }
+    /// Layout of the allocation.
+    pub(crate) fn layout(&self) -> Layout {
+        self.layout
+    }
+    /// Alignment of the allocation in bytes.
+    pub fn alignment(&self) -> usize {
+        self.layout.align()
+    }
+    /// Return `true` if the first element is aligned to `n` bytes.
+    pub fn is_aligned_to(&self, n: usize) -> bool {
+        (self.ptr.as_ptr() as usize).is_multiple_of(n)
+    }
+    /// Allocator the memory was obtained from.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// Return `true` if the buffer contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Number of elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Size in bytes of the layout requested from the allocator, at least
+    /// `capacity() * size_of::<T>()`. It is larger when the allocation is not
+    /// a whole number of elements, e.g. a buffer from
+    /// [`crate::aligned_vec_from_layout`], and zero when nothing was
+    /// allocated.
+    pub fn allocated_bytes(&self) -> usize {
+        self.layout.size()
+    }
+    /// Pointer to the first element.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_ptr()
+    }
+    /// Mutable pointer to the first element.
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+    /// Pointer to the first element as `NonNull`, aligned to
+    /// [`AlignedVec::alignment`]. An empty buffer returns a dangling pointer
+    /// with the same alignment, never null, e.g. for C routines that reject
+    /// null or misaligned pointers even for zero lengths.
+    pub fn as_nonnull(&self) -> NonNull<T> {
+        debug_assert!(
+            self.is_aligned_to(self.alignment()),
+            "AlignedVec::as_nonnull: pointer not aligned to {}",
+            self.alignment()
+        );
+        self.ptr
+    }
+    /// View elements as slice.
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+    /// View elements as mutable slice.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+    /// Uninitialised elements past the length, up to the capacity, as with
+    /// `Vec::spare_capacity_mut`. Write elements there then make them part of
+    /// the buffer with [`AlignedVec::set_len`]. The region starts
+    /// `len * size_of::<T>()` bytes after the aligned first element.
+    pub fn spare_capacity_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.ptr.as_ptr().add(self.len) as *mut MaybeUninit<T>,
+                self.capacity - self.len,
+            )
+        }
+    }
+    /// View elements as bytes. The byte slice starts at the first element and
+    /// is aligned to [`AlignedVec::alignment`] like the buffer.
+    pub fn as_bytes(&self) -> &[u8]
+    where
+        T: Pod,
+    {
+        bytemuck::cast_slice(self.as_slice())
+    }
+    /// View elements as mutable bytes, aligned like [`AlignedVec::as_bytes`].
+    pub fn as_bytes_mut(&mut self) -> &mut [u8]
+    where
+        T: Pod,
+    {
+        bytemuck::cast_slice_mut(self.as_mut_slice())
+    }
+    /// Reserve room for at least `additional` more elements. When the buffer
+    /// grows the allocation is extended in place if the allocator can,
+    /// otherwise the elements are moved into a new allocation with the same
+    /// alignment and backend. The capacity is at least doubled so that
+    /// repeated pushes take amortised constant time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if allocation fails.
+    pub fn reserve(&mut self, additional: usize) {
+        self.try_reserve(additional)
+            .unwrap_or_else(|e| panic!("AlignedVec::reserve: {e}"))
+    }
+    /// Fallible version of [`AlignedVec::reserve`], returns
+    /// [`AllocError::OutOfMemory`] instead of panicking when the allocator
+    /// fails. The buffer is left unchanged on failure: the old allocation is
+    /// only released once the elements are in the new one.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), AllocError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(AllocError::LayoutOverflow)?;
+        if required <= self.capacity {
+            return Ok(());
+        }
+        self.grow(required.max(self.capacity.saturating_mul(2)))
+    }
+    /// Reserve room for exactly `additional` more elements: when the buffer
+    /// grows its capacity becomes `len + additional`, without the slack added
+    /// by [`AlignedVec::reserve`]. The allocation keeps its alignment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size in bytes overflows or if allocation fails.
+    pub fn reserve_exact_aligned(&mut self, additional: usize) {
+        self.try_reserve_exact_aligned(additional)
+            .unwrap_or_else(|e| panic!("AlignedVec::reserve_exact_aligned: {e}"))
+    }
+    /// Fallible version of [`AlignedVec::reserve_exact_aligned`], the buffer
+    /// is left unchanged on failure.
+    pub fn try_reserve_exact_aligned(&mut self, additional: usize) -> Result<(), AllocError> {
+        let required = self
+            .len
+            .checked_add(additional)
+            .ok_or(AllocError::LayoutOverflow)?;
+        if required <= self.capacity {
+            return Ok(());
+        }
+        self.grow(required)
+    }
+    // Move the elements to an allocation of `capacity` elements with the same
+    // alignment, extending the current one in place if possible.
+    fn grow(&mut self, capacity: usize) -> Result<(), AllocError> {
+        if core::mem::size_of::<T>() == 0 {
+            // nothing to allocate
+            self.capacity = usize::MAX;
+            return Ok(());
+        }
+        let align = self.layout.align();
+        let (layout, capacity) = crate::aligned_layout::<T>(0, capacity, align)?;
+        let backend = self.backend.for_align(align);
+        let ptr = if self.layout.size() == 0 {
+            crate::alloc_layout::<T>(layout, false, backend)?
+        } else {
+            // extended in place if possible, moved otherwise
+            let p = unsafe {
+                self.backend
+                    .realloc(self.ptr.as_ptr() as *mut u8, self.layout, layout.size())
+            };
+            NonNull::new(p as *mut T).ok_or(AllocError::OutOfMemory { layout })?
+        };
+        self.ptr = ptr;
+        self.capacity = capacity;
+        self.layout = layout;
+        self.backend = backend;
+        Ok(())
+    }
+    /// Append an element, growing the buffer as described in
+    /// [`AlignedVec::reserve`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if growing the buffer fails.
+    pub fn push(&mut self, x: T) {
+        if self.len == self.capacity {
+            self.reserve(1);
+        }
+        unsafe {
+            self.ptr.as_ptr().add(self.len).write(x);
+        }
+        self.len += 1;
+    }
+    /// Insert `x` at position `index`, shifting the elements after it to the
+    /// right. The buffer grows as described in [`AlignedVec::reserve`] and
+    /// stays aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len` or if growing the buffer fails.
+    pub fn insert(&mut self, index: usize, x: T) {
+        assert!(
+            index <= self.len,
+            "insert: index {index} out of bounds for length {}",
+            self.len
+        );
+        if self.len == self.capacity {
+            self.reserve(1);
+        }
+        unsafe {
+            let p = self.ptr.as_ptr().add(index);
+            core::ptr::copy(p, p.add(1), self.len - index);
+            p.write(x);
+        }
+        self.len += 1;
+    }
+    /// Remove and return the element at `index`, replacing it with the last
+    /// element. The order is not preserved but no element is shifted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(
+            index < self.len,
+            "swap_remove: index {index} out of bounds for length {}",
+            self.len
+        );
+        unsafe {
+            let p = self.ptr.as_ptr();
+            let x = p.add(index).read();
+            self.len -= 1;
+            core::ptr::copy(p.add(self.len), p.add(index), 1);
+            x
+        }
+    }
+    /// Append clones of the elements of `src`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if growing the buffer fails.
+    pub fn extend_from_slice(&mut self, src: &[T])
+    where
+        T: Clone,
+    {
+        self.reserve(src.len());
+        for x in src {
+            // the length follows each write in case `clone` panics
+            unsafe {
+                self.ptr.as_ptr().add(self.len).write(x.clone());
+            }
+            self.len += 1;
+        }
+    }
+    /// Keep the first `len` elements and drop the others, nothing is done if
+    /// `len` is not smaller than the length. The allocation, its address and
+    /// its alignment are unchanged.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let tail = core::ptr::slice_from_raw_parts_mut(
+            unsafe { self.ptr.as_ptr().add(len) },
+            self.len - len,
+        );
+        // the length first, a panicking destructor must not cause a double drop
+        self.len = len;
+        unsafe { core::ptr::drop_in_place(tail) };
+    }
+    /// Grow the length to `new_len`, the new elements being all zeros, e.g.
+    /// to extend an accumulator. The existing elements are preserved and not
+    /// rewritten, the buffer keeps its alignment when it has to grow as
+    /// described in [`AlignedVec::reserve`]. Nothing is done if `new_len` is
+    /// not larger than the length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if growing the buffer fails.
+    pub fn grow_zeroed(&mut self, new_len: usize)
+    where
+        T: Zeroable,
+    {
+        self.try_grow_zeroed(new_len)
+            .unwrap_or_else(|e| panic!("AlignedVec::grow_zeroed: {e}"))
+    }
+    /// Fallible version of [`AlignedVec::grow_zeroed`], the buffer is left
+    /// unchanged on failure.
+    pub fn try_grow_zeroed(&mut self, new_len: usize) -> Result<(), AllocError>
+    where
+        T: Zeroable,
+    {
+        let Some(additional) = new_len.checked_sub(self.len) else {
+            return Ok(());
+        };
+        self.try_reserve(additional)?;
+        unsafe { core::ptr::write_bytes(self.ptr.as_ptr().add(self.len), 0, additional) };
+        self.len = new_len;
+        Ok(())
+    }
+    /// Resize the buffer to `new_len` elements, as [`Vec::resize`]: new
+    /// elements are clones of `value` and extra ones are dropped as by
+    /// [`AlignedVec::truncate`]. When the buffer has to grow it keeps its
+    /// alignment as described in [`AlignedVec::reserve`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if growing the buffer fails.
+    pub fn resize(&mut self, new_len: usize, value: T)
+    where
+        T: Clone,
+    {
+        let Some(additional) = new_len.checked_sub(self.len) else {
+            self.truncate(new_len);
+            return;
+        };
+        if additional == 0 {
+            return;
+        }
+        self.reserve(additional);
+        for _ in 1..additional {
+            // the length follows each write in case `clone` panics
+            unsafe { self.ptr.as_ptr().add(self.len).write(value.clone()) };
+            self.len += 1;
+        }
+        unsafe { self.ptr.as_ptr().add(self.len).write(value) };
+        self.len += 1;
+    }
+    /// Keep only the elements for which `f` returns `true`, in order, and drop
+    /// the others. The survivors are moved down in place, the allocation, its
+    /// address and its alignment are unchanged. If `f` or a destructor panics
+    /// the elements are leaked, not dropped.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let len = self.len;
+        self.len = 0;
+        let p = self.ptr.as_ptr();
+        let mut kept = 0;
+        for i in 0..len {
+            unsafe {
+                let x = p.add(i);
+                if f(&*x) {
+                    if i != kept {
+                        core::ptr::copy_nonoverlapping(x, p.add(kept), 1);
+                    }
+                    kept += 1;
+                } else {
+                    core::ptr::drop_in_place(x);
+                }
+            }
+        }
+        self.len = kept;
+    }
+    /// Remove consecutive repeated elements, as [`Vec::dedup`], e.g. after
+    /// sorting the buffer in place with the slice methods to build a lookup
+    /// table. The allocation, its address and its alignment are unchanged.
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
+    /// Same as [`AlignedVec::dedup`] with elements compared by `key`.
+    pub fn dedup_by_key<K: PartialEq>(&mut self, mut key: impl FnMut(&mut T) -> K) {
+        self.dedup_by(|a, b| key(a) == key(b));
+    }
+    /// Remove the elements for which `same_bucket(element, previous)` returns
+    /// `true`, `previous` being the last element kept, and drop them. The
+    /// survivors are moved down in place as with [`AlignedVec::retain`], the
+    /// elements are leaked if `same_bucket` or a destructor panics.
+    pub fn dedup_by(&mut self, mut same_bucket: impl FnMut(&mut T, &mut T) -> bool) {
+        let len = self.len;
+        if len < 2 {
+            return;
+        }
+        self.len = 0;
+        let p = self.ptr.as_ptr();
+        let mut kept = 1;
+        for i in 1..len {
+            unsafe {
+                let x = p.add(i);
+                if same_bucket(&mut *x, &mut *p.add(kept - 1)) {
+                    core::ptr::drop_in_place(x);
+                } else {
+                    if i != kept {
+                        core::ptr::copy_nonoverlapping(x, p.add(kept), 1);
+                    }
+                    kept += 1;
+                }
+            }
+        }
+        self.len = kept;
+    }
+    /// Append a copy of the elements in `src`, growing the buffer as described
+    /// in [`AlignedVec::reserve`]. The elements are copied once the buffer has
+    /// grown, from their position in the possibly moved allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` is out of bounds, if the start of `src` is past its end
+    /// or if growing the buffer fails.
+    pub fn extend_from_within(&mut self, src: Range<usize>)
+    where
+        T: Copy,
+    {
+        assert!(
+            src.start <= src.end && src.end <= self.len,
+            "extend_from_within: range {src:?} out of bounds for length {}",
+            self.len
+        );
+        let n = src.end - src.start;
+        self.reserve(n);
+        unsafe {
+            let p = self.ptr.as_ptr();
+            core::ptr::copy_nonoverlapping(p.add(src.start), p.add(self.len), n);
+        }
+        self.len += n;
+    }
+    /// Move the elements from `at` on into a new buffer with the same
+    /// alignment and backend and truncate `self` to `at` elements. The tail is
+    /// copied since a split point within the buffer is not aligned in
+    /// general, both buffers are aligned to [`AlignedVec::alignment`]. The
+    /// capacity of `self` is unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len` or if allocation fails.
+    pub fn split_off_aligned(&mut self, at: usize) -> Self {
+        assert!(
+            at <= self.len,
+            "split_off_aligned: index {at} out of bounds for length {}",
+            self.len
+        );
+        let n = self.len - at;
+        let mut tail = crate::alloc_aligned_vec_in(0, n, self.alignment(), false, self.backend)
+            .unwrap_or_else(|e| panic!("AlignedVec::split_off_aligned: {e}"));
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.ptr.as_ptr().add(at), tail.as_mut_ptr(), n);
+            // the elements are moved, not duplicated
+            self.len = at;
+            tail.set_len(n);
+        }
+        tail
+    }
+    /// Copy the elements in `src` to the elements starting at `dst`, the ranges
+    /// may overlap. Use it for the wrap-around copies of ring buffers: the
+    /// buffer is not reallocated, so offsets that are multiples of the
+    /// alignment stay aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `src` or `dst..dst + src.len()` is out of bounds or if the
+    /// start of `src` is past its end.
+    pub fn copy_within_aligned(&mut self, src: Range<usize>, dst: usize)
+    where
+        T: Copy,
+    {
+        assert!(
+            src.start <= src.end && src.end <= self.len,
+            "copy_within_aligned: source {src:?} out of bounds for length {}",
+            self.len
+        );
+        let n = src.end - src.start;
+        assert!(
+            dst <= self.len - n,
+            "copy_within_aligned: destination {dst} out of bounds for {n} elements and length {}",
+            self.len
+        );
+        // `copy` handles overlapping ranges
+        unsafe {
+            core::ptr::copy(
+                self.ptr.as_ptr().add(src.start),
+                self.ptr.as_ptr().add(dst),
+                n,
+            );
+        }
+    }
+    /// Copy all the elements of `src` into the buffer, e.g. to load data into
+    /// a freshly allocated or adopted buffer. Same as `copy_from_slice` on the
+    /// slice of elements, the buffer keeps its allocation and alignment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `src` differs from the length of the buffer.
+    pub fn copy_from_slice_aligned(&mut self, src: &[T])
+    where
+        T: Copy,
+    {
+        assert!(
+            src.len() == self.len,
+            "copy_from_slice_aligned: source length {} does not match length {}",
+            src.len(),
+            self.len
+        );
+        // `src` cannot alias the buffer, which is borrowed mutably
+        unsafe { core::ptr::copy_nonoverlapping(src.as_ptr(), self.ptr.as_ptr(), src.len()) };
+    }
+    /// Overwrite the `len` elements with clones of `value`, as
+    /// [`slice::fill`], e.g. to clear a reused buffer. The spare capacity is
+    /// not written, unlike by [`AlignedVec::reset`].
+    pub fn fill(&mut self, value: T)
+    where
+        T: Clone,
+    {
+        self.as_mut_slice().fill(value);
+    }
+    /// Overwrite the `len` elements with the values returned by `f`, in order,
+    /// as [`slice::fill_with`]. The spare capacity is not written.
+    pub fn fill_with(&mut self, f: impl FnMut() -> T) {
+        self.as_mut_slice().fill_with(f);
+    }
+    /// Empty the buffer for reuse without reallocating, e.g. when recycling
+    /// buffers from a pool: the length is set to zero and when `touch` is not
+    /// `None` one element per page of the whole capacity is written with the
+    /// passed value, as the constructors do, so that the pages stay faulted
+    /// in.
+    pub fn reset(&mut self, touch: Option<T>)
+    where
+        T: Copy,
+    {
+        self.len = 0;
+        if let Some(x) = touch {
+            let spare = unsafe {
+                core::slice::from_raw_parts_mut(
+                    self.ptr.as_ptr() as *mut MaybeUninit<T>,
+                    self.capacity,
+                )
+            };
+            for i in crate::stride_indices(spare, crate::os_page_size()) {
+                spare[i].write(x);
+            }
+        }
+    }
+    /// Fault in the pages of the whole allocation, e.g. of a buffer built
+    /// without touching before a latency critical section. When `touch` is
+    /// not `None` one element per page, spare capacity included, is written
+    /// with the passed value as the constructors do, overwriting those
+    /// elements. Otherwise one byte per page is read as by
+    /// [`crate::touch_pages_read`], the elements are unchanged but pages never
+    /// written may be backed by a shared zero page until their first write.
+    /// Calling it again only faults in pages released meanwhile.
+    pub fn prefault(&mut self, touch: Option<T>)
+    where
+        T: Copy,
+    {
+        let all = unsafe {
+            core::slice::from_raw_parts_mut(self.ptr.as_ptr() as *mut MaybeUninit<T>, self.capacity)
+        };
+        match touch {
+            Some(x) => {
+                for i in crate::stride_indices(all, crate::os_page_size()) {
+                    all[i].write(x);
+                }
+            }
+            None => crate::touch_pages_read(all),
+        }
+    }
+    /// Overwrite the whole allocation with zeros without reallocating. The
+    /// length is set to the capacity if `full` is `true`, to zero otherwise.
+    pub fn reset_zeroed(&mut self, full: bool)
+    where
+        T: Zeroable,
+    {
+        unsafe {
+            // elements are dropped before their memory is cleared
+            core::ptr::drop_in_place(self.as_mut_slice());
+            self.len = 0;
+            core::ptr::write_bytes(self.ptr.as_ptr() as *mut u8, 0, self.layout.size());
+        }
+        if full {
+            self.len = self.capacity;
+        }
+    }
+    /// Leak the buffer and return its `len` elements as a mutable slice living
+    /// as long as chosen, `'static` for tables set up at startup and used for
+    /// the lifetime of the program. The allocation, with its alignment, is
+    /// intentionally never released and the elements are never dropped.
+    pub fn leak<'a>(self) -> &'a mut [T] {
+        let v = core::mem::ManuallyDrop::new(self);
+        unsafe { core::slice::from_raw_parts_mut(v.ptr.as_ptr(), v.len) }
+    }
+    /// Decompose into pointer, length and capacity. The caller becomes
+    /// responsible for the memory, which can be released with
+    /// [`crate::dealloc_aligned`] passing [`AlignedVec::alignment`] if the
+    /// buffer was allocated by [`Backend::Rust`] with a layout of exactly
+    /// `capacity` elements, see [`AlignedVec::allocated_bytes`].
+    pub fn into_raw_parts(self) -> (*mut T, usize, usize) {
+        let v = core::mem::ManuallyDrop::new(self);
+        (v.ptr.as_ptr(), v.len, v.capacity)
+    }
+    /// Decompose into pointer, length, capacity and
+    /// [`AlignedVec::alignment`], e.g. to hand the buffer over a C call. The
+    /// buffer is put back together with [`AlignedVec::from_raw_parts_aligned`].
+    pub fn into_raw_parts_aligned(self) -> (*mut T, usize, usize, usize) {
+        let align = self.alignment();
+        let (ptr, len, capacity) = self.into_raw_parts();
+        (ptr, len, capacity, align)
+    }
+    /// Rebuild a buffer taken apart with [`AlignedVec::into_raw_parts_aligned`],
+    /// the layout is rebuilt from `capacity` and `align` so that the buffer is
+    /// released as it was allocated when dropped.
+    ///
+    /// # Safety
+    ///
+    /// - `ptr`, `capacity` and `align` must have been returned together by
+    ///   [`AlignedVec::into_raw_parts_aligned`] for a buffer allocated by
+    ///   [`Backend::Rust`], the default of the constructors, and the memory
+    ///   must not have been released or reused since.
+    /// - The allocation must be `capacity * size_of::<T>()` bytes long, which
+    ///   does not hold for buffers whose capacity was rounded down, see
+    ///   [`crate::aligned_vec_from_layout`].
+    /// - `len` must not exceed `capacity` and the first `len` elements must be
+    ///   initialised.
+    pub unsafe fn from_raw_parts_aligned(
+        ptr: *mut T,
+        len: usize,
+        capacity: usize,
+        align: usize,
+    ) -> Self {
+        let (layout, _) =
+            crate::aligned_layout::<T>(0, capacity, align).expect("from_raw_parts_aligned: layout");
+        Self {
+            ptr: NonNull::new(ptr).expect("from_raw_parts_aligned: null pointer"),
+            len,
+            capacity,
+            layout,
+            backend: Backend::Rust.for_align(layout.align()),
+        }
+    }
+    /// Convert into an `AlignedBox<[T]>` with the same alignment and
+    /// [`Backend`]. A full buffer is handed over as is, spare capacity is
+    /// released by moving the elements into an allocation that exactly fits
+    /// them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new allocation fails.
+    pub fn into_boxed_slice(self) -> AlignedBox<[T]> {
+        let v = core::mem::ManuallyDrop::new(self);
+        let slice = |ptr| NonNull::slice_from_raw_parts(ptr, v.len);
+        if v.len == v.capacity {
+            return unsafe { AlignedBox::from_raw_parts(slice(v.ptr), v.layout, v.backend) };
+        }
+        let layout =
+            Layout::from_size_align(v.len * core::mem::size_of::<T>(), v.layout.align()).unwrap();
+        unsafe {
+            let ptr = if layout.size() == 0 {
+                NonNull::new(core::ptr::without_provenance_mut(layout.align())).unwrap()
+            } else {
+                let p = v.backend.alloc(layout, false) as *mut T;
+                NonNull::new(p).unwrap_or_else(|| alloc::alloc::handle_alloc_error(layout))
+            };
+            core::ptr::copy_nonoverlapping(v.ptr.as_ptr(), ptr.as_ptr(), v.len);
+            if v.layout.size() != 0 {
+                v.backend.dealloc(v.ptr.as_ptr() as *mut u8, v.layout);
+            }
+            AlignedBox::from_raw_parts(slice(ptr), layout, v.backend)
+        }
+    }
+    /// Move the elements into a `Vec<T>` allocated by the global allocator
+    /// with the alignment of `T`, the alignment of the buffer is lost. The
+    /// aligned buffer is released, the returned `Vec` is safe to hand to code
+    /// that owns and drops `Vec<T>`.
+    pub fn into_std_vec(mut self) -> Vec<T> {
+        let mut v = Vec::with_capacity(self.len);
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.ptr.as_ptr(), v.as_mut_ptr(), self.len);
+            v.set_len(self.len);
+            // elements were moved out, only the memory is released
+            self.len = 0;
+        }
+        v
+    }
+    /// Take over the memory of `v` without copying, the inverse of
+    /// [`AlignedVec::try_into_std_vec_zero_copy`]. The buffer is aligned to
+    /// `align_of::<T>()` and released by [`Backend::Rust`] with the layout
+    /// `Vec` allocated it with.
+    pub(crate) fn from_std_vec(v: Vec<T>) -> Self {
+        let mut v = core::mem::ManuallyDrop::new(v);
+        let (len, capacity) = (v.len(), v.capacity());
+        let layout = match Layout::array::<T>(capacity) {
+            Ok(layout) if layout.size() != 0 => layout,
+            // nothing allocated, zero-sized `T` or no capacity
+            _ => {
+                let mut e = Self::empty(1);
+                unsafe { e.set_len(len) };
+                return e;
+            }
+        };
+        let ptr = NonNull::new(v.as_mut_ptr()).unwrap();
+        Backend::Rust.record_alloc(ptr.as_ptr() as *mut u8, layout);
+        unsafe { Self::from_raw_parts_in(ptr, len, capacity, layout, Backend::Rust) }
+    }
+    /// Convert into a `Vec<T>` without copying, possible only if the buffer
+    /// was allocated by [`Backend::Rust`] with the layout `Vec` deallocates
+    /// with: aligned to exactly `align_of::<T>()` and sized for exactly its
+    /// capacity, which buffers from [`crate::aligned_vec_from_layout`] may not
+    /// be. The buffer is returned unchanged otherwise.
+    pub fn try_into_std_vec_zero_copy(self) -> Result<Vec<T>, Self> {
+        if Layout::array::<T>(self.capacity).ok() != Some(self.layout)
+            || self.backend != Backend::Rust
+        {
+            return Err(self);
+        }
+        let v = core::mem::ManuallyDrop::new(self);
+        if v.layout.size() != 0 {
+            // from now on the memory is owned by `Vec`
+            Backend::Rust.record_free(v.ptr.as_ptr() as *mut u8, v.layout);
+        }
+        Ok(unsafe { Vec::from_raw_parts(v.ptr.as_ptr(), v.len, v.capacity) })
+    }
+}
+
+// The buffer owns its elements like a `Vec<T>`: no other value holds the
+// pointer and the memory is released by the backend, from any thread, only on
+// drop. Sending the buffer sends the elements, sharing it shares them.
+unsafe impl<T: Send> Send for AlignedVec<T> {}
+unsafe impl<T: Sync> Sync for AlignedVec<T> {}
+
+impl<T> Drop for AlignedVec<T> {
+    fn drop(&mut self) {
+        unsafe {
+            core::ptr::drop_in_place(self.as_mut_slice());
+            if self.layout.size() != 0 {
+                self.backend
+                    .dealloc(self.ptr.as_ptr() as *mut u8, self.layout);
+            }
+        }
+    }
+}
+
+// Clones have the same alignment and backend, their capacity is the length.
+impl<T: Clone> Clone for AlignedVec<T> {
+    fn clone(&self) -> Self {
+        let mut c = crate::alloc_aligned_vec_in(0, self.len, self.alignment(), false, self.backend)
+            .unwrap_or_else(|e| panic!("AlignedVec::clone: {e}"));
+        c.extend_from_slice(self.as_slice());
+        c
+    }
+}
+
+impl<T> fmt::Debug for AlignedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedVec")
+            .field("ptr", &self.ptr)
+            .field("align", &self.alignment())
+            .field("len", &self.len)
+            .field("capacity", &self.capacity)
+            .field("aligned", &self.is_aligned_to(self.alignment()))
+            .finish()
+    }
+}
+
+// The slice covers the `len` initialised elements, not the capacity.
+impl<T> Deref for AlignedVec<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for AlignedVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T> AsRef<[T]> for AlignedVec<T> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> AsMut<[T]> for AlignedVec<T> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+// Comparisons and hashing look at the elements only, the alignment, capacity
+// and backend are ignored.
+impl<T: PartialEq> PartialEq for AlignedVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq> Eq for AlignedVec<T> {}
+
+impl<T: PartialOrd> PartialOrd for AlignedVec<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_slice().partial_cmp(other.as_slice())
+    }
+}
+
+impl<T: Ord> Ord for AlignedVec<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_slice().cmp(other.as_slice())
+    }
+}
+
+impl<T: Hash> Hash for AlignedVec<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+// Collected with the alignment of `T`, see `crate::from_iter_aligned` for a
+// larger one.
+impl<T> FromIterator<T> for AlignedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        crate::from_iter_aligned(iter, core::mem::align_of::<T>())
+    }
+}
+
+// The buffer grows as with `push`, keeping its alignment.
+impl<T> Extend<T> for AlignedVec<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for x in iter {
+            self.push(x);
+        }
+    }
+}
+
+impl<T> IntoIterator for AlignedVec<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+    fn into_iter(mut self) -> IntoIter<T> {
+        let end = self.len;
+        // the elements are owned by the iterator from now on, the buffer only
+        // releases the memory
+        self.len = 0;
+        IntoIter {
+            vec: self,
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AlignedVec<T> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut AlignedVec<T> {
+    type Item = &'a mut T;
+    type IntoIter = core::slice::IterMut<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_mut_slice().iter_mut()
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for AlignedVec<T> {
+    type Output = I::Output;
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for AlignedVec<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+//-----------------------------------------------------------------------------
+/// Consuming iterator over the elements of an [`AlignedVec`], the memory is
+/// released when the iterator is dropped.
+pub struct IntoIter<T> {
+    vec: AlignedVec<T>,
+    // elements in `start..end` have not been moved out yet
+    start: usize,
+    end: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        let x = unsafe { self.vec.ptr.as_ptr().add(self.start).read() };
+        self.start += 1;
+        Some(x)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.start;
+        (n, Some(n))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(unsafe { self.vec.ptr.as_ptr().add(self.end).read() })
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        // the remaining elements, the buffer then releases the memory
+        unsafe {
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(
+                self.vec.ptr.as_ptr().add(self.start),
+                self.end - self.start,
+            ));
+        }
+    }
+}
+
+//=============================================================================
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    #[test]
+    fn iter_test() {
+        let v = crate::from_iter_aligned(0..1000u32, 64);
+        assert!(v.is_aligned_to(64));
+        assert_eq!(v.alignment(), 64);
+        assert!(v.iter().copied().eq(0..1000));
+        // no useful size hint, the buffer grows keeping its alignment
+        let v = crate::from_iter_aligned((0..5000u64).filter(|x| x % 3 == 0), 256);
+        assert!(v.is_aligned_to(256));
+        assert!(v.iter().copied().eq((0..5000).step_by(3)));
+        let mut n = 0;
+        for x in &v {
+            n += x;
+        }
+        assert_eq!(n, v.iter().sum::<u64>());
+        let mut v: AlignedVec<String> = (0..10).map(|i| i.to_string()).collect();
+        for s in &mut v {
+            s.push('!');
+        }
+        v.extend(["a".to_string()]);
+        assert_eq!(v.len(), 11);
+        // the elements left are dropped with the iterator
+        let mut it = v.into_iter();
+        assert_eq!(it.len(), 11);
+        assert_eq!(it.next().as_deref(), Some("0!"));
+        assert_eq!(it.next_back().as_deref(), Some("a"));
+        assert_eq!(it.size_hint(), (9, Some(9)));
+        drop(it);
+        let v: AlignedVec<String> = (0..3).map(|i| i.to_string()).collect();
+        assert_eq!(v.into_iter().collect::<Vec<_>>(), ["0", "1", "2"]);
+        assert!(crate::try_from_iter_aligned(0..1u8, 3).is_err());
+    }
+    #[test]
+    fn split_off_aligned_test() {
+        let mut v = crate::from_iter_aligned(0..1000u32, 64);
+        let tail = v.split_off_aligned(333);
+        assert!(v.is_aligned_to(64) && tail.is_aligned_to(64));
+        assert_eq!(tail.alignment(), 64);
+        assert_eq!(tail.backend(), v.backend());
+        assert!(v.iter().copied().eq(0..333));
+        assert!(tail.iter().copied().eq(333..1000));
+        assert!(v.split_off_aligned(333).is_empty());
+        assert!(v.split_off_aligned(0).iter().copied().eq(0..333));
+        assert!(v.is_empty());
+        let mut v: AlignedVec<String> = crate::from_iter_aligned((0..5).map(|i| i.to_string()), 32);
+        let tail = v.split_off_aligned(2);
+        assert_eq!(v.as_slice(), ["0", "1"]);
+        assert_eq!(tail.as_slice(), ["2", "3", "4"]);
+    }
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn split_off_aligned_out_of_bounds_test() {
+        crate::aligned_vec::<u8>(10, 0, 64, None).split_off_aligned(11);
+    }
+    #[test]
+    fn copy_within_aligned_test() {
+        let cases = [
+            (0..600, 100),
+            (100..700, 0),
+            (0..0, 1000),
+            (1000..1000, 0),
+            (0..1000, 0),
+        ];
+        for (src, dst) in cases {
+            let mut v = crate::from_iter_aligned(0..1000u32, 64);
+            let mut reference: Vec<u32> = (0..1000).collect();
+            v.copy_within_aligned(src.clone(), dst);
+            reference.copy_within(src, dst);
+            assert_eq!(v.as_slice(), reference);
+            assert!(v.is_aligned_to(64));
+        }
+    }
+    #[test]
+    #[should_panic(expected = "destination 500 out of bounds")]
+    fn copy_within_aligned_out_of_bounds_test() {
+        let mut v = crate::aligned_vec::<u8>(1000, 0, 64, None);
+        v.copy_within_aligned(0..501, 500);
+    }
+    #[test]
+    #[should_panic(expected = "source 900..1001 out of bounds")]
+    fn copy_within_aligned_source_out_of_bounds_test() {
+        let mut v = crate::aligned_vec::<u8>(1000, 0, 64, None);
+        v.copy_within_aligned(900..1001, 0);
+    }
+    #[test]
+    fn copy_from_slice_aligned_test() {
+        let mut v = crate::aligned_vec::<u32>(1000, 0, 4096, Some(0));
+        let src: Vec<u32> = (0..1000).collect();
+        v.copy_from_slice_aligned(&src);
+        assert_eq!(v.as_slice(), src);
+        assert!(v.is_aligned_to(4096));
+        assert_eq!(v.capacity(), 1000);
+        let mut e = crate::aligned_vec_with_capacity::<u32>(10, 64);
+        e.copy_from_slice_aligned(&[]);
+        assert!(e.is_empty());
+    }
+    #[test]
+    #[should_panic(expected = "source length 999 does not match length 1000")]
+    fn copy_from_slice_aligned_length_mismatch_test() {
+        let mut v = crate::aligned_vec::<u8>(1000, 0, 64, None);
+        v.copy_from_slice_aligned(&[1; 999]);
+    }
+    #[test]
+    fn as_nonnull_test() {
+        let v = crate::aligned_vec::<f32>(100, 0, 64, Some(1.0));
+        let p = v.as_nonnull();
+        assert_eq!(p.as_ptr() as *const f32, v.as_ptr());
+        assert!((p.as_ptr() as usize).is_multiple_of(64));
+        assert_eq!(unsafe { *p.as_ptr() }, 1.0);
+        for align in [1, 64, 4096] {
+            let v = crate::aligned_vec::<f32>(0, 0, align, None);
+            let p = v.as_nonnull();
+            assert!((p.as_ptr() as usize).is_multiple_of(align.max(4)));
+        }
+        // also after the elements are gone
+        let mut v = crate::aligned_vec::<u8>(10, 0, 256, None);
+        v.split_off_aligned(0);
+        assert!((v.as_nonnull().as_ptr() as usize).is_multiple_of(256));
+    }
+    #[test]
+    fn fill_test() {
+        let mut v = crate::init_aligned_vec::<u32>(10, 100, 64, 1);
+        for x in v.spare_capacity_mut() {
+            x.write(5);
+        }
+        v.fill(7);
+        assert_eq!(v.as_slice(), [7; 10]);
+        assert!(v.is_aligned_to(64));
+        // written above, the spare elements are initialised
+        let spare = v.spare_capacity_mut();
+        assert_eq!(spare.len(), 90);
+        assert!(spare.iter().all(|x| unsafe { x.assume_init() } == 5));
+        let mut n = 0;
+        v.fill_with(|| {
+            n += 1;
+            n
+        });
+        assert!(v.iter().copied().eq(1..=10));
+        assert!(v
+            .spare_capacity_mut()
+            .iter()
+            .all(|x| unsafe { x.assume_init() } == 5));
+        let mut v = crate::from_iter_aligned(["a".to_string(), "b".to_string()], 64);
+        v.fill("c".to_string());
+        assert_eq!(v.as_slice().concat(), "cc");
+    }
+    #[cfg(unix)]
+    #[test]
+    fn prefault_test() {
+        let ps = crate::os_page_size();
+        // above the largest mmap threshold of glibc, never reused heap memory,
+        // and not touched by the constructor
+        let bytes = 64 << 20;
+        let pages = bytes / ps;
+        let mut v = crate::aligned_vec::<u64>(bytes / 8, 0, ps, None);
+        let ptr = v.as_ptr();
+        assert!(crate::resident_pages(&v) < pages);
+        v.prefault(Some(1));
+        assert_eq!(crate::resident_pages(&v), pages);
+        v[1] = 2;
+        v.prefault(Some(1));
+        assert_eq!(crate::resident_pages(&v), pages);
+        assert_eq!((v[0], v[1], v[ps / 8]), (1, 2, 1));
+        assert_eq!(v.as_ptr(), ptr);
+        let mut v = crate::aligned_vec::<u8>(bytes, 0, ps, None);
+        assert!(crate::resident_pages(&v) < pages);
+        v.prefault(None);
+        assert_eq!(crate::resident_pages(&v), pages);
+        // spare capacity included
+        let mut v = crate::aligned_vec_with_capacity::<u8>(bytes, ps);
+        v.prefault(Some(3));
+        unsafe { v.set_len(bytes) };
+        assert_eq!(crate::resident_pages(&v), pages);
+        assert!(v.iter().step_by(ps).all(|&x| x == 3));
+        crate::aligned_vec_with_capacity::<()>(10, 64).prefault(Some(()));
+        crate::aligned_vec_with_capacity::<u8>(0, 64).prefault(None);
+    }
+    #[test]
+    fn reset_test() {
+        let ps = crate::os_page_size();
+        let mut v = crate::init_aligned_vec::<u32>(2 * ps, 0, 64, 7);
+        let (ptr, capacity) = (v.as_ptr(), v.capacity());
+        v.reset_zeroed(true);
+        assert_eq!(
+            (v.as_ptr(), v.len(), v.capacity()),
+            (ptr, capacity, capacity)
+        );
+        assert!(v.iter().all(|&x| x == 0));
+        v.as_mut_slice().fill(9);
+        v.reset_zeroed(false);
+        assert!(v.is_empty());
+        unsafe { v.set_len(capacity) };
+        assert!(v.iter().all(|&x| x == 0));
+        v.fill(9);
+        v.reset(Some(3));
+        assert!(v.is_empty());
+        assert_eq!(v.as_ptr(), ptr);
+        unsafe { v.set_len(capacity) };
+        // one element per page spanned by the capacity
+        let touched: Vec<usize> = crate::stride_indices(v.as_slice(), ps).collect();
+        assert!(touched.len() > 2);
+        for (i, &x) in v.iter().enumerate() {
+            assert_eq!(x, if touched.contains(&i) { 3 } else { 9 });
+        }
+        v.reset(None);
+        assert!(v.is_empty());
+    }
+    #[test]
+    fn leak_test() {
+        let mut v = crate::aligned_vec_with_capacity::<f64>(100, 128);
+        v.extend_from_slice(&[1.0, 2.0, 3.0]);
+        let table: &'static mut [f64] = v.leak();
+        assert_eq!(table.len(), 3);
+        assert!((table.as_ptr() as usize).is_multiple_of(128));
+        table[1] = 5.0;
+        assert_eq!(table, [1.0, 5.0, 3.0]);
+        assert!(crate::aligned_vec::<u8>(0, 0, 64, None).leak().is_empty());
+    }
+    #[test]
+    fn extend_from_within_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(3, 64);
+        v.extend_from_slice(&[1, 2, 3]);
+        // the buffer is full, the source is read from the grown allocation
+        v.extend_from_within(0..3);
+        assert_eq!(v.as_slice(), [1, 2, 3, 1, 2, 3]);
+        assert!(v.is_aligned_to(64));
+        for _ in 0..10 {
+            v.extend_from_within(0..v.len());
+        }
+        assert_eq!(v.len(), 6 << 10);
+        assert!(v.chunks(3).all(|c| c == [1, 2, 3]));
+        assert!(v.is_aligned_to(64));
+        v.extend_from_within(4..4);
+        assert_eq!(v.len(), 6 << 10);
+    }
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn extend_from_within_out_of_bounds_test() {
+        let mut v = crate::aligned_vec::<u8>(3, 0, 64, None);
+        v.extend_from_within(2..4);
+    }
+    #[test]
+    fn truncate_retain_test() {
+        let mut v = crate::from_iter_aligned(0..1000u32, 256);
+        let (ptr, capacity) = (v.as_ptr(), v.capacity());
+        v.truncate(2000);
+        assert_eq!(v.len(), 1000);
+        v.truncate(600);
+        v.retain(|&x| x % 3 == 0);
+        assert_eq!((v.as_ptr(), v.capacity()), (ptr, capacity));
+        assert!(v.is_aligned_to(256));
+        assert!(v.iter().copied().eq((0..600).step_by(3)));
+        v.retain(|_| false);
+        assert!(v.is_empty());
+        assert_eq!(v.as_ptr(), ptr);
+        // removed elements are dropped exactly once
+        let rc = std::rc::Rc::new(());
+        let mut v = crate::from_iter_aligned((0..10).map(|i| (i, rc.clone())), 64);
+        v.retain(|(i, _)| i % 2 == 1);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 6);
+        assert!(v.iter().map(|x| x.0).eq([1, 3, 5, 7, 9]));
+        v.truncate(2);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 3);
+        drop(v);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+    #[test]
+    fn dedup_test() {
+        let mut v = crate::from_iter_aligned([5u32, 1, 3, 1, 5, 5, 2, 3], 128);
+        let ptr = v.as_ptr();
+        // sorted in place through the slice
+        v.sort_unstable();
+        assert_eq!(v.as_slice(), [1, 1, 2, 3, 3, 5, 5, 5]);
+        v.dedup();
+        assert_eq!(v.as_slice(), [1, 2, 3, 5]);
+        assert_eq!(v.as_ptr(), ptr);
+        assert!(v.is_aligned_to(128));
+        v.dedup_by_key(|x| *x / 2);
+        assert_eq!(v.as_slice(), [1, 2, 5]);
+        assert_eq!(v.as_ptr(), ptr);
+        let mut v = crate::aligned_vec_with_capacity::<u8>(0, 64);
+        v.dedup();
+        assert!(v.is_empty());
+        // removed elements are dropped exactly once
+        let rc = std::rc::Rc::new(());
+        let mut v = crate::from_iter_aligned([1, 1, 2, 2, 2, 1].map(|i| (i, rc.clone())), 64);
+        v.dedup_by(|a, b| a.0 == b.0);
+        assert!(v.iter().map(|x| x.0).eq([1, 2, 1]));
+        assert_eq!(std::rc::Rc::strong_count(&rc), 4);
+        drop(v);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+    #[test]
+    fn deref_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(16, 64);
+        v.extend_from_slice(&[1, 2, 3, 4]);
+        // the slice stops at the length, not the capacity
+        let s: &[u32] = &v;
+        assert_eq!(s.len(), 4);
+        assert_eq!(v.iter().sum::<u32>(), 10);
+        assert_eq!(v[1..3], [2, 3]);
+        let (a, b) = v.split_at(1);
+        assert_eq!((a, b), (&[1][..], &[2, 3, 4][..]));
+        v.iter_mut().for_each(|x| *x *= 2);
+        v.reverse();
+        assert_eq!(v.first(), Some(&8));
+        fn sum(s: impl AsRef<[u32]>) -> u32 {
+            s.as_ref().iter().sum()
+        }
+        fn zero(mut s: impl AsMut<[u32]>) {
+            s.as_mut().fill(0)
+        }
+        assert_eq!(sum(&v), 20);
+        zero(&mut v);
+        assert_eq!(v.as_ref(), [0; 4]);
+        assert!(v.contains(&0));
+    }
+    #[test]
+    fn push_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u64>(3, 256);
+        let mut grown = 0;
+        for i in 0..1000 {
+            let capacity = v.capacity();
+            v.push(i);
+            if v.capacity() != capacity {
+                grown += 1;
+                assert!(v.capacity() >= 2 * capacity);
+            }
+            assert!(v.is_aligned_to(256));
+        }
+        assert!(grown >= 5);
+        assert!((0..1000).eq(v.as_slice().iter().copied()));
+        v.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(v.len(), 1003);
+        assert_eq!(v[1002], 3);
+        v.reserve(5000);
+        assert!(v.capacity() >= 6003);
+        assert!(v.is_aligned_to(256));
+        assert_eq!(v.alignment(), 256);
+        let mut v = crate::aligned_vec_with_capacity::<String>(0, 64);
+        v.extend_from_slice(&["a".to_string(), "b".to_string()]);
+        v.push("c".to_string());
+        assert_eq!(v.as_slice().concat(), "abc");
+        let mut v = crate::aligned_vec_with_capacity::<()>(0, 64);
+        v.push(());
+        assert_eq!(v.len(), 1);
+        let r = crate::aligned_vec::<u8>(1, 0, 8, None).try_reserve(usize::MAX);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+    }
+    #[test]
+    fn spare_capacity_mut_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(64, 64);
+        assert_eq!(v.spare_capacity_mut().len(), 64);
+        assert_eq!(v.spare_capacity_mut().as_ptr() as usize % 64, 0);
+        for (i, x) in v.spare_capacity_mut()[..16].iter_mut().enumerate() {
+            x.write(i as u32);
+        }
+        unsafe { v.set_len(16) };
+        assert!(v.iter().copied().eq(0..16));
+        // 16 elements of 4 bytes further, still on a boundary
+        let spare = v.spare_capacity_mut();
+        assert_eq!(spare.len(), 48);
+        assert_eq!(spare.as_ptr() as usize % 64, 0);
+        spare[0].write(100);
+        unsafe { v.set_len(17) };
+        assert_eq!(v[16], 100);
+        assert!(v.is_aligned_to(64));
+    }
+    #[test]
+    fn allocated_bytes_test() {
+        // 100 elements of 2 bytes, the alignment does not pad the size
+        let v = crate::from_vec_aligned(vec![7u16; 100], 64);
+        assert_eq!(v.alignment(), 64);
+        assert_eq!(v.allocated_bytes(), 200);
+        assert_eq!(v.allocated_bytes(), v.layout().size());
+        let v = crate::aligned_vec::<u16>(100, 150, 64, None);
+        assert_eq!(v.allocated_bytes(), 300);
+        // padding to the alignment is usable capacity
+        let (v, capacity) = crate::padded_aligned_vec::<u16>(100, 0, 64, None);
+        assert_eq!(v.allocated_bytes(), 256);
+        assert_eq!(capacity, 128);
+        // an odd byte is reserved but holds no element
+        let layout = Layout::from_size_align(201, 64).unwrap();
+        let v = crate::aligned_vec_from_layout::<u16>(layout, 100, None).unwrap();
+        assert_eq!(v.capacity(), 100);
+        assert_eq!(v.allocated_bytes(), 201);
+        assert_eq!(
+            crate::aligned_vec_with_capacity::<u16>(0, 64).allocated_bytes(),
+            0
+        );
+        assert_eq!(
+            crate::aligned_vec::<()>(10, 0, 64, None).allocated_bytes(),
+            0
+        );
+    }
+    #[test]
+    fn grow_zeroed_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(3, 128);
+        v.extend_from_slice(&[1, 2, 3]);
+        v.grow_zeroed(10);
+        assert_eq!(v.as_slice(), [1, 2, 3, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(v.is_aligned_to(128));
+        // within the capacity, dirty spare elements are cleared
+        v.truncate(2);
+        v.grow_zeroed(4);
+        assert_eq!(v.as_slice(), [1, 2, 0, 0]);
+        v.grow_zeroed(1);
+        assert_eq!(v.len(), 4);
+        let r = v.try_grow_zeroed(usize::MAX);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        assert_eq!(v.as_slice(), [1, 2, 0, 0]);
+    }
+    #[test]
+    fn resize_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(4, 256);
+        v.extend_from_slice(&[1, 2]);
+        v.resize(4, 7);
+        assert_eq!(v.as_slice(), [1, 2, 7, 7]);
+        // past the capacity, reallocated with the same alignment
+        v.resize(100, 9);
+        assert!(v.is_aligned_to(256));
+        assert_eq!(v.len(), 100);
+        assert_eq!(&v[..5], [1, 2, 7, 7, 9]);
+        assert!(v[4..].iter().all(|&x| x == 9));
+        let p = v.as_ptr();
+        v.resize(3, 0);
+        assert_eq!(v.as_slice(), [1, 2, 7]);
+        assert_eq!(v.as_ptr(), p);
+        assert!(v.is_aligned_to(256));
+        v.resize(3, 0);
+        assert_eq!(v.as_slice(), [1, 2, 7]);
+        // dropped when shrinking, the value is moved into the last slot
+        let mut v = crate::aligned_vec_with_capacity::<String>(0, 64);
+        v.resize(3, "a".to_string());
+        v[1].push('b');
+        assert_eq!(v.as_slice().concat(), "aaba");
+        v.resize(1, String::new());
+        assert_eq!(v.as_slice(), ["a"]);
+        v.resize(0, String::new());
+        assert!(v.is_empty());
+    }
+    #[test]
+    fn insert_swap_remove_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u64>(4, 1024);
+        v.extend_from_slice(&[1, 2, 4, 5]);
+        // at capacity, the insertion reallocates
+        v.insert(2, 3);
+        assert_ne!(v.capacity(), 4);
+        assert!(v.is_aligned_to(1024));
+        assert_eq!(v.as_slice(), [1, 2, 3, 4, 5]);
+        v.insert(0, 0);
+        v.insert(6, 6);
+        assert_eq!(v.as_slice(), [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(v.swap_remove(1), 1);
+        assert_eq!(v.as_slice(), [0, 6, 2, 3, 4, 5]);
+        assert_eq!(v.swap_remove(5), 5);
+        assert_eq!(v.as_slice(), [0, 6, 2, 3, 4]);
+        assert!(v.is_aligned_to(1024));
+        let mut v = crate::aligned_vec_with_capacity::<String>(0, 64);
+        v.insert(0, "b".to_string());
+        v.insert(0, "a".to_string());
+        v.insert(2, "c".to_string());
+        assert_eq!(v.swap_remove(0), "a");
+        assert_eq!(v.as_slice().concat(), "cb");
+    }
+    #[test]
+    #[should_panic(expected = "insert: index 2 out of bounds for length 1")]
+    fn insert_out_of_bounds_test() {
+        crate::init_aligned_vec::<u8>(1, 0, 64, 0).insert(2, 0);
+    }
+    #[test]
+    #[should_panic(expected = "swap_remove: index 1 out of bounds for length 1")]
+    fn swap_remove_out_of_bounds_test() {
+        crate::init_aligned_vec::<u8>(1, 0, 64, 0).swap_remove(1);
+    }
+    #[test]
+    fn reserve_exact_aligned_test() {
+        let mut v = crate::init_aligned_vec::<u32>(5, 0, 512, 3);
+        v.reserve_exact_aligned(10);
+        assert_eq!(v.capacity(), 15);
+        assert!(v.is_aligned_to(512));
+        assert_eq!(v.as_slice(), [3; 5]);
+        // enough room already, nothing changes
+        v.reserve_exact_aligned(2);
+        assert_eq!(v.capacity(), 15);
+        v.extend_from_slice(&[4; 10]);
+        v.reserve_exact_aligned(1);
+        assert_eq!(v.capacity(), 16);
+        assert!(v.is_aligned_to(512));
+        let r = v.try_reserve_exact_aligned(usize::MAX);
+        assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+        let mut v = crate::aligned_vec_with_capacity::<u64>(0, 64);
+        v.reserve_exact_aligned(7);
+        assert_eq!(v.capacity(), 7);
+        assert!(v.is_aligned_to(64));
+    }
+    #[test]
+    fn raw_parts_aligned_test() {
+        for align in [1, 64, 4096, 1 << 20] {
+            let mut v = crate::aligned_vec_with(10, 30, align, |i| i.to_string());
+            v.push("10".to_string());
+            let (ptr, len, capacity, align) = v.into_raw_parts_aligned();
+            assert_eq!(ptr as usize % align, 0);
+            assert_eq!((len, capacity), (11, 30));
+            let mut v = unsafe { AlignedVec::from_raw_parts_aligned(ptr, len, capacity, align) };
+            assert_eq!(v.alignment(), align.max(8));
+            assert_eq!(v[10], "10");
+            // reallocated with the layout it was rebuilt with
+            v.extend((11..100).map(|i| i.to_string()));
+            assert!(v.is_aligned_to(align));
+            assert_eq!(v[99], "99");
+        }
+        let (ptr, len, capacity, align) =
+            crate::aligned_vec_with_capacity::<u16>(0, 64).into_raw_parts_aligned();
+        let v = unsafe { AlignedVec::from_raw_parts_aligned(ptr, len, capacity, align) };
+        assert!(v.is_empty());
+        assert_eq!(v.alignment(), 64);
+    }
+    #[test]
+    fn set_len_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(100, 64);
+        unsafe {
+            // filled as a C function would
+            for i in 0..100 {
+                v.as_mut_ptr().add(i).write(i as u32 * 3);
+            }
+            v.set_len(100);
+        }
+        assert_eq!(v.len(), 100);
+        assert!(v.iter().enumerate().all(|(i, &x)| x == i as u32 * 3));
+        unsafe { v.set_len(10) };
+        assert_eq!(v.as_slice(), [0, 3, 6, 9, 12, 15, 18, 21, 24, 27]);
+    }
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "set_len: length 101 exceeds capacity 100")]
+    fn set_len_over_capacity_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(100, 64);
+        unsafe { v.set_len(101) };
+    }
+    #[test]
+    fn cmp_hash_test() {
+        let a = crate::from_slice_aligned(&[1u32, 2, 3], 64);
+        let b = crate::from_slice_aligned(&[1u32, 2, 3], 4096);
+        let c = crate::from_slice_aligned(&[1u32, 2, 4], 8);
+        let mut d = crate::aligned_vec_with_capacity::<u32>(100, 256);
+        d.extend_from_slice(&[1, 2]);
+        // contents only, whatever the alignment and capacity
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(d < a && a < c);
+        assert_eq!(a.cmp(&b), Ordering::Equal);
+        let set: std::collections::BTreeSet<_> = [c.clone(), a.clone(), b.clone(), d.clone()]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 3);
+        assert!(set
+            .iter()
+            .map(|v| v.as_slice())
+            .eq([&[1, 2][..], &[1, 2, 3], &[1, 2, 4]]));
+        let mut map = std::collections::HashMap::new();
+        map.insert(a, "a");
+        map.insert(c, "c");
+        assert_eq!(map[&b], "a");
+        assert_eq!(map.insert(b, "b"), Some("a"));
+        assert_eq!(map.len(), 2);
+        assert!(!map.contains_key(&d));
+        let nan = crate::from_slice_aligned(&[f64::NAN], 64);
+        assert_ne!(nan, nan.clone());
+        assert_eq!(nan.partial_cmp(&nan), None);
+    }
+    #[test]
+    fn clone_test() {
+        let mut v = crate::aligned_vec_with_capacity::<u32>(100, 64);
+        v.extend_from_slice(&[0xdead_beef, 1, 2, 3]);
+        let c = v.clone();
+        assert!(c.is_aligned_to(64));
+        assert_eq!(c.alignment(), 64);
+        assert_eq!(c.capacity(), 4);
+        assert_eq!(c.as_slice(), v.as_slice());
+        let v = crate::aligned_vec_with(10, 0, 128, |i| vec![i; i]);
+        let c = v.clone();
+        assert!(c.is_aligned_to(128));
+        assert_eq!(c.as_slice(), v.as_slice());
+        let v = crate::aligned_vec_with_capacity::<u8>(0, 4096);
+        let c = v.clone();
+        assert!(c.is_empty());
+        assert_eq!(c.alignment(), 4096);
+    }
+    #[test]
+    fn into_std_vec_test() {
+        let v = crate::aligned_vec_with(100, 200, 4096, |i| i.to_string());
+        let s = v.into_std_vec();
+        assert_eq!(s.len(), 100);
+        assert_eq!(s[99], "99");
+        let v = crate::aligned_vec_with_capacity::<u64>(0, 64);
+        assert!(v.into_std_vec().is_empty());
+    }
+    #[test]
+    fn try_into_std_vec_zero_copy_test() {
+        let v = crate::aligned_vec_with(10, 30, 1, |i| vec![i; 3]);
+        let p = v.as_ptr();
+        let mut s = v.try_into_std_vec_zero_copy().unwrap();
+        assert_eq!(s.as_ptr(), p);
+        assert_eq!(s.capacity(), 30);
+        assert_eq!(s[9], [9; 3]);
+        // `Vec` owns the memory, it can grow it
+        s.extend((10..100).map(|i| vec![i; 3]));
+        assert_eq!(s[99], [99; 3]);
+        let v = crate::init_aligned_vec::<u32>(10, 0, 64, 7);
+        let v = v.try_into_std_vec_zero_copy().unwrap_err();
+        assert_eq!(v.as_slice(), [7; 10]);
+        let v = crate::aligned_vec_in::<u32>(10, 0, 4, Some(0), Backend::OverAligned);
+        assert!(v.try_into_std_vec_zero_copy().is_err());
+        let v = crate::aligned_vec_with_capacity::<u16>(0, 1);
+        assert!(v.try_into_std_vec_zero_copy().unwrap().is_empty());
+        // a size that is not a whole number of elements
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let v = crate::aligned_vec_from_layout::<u64>(layout, 3, Some(1)).unwrap();
+        assert_eq!(v.capacity(), 12);
+        assert!(v.try_into_std_vec_zero_copy().is_err());
+        let layout = Layout::from_size_align(96, 8).unwrap();
+        let v = crate::aligned_vec_from_layout::<u64>(layout, 3, Some(1)).unwrap();
+        assert_eq!(v.try_into_std_vec_zero_copy().unwrap().capacity(), 12);
+    }
+    #[test]
+    fn try_reserve_test() {
+        // far more than any allocator can provide, the request itself is a
+        // valid layout
+        let huge = isize::MAX as usize / 4 / 8;
+        let backends = [
+            Backend::Rust,
+            #[cfg(unix)]
+            Backend::PosixMemalign,
+            #[cfg(unix)]
+            Backend::AlignedAlloc,
+            Backend::OverAligned,
+        ];
+        for backend in backends {
+            let mut v = crate::aligned_vec_in::<u64>(1000, 0, 128, Some(0), backend);
+            v.as_mut_slice()
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, x)| *x = i as u64);
+            let (ptr, capacity) = (v.as_ptr(), v.capacity());
+            let r = v.try_reserve(huge);
+            assert!(matches!(r, Err(AllocError::OutOfMemory { .. })));
+            assert_eq!((v.as_ptr(), v.capacity(), v.len()), (ptr, capacity, 1000));
+            assert!((0..1000).eq(v.as_slice().iter().copied()));
+            let r = v.try_reserve(usize::MAX);
+            assert!(matches!(r, Err(AllocError::LayoutOverflow)));
+            v.try_reserve(1000).unwrap();
+            assert!(v.is_aligned_to(128));
+            assert!((0..1000).eq(v.as_slice().iter().copied()));
+        }
+        let mut v = crate::aligned_vec_with_capacity::<u64>(0, 64);
+        assert!(v.try_reserve(huge).is_err());
+        assert_eq!(v.capacity(), 0);
+        // mock allocator moving blocks aligned above what its `realloc`
+        // honours; a failure leaves the block untouched and not freed
+        let mut v = crate::from_slice_aligned(&[7u64; 100], 128);
+        let (ptr, layout) = (v.as_mut_ptr() as *mut u8, v.layout());
+        let p = unsafe {
+            crate::backend::realloc_aligned_with(
+                ptr,
+                layout,
+                1600,
+                16,
+                |_, _| unreachable!(),
+                |_| core::ptr::null_mut(),
+                |_| panic!("original block freed"),
+            )
+        };
+        assert!(p.is_null());
+        assert!(v.iter().all(|&x| x == 7));
+        // the original block is freed once the new one is populated
+        let mut new = crate::aligned_vec::<u64>(200, 0, 128, Some(0));
+        let q = new.as_mut_ptr();
+        let mut freed = false;
+        let p = unsafe {
+            crate::backend::realloc_aligned_with(
+                ptr,
+                layout,
+                1600,
+                16,
+                |_, _| unreachable!(),
+                |l| {
+                    assert_eq!((l.size(), l.align()), (1600, 128));
+                    q as *mut u8
+                },
+                |p| {
+                    assert_eq!(p, ptr);
+                    assert!(std::slice::from_raw_parts(q, 100).iter().all(|&x| x == 7));
+                    freed = true;
+                },
+            )
+        };
+        assert!(freed && p == q as *mut u8);
+        assert!(new[..100].iter().all(|&x| x == 7));
+    }
+    #[test]
+    fn as_bytes_test() {
+        let mut v = crate::init_aligned_vec::<u32>(100, 0, 512, 0x0101_0101);
+        assert_eq!(v.as_bytes().len(), 100 * 4);
+        assert_eq!(v.as_bytes().as_ptr().align_offset(512), 0);
+        assert!(v.as_bytes().iter().all(|&b| b == 1));
+        v.as_bytes_mut()[4..8].copy_from_slice(&7u32.to_ne_bytes());
+        assert_eq!(v[1], 7);
+        assert_eq!(v.as_bytes_mut().as_ptr().align_offset(512), 0);
+        let mut v = crate::aligned_vec_with_capacity::<u64>(0, 64);
+        assert!(v.as_bytes().is_empty());
+        assert!(v.as_bytes_mut().is_empty());
+    }
+    #[test]
+    fn debug_test() {
+        let v = crate::aligned_vec::<u8>(100, 0, 256, None);
+        assert_eq!(v.alignment(), 256);
+        assert!(v.is_aligned_to(256));
+        assert_eq!(v.is_aligned_to(512), v.as_ptr().align_offset(512) == 0);
+        let s = format!("{v:?}");
+        assert!(s.starts_with("AlignedVec { ptr: "));
+        assert!(s.ends_with("align: 256, len: 100, capacity: 100, aligned: true }"));
+    }
+}
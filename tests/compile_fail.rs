@@ -0,0 +1,10 @@
+// Programs checked at compile time, the expected errors are in tests/ui. A
+// passing case makes trybuild build instead of only checking the programs,
+// which is needed for the const assertions evaluated at monomorphization.
+#[test]
+fn compile_fail_test() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/align_power_of_two.rs");
+    t.compile_fail("tests/ui/align_not_power_of_two.rs");
+    t.compile_fail("tests/ui/not_send.rs");
+}
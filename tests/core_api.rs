@@ -0,0 +1,23 @@
+// The allocation API available without the `std` feature, which also drops
+// `nix` and the other OS dependencies; CI runs it with
+// `--no-default-features` and builds the crate for an embedded target. Page
+// locking needs `std`: without it `page_aligned_vec` and `PageLockedVec` do
+// not exist and requesting locked pages fails to compile.
+use aligned_vec::{aligned_vec, init_aligned_vec, try_aligned_vec, AlignedVecBuilder, AllocError};
+
+#[test]
+fn core_api_test() {
+    let v = aligned_vec::<u8>(1000, 2000, 64, Some(0));
+    assert_eq!(v.as_ptr() as usize % 64, 0);
+    assert_eq!((v.len(), v.capacity()), (1000, 2000));
+    let v = init_aligned_vec::<u32>(100, 0, 256, 7);
+    assert!(v.iter().all(|&x| x == 7));
+    let v = AlignedVecBuilder::<u16>::new()
+        .size(10)
+        .align(32)
+        .build()
+        .unwrap();
+    assert_eq!(v.alignment(), 32);
+    let r = try_aligned_vec::<u8>(1, 0, 3, None);
+    assert!(matches!(r, Err(AllocError::InvalidAlignment { align: 3 })));
+}
@@ -0,0 +1,5 @@
+use aligned_vec::AlignedVecN;
+
+fn main() {
+    let _ = AlignedVecN::<f32, 24>::with_capacity(16);
+}
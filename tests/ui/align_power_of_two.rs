@@ -0,0 +1,6 @@
+use aligned_vec::AlignedVecN;
+
+fn main() {
+    let v = AlignedVecN::<f32, 32>::with_capacity(16);
+    assert!(v.is_aligned_to(32));
+}
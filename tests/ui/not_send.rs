@@ -0,0 +1,9 @@
+use aligned_vec::AlignedVec;
+use std::rc::Rc;
+
+fn assert_send<T: Send>() {}
+
+fn main() {
+    // sending the buffer would send the elements
+    assert_send::<AlignedVec<Rc<u8>>>();
+}